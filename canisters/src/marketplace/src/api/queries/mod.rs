@@ -1,10 +1,19 @@
 use ic_cdk::api::{msg_caller, canister_self};
 use ic_cdk::query;
-use ic_cdk::println;
 use candid::{CandidType, Principal};
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
 use crate::types::*;
 use crate::state;
+use crate::state::config::Config;
+use crate::guards::caller_is_admin;
+use crate::migrations::{self, MigrationStatus};
+use crate::certification;
+use ic_http_types::{HttpRequest, HttpResponse};
+use icrc_ledger_types::icrc3::archive::{GetArchivesArgs, ICRC3ArchiveInfo};
+use icrc_ledger_types::icrc3::blocks::{
+    GetBlocksRequest, GetBlocksResult, ICRC3DataCertificate, SupportedBlockType,
+};
 use crate::solana::solana_wallet::SolanaWallet;
 
 #[query]
@@ -37,6 +46,58 @@ pub fn get_collection_listings(
     state::get_collection_listings(&collection_id, page, limit, status)
 }
 
+/// `get_collection` with a witness from the certified data tree.
+#[query]
+pub fn get_certified_collection(collection_id: String) -> CertifiedCollection {
+    CertifiedCollection {
+        collection: state::get_collection(&collection_id),
+        certificate: ic_cdk::api::data_certificate().map(ByteBuf::from),
+        witness: certification::collection_witness(&collection_id),
+    }
+}
+
+/// `get_listing` with a witness from the certified data tree.
+#[query]
+pub fn get_certified_listing(collection_id: String, listing_id: String) -> CertifiedListing {
+    CertifiedListing {
+        listing: state::get_listing(&collection_id, &listing_id),
+        certificate: ic_cdk::api::data_certificate().map(ByteBuf::from),
+        witness: certification::listings_witness(&collection_id, &[listing_id]),
+    }
+}
+
+/// Active listings of a collection with a witness covering every listing returned.
+#[query]
+pub fn get_certified_collection_listings(
+    collection_id: String,
+    page: u32,
+    limit: u32,
+) -> CertifiedListings {
+    let listings =
+        state::get_collection_listings(&collection_id, page, limit, Some(ListingStatus::Active));
+    let listing_ids: Vec<String> = listings.iter().map(|listing| listing.id.clone()).collect();
+    CertifiedListings {
+        listings,
+        certificate: ic_cdk::api::data_certificate().map(ByteBuf::from),
+        witness: certification::listings_witness(&collection_id, &listing_ids),
+    }
+}
+
+#[query]
+pub fn get_cheapest_listings(collection_id: String, page: u32, limit: u32) -> Vec<Listing> {
+    state::get_cheapest_listings(&collection_id, page, limit)
+}
+
+#[query]
+pub fn get_floor_listing(collection_id: String) -> Option<Listing> {
+    state::get_floor_listing(&collection_id)
+}
+
+#[query]
+pub fn get_listings_by_status(status: ListingStatus, page: u32, limit: u32) -> Vec<Listing> {
+    state::get_listings_by_status(&status, page, limit)
+}
+
 #[query]
 pub fn get_creator_draft_collections(creator : Principal) -> Vec<Collection> {
     state::get_draft_collections(&creator)
@@ -61,7 +122,6 @@ pub fn get_collection_listing_count(collection_id: String) -> u32 {
 #[query]
 pub fn get_user_collections(page: u32, limit: u32) -> Vec<Collection> {
     let caller = msg_caller();
-    println!("User principal : {}",caller.to_text());
     state::get_user_collections(&caller, page, limit)
 }
 
@@ -72,7 +132,129 @@ pub fn get_my_draft_collections() -> Vec<Collection> {
 }
 
 #[query]
-pub async fn get_canister_solana_info() -> Result<CanisterSolanaInfo, String> {
+pub fn get_offer(offer_id: String) -> Option<Offer> {
+    state::get_offer(&offer_id)
+}
+
+#[query]
+pub fn get_listing_offers(
+    collection_id: String,
+    listing_id: String,
+    status: Option<OfferStatus>,
+) -> Vec<Offer> {
+    state::get_listing_offers(&collection_id, &listing_id, status)
+}
+
+#[query]
+pub fn get_my_offers(page: u32, limit: u32) -> Vec<Offer> {
+    let caller = msg_caller();
+    state::get_bidder_offers(&caller, page, limit)
+}
+
+#[query]
+pub fn get_bidder_offers(bidder: Principal, page: u32, limit: u32) -> Vec<Offer> {
+    state::get_bidder_offers(&bidder, page, limit)
+}
+
+#[query]
+pub fn get_nft_offers(collection_id: String, nft_id: String) -> Vec<Offer> {
+    state::get_nft_offers(&collection_id, &nft_id)
+}
+
+#[query]
+pub fn get_sale(sale_id: String) -> Option<Sale> {
+    state::get_sale(&sale_id)
+}
+
+#[query]
+pub fn get_collection_sales(collection_id: String, page: u32, limit: u32) -> Vec<Sale> {
+    state::get_collection_sales(&collection_id, page, limit)
+}
+
+#[query]
+pub fn get_buyer_sales(buyer: Principal, page: u32, limit: u32) -> Vec<Sale> {
+    state::get_buyer_sales(&buyer, page, limit)
+}
+
+#[query]
+pub fn get_seller_sales(seller: Principal, page: u32, limit: u32) -> Vec<Sale> {
+    state::get_seller_sales(&seller, page, limit)
+}
+
+#[query]
+pub fn get_nft_sales(collection_id: String, nft_id: String) -> Vec<Sale> {
+    state::get_nft_sales(&collection_id, &nft_id)
+}
+
+#[query]
+pub fn get_moderators() -> Vec<Principal> {
+    state::get_moderators()
+}
+
+/// Activity log, newest first. See `ActivityFilter` for the available filters.
+#[query]
+pub fn get_activity(filter: ActivityFilter, cursor: Option<u64>, limit: u32) -> ActivityPage {
+    state::get_activity(filter, cursor, limit)
+}
+
+/// Listing, offer and sale blocks. Each request is served from `start`, with at
+/// most `MAX_BLOCKS_PER_RESPONSE` blocks across all requests.
+#[query]
+pub fn icrc3_get_blocks(args: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    state::get_blocks(args)
+}
+
+/// Blocks are never archived, so there are no archive canisters.
+#[query]
+pub fn icrc3_get_archives(_args: GetArchivesArgs) -> Vec<ICRC3ArchiveInfo> {
+    vec![]
+}
+
+#[query]
+pub fn icrc3_get_tip_certificate() -> Option<ICRC3DataCertificate> {
+    let certificate = ic_cdk::api::data_certificate()?;
+    let hash_tree = certification::block_tip_witness()?;
+    Some(ICRC3DataCertificate {
+        certificate: ByteBuf::from(certificate),
+        hash_tree,
+    })
+}
+
+#[query]
+pub fn icrc3_supported_block_types() -> Vec<SupportedBlockType> {
+    state::supported_block_types()
+}
+
+#[query]
+pub fn http_request(req: HttpRequest) -> HttpResponse {
+    crate::http::handle(req)
+}
+
+#[query(guard = "caller_is_admin")]
+pub fn get_config() -> Config {
+    state::config::read_config(|c| c.clone())
+}
+
+/// Pauses currently in effect. Public so clients can explain rejected calls.
+#[query]
+pub fn get_pause_status() -> PauseStatus {
+    state::get_pause_status()
+}
+
+/// Canister logs of every level as JSON; `/logs` only serves `INFO`.
+#[query(guard = "caller_is_admin")]
+pub fn get_logs(priority: Option<crate::logs::Priority>, since: Option<u64>) -> String {
+    let logs = crate::logs::export_logs(priority, since.unwrap_or_default(), canlog::Sort::Ascending);
+    String::from_utf8(logs).unwrap_or_default()
+}
+
+#[query(guard = "caller_is_admin")]
+pub fn get_migration_status() -> MigrationStatus {
+    migrations::get_migration_status()
+}
+
+#[query]
+pub async fn get_canister_solana_info() -> MarketplaceResult<CanisterSolanaInfo> {
     let canister_id = ic_cdk::api::canister_self();
 
     let wallet = SolanaWallet::new(canister_id).await;
@@ -88,24 +270,36 @@ pub async fn get_canister_solana_info() -> Result<CanisterSolanaInfo, String> {
 #[query]
 pub async fn get_collection_solana_accounts(
     collection_id: String,
-) -> Result<CollectionSolanaAccounts, String> {
+) -> MarketplaceResult<CollectionSolanaAccounts> {
     if state::get_collection(&collection_id).is_none() {
-        return Err("Collection not found".to_string());
+        return Err(MarketplaceError::not_found(Entity::Collection, collection_id));
     }
 
-     let wallet = SolanaWallet::new(canister_self()).await;
+    let wallet = SolanaWallet::new(canister_self()).await;
     let payer = wallet.solana_account();
     let candy_machine = wallet.candy_machine_account(&collection_id);
     let collection = wallet.collection_account(&collection_id);
 
     Ok(CollectionSolanaAccounts {
-        collection_id,
         payer_address: payer.to_string(),
         candy_machine_address: candy_machine.to_string(),
         collection_mint: Some(collection.to_string()),
+        lamports_deposited: state::get_lamports_deposited(&collection_id),
+        lamports_spent: state::get_lamports_spent(&collection_id),
+        collection_id,
     })
 }
 
+/// Every transaction the canister sent for the collection, oldest first, with its
+/// current status.
+#[query]
+pub fn get_deployment_timeline(collection_id: String) -> MarketplaceResult<Vec<DeploymentTransaction>> {
+    if state::get_collection(&collection_id).is_none() {
+        return Err(MarketplaceError::not_found(Entity::Collection, collection_id));
+    }
+    Ok(state::get_deployment_timeline(&collection_id))
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CanisterSolanaInfo {
     pub canister_id: String,
@@ -118,4 +312,8 @@ pub struct CollectionSolanaAccounts {
     pub payer_address: String,
     pub candy_machine_address: String,
     pub collection_mint: Option<String>,
+    /// Lamports deposited for the collection through `record_solana_deposit`.
+    pub lamports_deposited: u64,
+    /// Lamports committed to transactions the canister signed for the collection.
+    pub lamports_spent: u64,
 }
@@ -1,33 +1,87 @@
+use candid::Principal;
 use ic_cdk::api::msg_caller;
 use ic_cdk::update;
 use crate::types::*;
+use crate::state::config::{Ed25519KeyName, PriorityFeePolicy, SolanaNetwork};
+use sol_rpc_types::CommitmentLevel;
 use crate::state;
-use crate::solana::candy_machine;
+use crate::migrations;
+use crate::utils::validation;
+use crate::guards::{self, caller_is_admin, caller_is_not_anonymous};
+use crate::solana::{candy_machine, deposits, sale_verification};
+use crate::solana::priority_fees::MAX_COMPUTE_UNIT_PRICE;
+use crate::logs::{log, Priority};
 
-#[update]
-pub async fn create_collection(args: CreateCollectionArgs) -> Result<String, String> {
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn create_collection(args: CreateCollectionArgs) -> MarketplaceResult<String> {
     let caller = msg_caller();
-   return state::add_collection(args, caller).await;
+    validation::validate_create_collection(&args)?;
+    state::add_collection(args, caller).await
 }
 
-#[update]
-pub async fn create_listing(args: CreateListingArgs, blockchain: Blockchain) -> Result<String, String> {
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn create_listing(args: CreateListingArgs, blockchain: Blockchain) -> MarketplaceResult<String> {
     let caller = msg_caller();
+    guards::ensure_not_paused(PauseTarget::Listings)?;
+    validation::validate_create_listing(&args, &blockchain, crate::time::get_current_time())?;
+
+    let collection = state::get_collection(&args.collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, &args.collection_id))?;
+    if collection.blockchain != blockchain {
+        return Err(vec![FieldError {
+            field: "blockchain".to_string(),
+            message: format!("collection is on {}", collection.blockchain),
+        }]
+        .into());
+    }
+
     state::add_listing(args, caller, blockchain).await
 }
 
 #[update]
-pub fn update_listing(args: UpdateListingArgs, collection_id: String) -> Result<(), String> {
+pub fn update_listing(args: UpdateListingArgs, collection_id: String) -> MarketplaceResult<()> {
+    let caller = msg_caller();
+    validation::validate_update_listing(&args)?;
+
+    let listing = state::get_listing(&collection_id, &args.listing_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, &args.listing_id))?;
+
+    guards::authorize_listing_seller(&caller, &listing)?;
+
+    if listing.status != ListingStatus::Active {
+        return Err(MarketplaceError::invalid_state("Listing is not active"));
+    }
+    match args.status {
+        None | Some(ListingStatus::Active) | Some(ListingStatus::Cancelled) => {}
+        Some(ListingStatus::Sold) => {
+            return Err(MarketplaceError::invalid_input(
+                "Listings can only be marked sold through record_sale",
+            ));
+        }
+        Some(ListingStatus::Expired) => {
+            return Err(MarketplaceError::invalid_input(
+                "Listings expire on their own once expires_at has passed",
+            ));
+        }
+    }
+    if args.price.is_some() && listing.reserved_for.is_some() {
+        return Err(MarketplaceError::invalid_state(
+            "Listing price is set by the accepted offer",
+        ));
+    }
+
     state::update_listing(args, &collection_id)
 }
 
 #[update]
-pub fn cancel_listing(collection_id: String, listing_id: String) -> Result<(), String> {
+pub fn cancel_listing(collection_id: String, listing_id: String) -> MarketplaceResult<()> {
     let caller = msg_caller();
 
     if let Some(listing) = state::get_listing(&collection_id, &listing_id) {
-        if listing.seller != caller {
-            return Err("Not authorized".to_string());
+        guards::authorize_listing_moderation(&caller, &listing)?;
+
+        if listing.status != ListingStatus::Active {
+            return Err(MarketplaceError::invalid_state("Listing is not active"));
         }
 
         state::update_listing(
@@ -39,35 +93,41 @@ pub fn cancel_listing(collection_id: String, listing_id: String) -> Result<(), S
             &collection_id,
         )
     } else {
-        Err("Listing not found".to_string())
+        Err(MarketplaceError::not_found(Entity::Listing, listing_id))
     }
 }
 
 #[update]
-pub fn update_collection_status(args: UpdateCollectionStatusArgs) -> Result<(), String> {
+pub fn update_collection_status(args: UpdateCollectionStatusArgs) -> MarketplaceResult<()> {
     let caller = msg_caller();
 
     if let Some(collection) = state::get_collection(&args.collection_id) {
-        if collection.creator != caller {
-            return Err("Not authorized".to_string());
-        }
+        guards::authorize_collection_creator(&caller, &collection)?;
         state::update_collection_status(args)
     } else {
-        Err("Collection not found".to_string())
+        Err(MarketplaceError::not_found(Entity::Collection, args.collection_id))
     }
 }
 
 #[update]
-pub fn update_solana_stage(args: UpdateSolanaStageArgs) -> Result<(), String> {
+pub fn update_solana_stage(args: UpdateSolanaStageArgs) -> MarketplaceResult<()> {
     let caller = msg_caller();
 
     if let Some(collection) = state::get_collection(&args.collection_id) {
-        if collection.creator != caller {
-            return Err("Not authorized".to_string());
+        guards::authorize_collection_creator(&caller, &collection)?;
+        // On-chain addresses and the Deployed stage come from finalized deployment
+        // transactions; only the admin may set them by hand
+        let sets_chain_state = args.candy_machine_address.is_some()
+            || args.collection_mint.is_some()
+            || args.candy_machine_authority.is_some()
+            || args.stage == SolanaDeploymentStage::Deployed;
+        if sets_chain_state && !guards::is_admin(&caller) {
+            return Err(MarketplaceError::Unauthorized);
         }
+        validation::validate_solana_stage_update(&args)?;
         state::update_solana_stage(args)
     } else {
-        Err("Collection not found".to_string())
+        Err(MarketplaceError::not_found(Entity::Collection, args.collection_id))
     }
 }
 
@@ -77,89 +137,63 @@ pub async fn sign_and_send_solana_transaction(
     serialized_message: Vec<u8>,
     transaction_type: TransactionType,
     user_wallet_address: Option<String>,
-) -> Result<String, String> {
+) -> MarketplaceResult<String> {
     let caller = msg_caller();
 
     let collection = state::get_collection(&collection_id)
-        .ok_or("Collection not found")?;
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, &collection_id))?;
 
-    if collection.creator != caller {
-        return Err("Not authorized".to_string());
-    }
+    guards::authorize_collection_creator(&caller, &collection)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)?;
 
-    let signature = candy_machine::sign_and_send_transaction(
-        collection_id.clone(),
+    candy_machine::sign_and_send_transaction(
+        collection_id,
         serialized_message,
-        transaction_type.clone(),
-        user_wallet_address.clone(),
-    ).await?;
-
-    match transaction_type {
-        TransactionType::CreateCandyMachine => {
-            // Update state to track candy machine creation
-            // Note: candy_machine_address will be set after we get it from the transaction
-            ic_cdk::println!("Candy Machine creation transaction sent: {}", signature);
-        }
-        TransactionType::TransferAuthority => {
-            if let Some(new_authority) = user_wallet_address {
-                state::update_solana_stage(UpdateSolanaStageArgs {
-                    collection_id: collection_id.clone(),
-                    stage: SolanaDeploymentStage::Deployed,
-                    candy_machine_address: None,
-                    candy_machine_authority: Some(new_authority),
-                    collection_mint: None,
-                    manifest_url: None,
-                    candy_machine_config: None,
-                    files_uploaded: None,
-                    metadata_created: None,
-                })?;
-            }
-        }
-        TransactionType::UpdateCandyMachine => {
-            ic_cdk::println!("Candy Machine update transaction sent: {}", signature);
-        }
-    }
-
-    Ok(signature)
+        transaction_type,
+        user_wallet_address,
+    ).await
 }
 
+/// Credits a SOL transfer to the canister payer to the collection. The transfer
+/// must carry the collection id as an SPL Memo. Deposits fund the rent and fees of
+/// every transaction the canister signs for the collection. Returns the
+/// collection's total deposits in lamports.
 #[update]
-pub async fn create_candy_machine_from_instruction(
-    collection_id: String,
-    instructions_data: Vec<InstructionData>,
-) -> Result<String, String> {
-    let caller = msg_caller();
-
+pub async fn record_solana_deposit(collection_id: String, tx_signature: String) -> MarketplaceResult<u64> {
     let collection = state::get_collection(&collection_id)
-        .ok_or("Collection not found")?;
-
-    if collection.creator != caller {
-        return Err("Not authorized".to_string());
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, &collection_id))?;
+    guards::authorize_collection_creator(&msg_caller(), &collection)?;
+    if !matches!(collection.chain_data, ChainData::Solana(_)) {
+        return Err(MarketplaceError::invalid_state("Collection is not a Solana collection"));
     }
+    deposits::record_deposit(&collection_id, &tx_signature).await
+}
 
-    let signature = candy_machine::create_candy_machine_from_instruction(
-        collection_id.clone(),
-        instructions_data,
-    ).await?;
-
-    ic_cdk::println!("Candy Machine creation transaction sent: {}", signature);
-
-    Ok(signature)
+/// Creates the collection NFT at the collection's derived mint address.
+#[update]
+pub async fn create_collection_nft(collection_id: String) -> MarketplaceResult<String> {
+    authorize_candy_machine_call(&collection_id)?;
+    candy_machine::create_collection_nft(&collection_id).await
 }
 
+/// Creates the Candy Machine from the collection's stored config.
 #[update]
+pub async fn create_candy_machine(collection_id: String) -> MarketplaceResult<String> {
+    authorize_candy_machine_call(&collection_id)?;
+    candy_machine::create_candy_machine(&collection_id).await
+}
+
+/// Admin repair for a collection whose candy machine address was lost. Deployments
+/// record the address themselves once the transaction finalizes.
+#[update(guard = "caller_is_admin")]
 pub fn update_candy_machine_address(
     collection_id: String,
     candy_machine_address: String,
-) -> Result<(), String> {
-    let caller = msg_caller();
-
-    let collection = state::get_collection(&collection_id)
-        .ok_or("Collection not found")?;
-
-    if collection.creator != caller {
-        return Err("Not authorized".to_string());
-    }
+) -> MarketplaceResult<()> {
+    state::get_collection(&collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, &collection_id))?;
+    validation::validate_address(&Blockchain::Solana, &candy_machine_address)
+        .map_err(MarketplaceError::InvalidInput)?;
 
     state::update_solana_stage(UpdateSolanaStageArgs {
         collection_id,
@@ -177,16 +211,314 @@ pub fn update_candy_machine_address(
 #[update]
 pub async fn add_items_to_candy_machine(
     collection_id: String,
-    instruction_data: InstructionData,
-) -> Result<String, String> {
-    let caller = msg_caller();
+    index: u32,
+    items: Vec<ConfigLine>,
+) -> MarketplaceResult<String> {
+    authorize_candy_machine_call(&collection_id)?;
+    candy_machine::add_items_to_candy_machine(&collection_id, index, items).await
+}
+
+#[update]
+pub async fn set_candy_machine_collection(
+    collection_id: String,
+    new_collection_mint: String,
+) -> MarketplaceResult<String> {
+    authorize_candy_machine_call(&collection_id)?;
+    candy_machine::set_candy_machine_collection(&collection_id, &new_collection_mint).await
+}
 
+#[update]
+pub async fn transfer_candy_machine_authority(
+    collection_id: String,
+    new_authority: String,
+) -> MarketplaceResult<String> {
+    authorize_candy_machine_call(&collection_id)?;
+    candy_machine::transfer_candy_machine_authority(&collection_id, &new_authority).await
+}
+
+/// Sets the mint phases and guards of the collection's Candy Machine, creating its
+/// Candy Guard on first use. Returns the transaction signature.
+#[update]
+pub async fn set_candy_guard(collection_id: String, config: CandyGuardConfig) -> MarketplaceResult<String> {
+    authorize_candy_machine_call(&collection_id)?;
+    candy_machine::set_candy_guard(&collection_id, config).await
+}
+
+/// Reads the Candy Machine from chain into the collection record, for live mint progress.
+#[update]
+pub async fn sync_candy_machine_state(collection_id: String) -> MarketplaceResult<CandyMachineState> {
     let collection = state::get_collection(&collection_id)
-        .ok_or("Collection not found")?;
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, &collection_id))?;
+    guards::authorize_collection_creator(&msg_caller(), &collection)?;
+    candy_machine::sync_candy_machine_state(&collection_id).await
+}
+
+/// Checks that the caller may have the canister sign for this collection's Candy Machine.
+fn authorize_candy_machine_call(collection_id: &str) -> MarketplaceResult<()> {
+    let collection = state::get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    guards::authorize_collection_creator(&msg_caller(), &collection)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)
+}
+
+#[update(guard = "caller_is_not_anonymous")]
+pub async fn make_offer(args: CreateOfferArgs) -> MarketplaceResult<String> {
+    let caller = msg_caller();
+    guards::ensure_not_paused(PauseTarget::Offers)?;
+
+    let listing = state::get_listing(&args.collection_id, &args.listing_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, &args.listing_id))?;
+
+    if listing.status != ListingStatus::Active {
+        return Err(MarketplaceError::invalid_state("Listing is not active"));
+    }
+    if listing.reserved_for.is_some() {
+        return Err(MarketplaceError::invalid_state("Listing already has an accepted offer"));
+    }
+    if listing.seller == caller {
+        return Err(MarketplaceError::invalid_input("Cannot make an offer on your own listing"));
+    }
+    if listing.currency != args.currency {
+        return Err(MarketplaceError::invalid_input(format!(
+            "Offer currency must be {}",
+            listing.currency
+        )));
+    }
+    validation::validate_price(args.price).map_err(MarketplaceError::InvalidInput)?;
+    if args.expires_at <= crate::time::get_current_time() {
+        return Err(MarketplaceError::invalid_input("Offer expiry must be in the future"));
+    }
+
+    state::add_offer(args, caller, listing.nft_id).await
+}
+
+#[update]
+pub fn cancel_offer(offer_id: String) -> MarketplaceResult<()> {
+    let caller = msg_caller();
+
+    let offer = state::get_offer(&offer_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Offer, &offer_id))?;
 
-    if collection.creator != caller {
-        return Err("Not authorized".to_string());
+    guards::authorize_offer_bidder(&caller, &offer)?;
+
+    state::update_offer_status(UpdateOfferArgs {
+        offer_id,
+        status: OfferStatus::Cancelled,
+    })
+}
+
+#[update]
+pub fn accept_offer(offer_id: String) -> MarketplaceResult<()> {
+    let caller = msg_caller();
+    guards::ensure_not_paused(PauseTarget::Offers)?;
+
+    let offer = state::get_offer(&offer_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Offer, &offer_id))?;
+    let listing = state::get_listing(&offer.collection_id, &offer.listing_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, &offer.listing_id))?;
+
+    guards::authorize_listing_seller(&caller, &listing)?;
+    if listing.status != ListingStatus::Active {
+        return Err(MarketplaceError::invalid_state("Listing is not active"));
+    }
+    if listing.reserved_for.is_some() {
+        return Err(MarketplaceError::invalid_state("Listing already has an accepted offer"));
+    }
+
+    state::accept_offer(&offer_id)
+}
+
+#[update]
+pub fn reject_offer(offer_id: String) -> MarketplaceResult<()> {
+    let caller = msg_caller();
+
+    let offer = state::get_offer(&offer_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Offer, &offer_id))?;
+    let listing = state::get_listing(&offer.collection_id, &offer.listing_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, &offer.listing_id))?;
+
+    guards::authorize_listing_seller(&caller, &listing)?;
+
+    state::update_offer_status(UpdateOfferArgs {
+        offer_id,
+        status: OfferStatus::Rejected,
+    })
+}
+
+#[update]
+pub async fn record_sale(args: RecordSaleArgs) -> MarketplaceResult<String> {
+    let caller = msg_caller();
+    guards::ensure_not_paused(PauseTarget::Sales)?;
+
+    let listing = state::get_listing(&args.collection_id, &args.listing_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, &args.listing_id))?;
+
+    guards::authorize_sale_party(&caller, &listing, &args.buyer)?;
+    if listing.seller == args.buyer {
+        return Err(MarketplaceError::invalid_input("Buyer cannot be the seller"));
+    }
+    if args.tx_signature.is_empty() {
+        return Err(MarketplaceError::invalid_input("Transaction signature is required"));
+    }
+
+    if listing.blockchain == Blockchain::Solana {
+        let buyer_address = args
+            .buyer_address
+            .as_deref()
+            .ok_or(SaleRejectionReason::MissingBuyerAddress)?;
+
+        if let Err(reason) =
+            sale_verification::verify_sale(&listing, buyer_address, &args.tx_signature).await
+        {
+            log!(
+                Priority::Info,
+                "Rejected sale of listing {} (tx {}): {:?}",
+                listing.id,
+                args.tx_signature,
+                reason
+            );
+            return Err(reason.into());
+        }
     }
 
-    candy_machine::add_items_to_candy_machine(collection_id, instruction_data).await
+    state::record_sale(args, &listing).await
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn add_moderator(principal: Principal) -> MarketplaceResult<()> {
+    state::add_moderator(principal);
+    Ok(())
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn remove_moderator(principal: Principal) -> MarketplaceResult<()> {
+    if state::remove_moderator(&principal) {
+        Ok(())
+    } else {
+        Err(MarketplaceError::invalid_input("Principal is not a moderator"))
+    }
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn set_sol_rpc_canister_id(canister_id: Option<Principal>) -> MarketplaceResult<()> {
+    state::config::set_sol_rpc_canister_id(canister_id);
+    log!(Priority::Info, "SOL RPC canister set to {:?}", canister_id);
+    Ok(())
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn set_solana_network(network: SolanaNetwork) -> MarketplaceResult<()> {
+    log!(Priority::Info, "Solana network set to {:?}", network);
+    state::config::set_solana_network(network);
+    Ok(())
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn set_solana_commitment_level(commitment_level: CommitmentLevel) -> MarketplaceResult<()> {
+    log!(Priority::Info, "Solana commitment level set to {:?}", commitment_level);
+    state::config::set_solana_commitment_level(commitment_level);
+    Ok(())
+}
+
+/// Sets how priority fees are chosen for the Solana transactions the canister signs.
+#[update(guard = "caller_is_admin")]
+pub fn set_priority_fee_policy(policy: PriorityFeePolicy) -> MarketplaceResult<()> {
+    if policy.percentile > 100 {
+        return Err(MarketplaceError::invalid_input("Percentile must be between 0 and 100"));
+    }
+    if policy.max_compute_unit_price > MAX_COMPUTE_UNIT_PRICE {
+        return Err(MarketplaceError::invalid_input(format!(
+            "Maximum compute unit price must be at most {} micro-lamports",
+            MAX_COMPUTE_UNIT_PRICE
+        )));
+    }
+    if policy.fallback_compute_unit_price.is_some_and(|price| price > policy.max_compute_unit_price) {
+        return Err(MarketplaceError::invalid_input(
+            "Fallback compute unit price must be at most the maximum compute unit price",
+        ));
+    }
+    log!(Priority::Info, "Priority fee policy set to {:?}", policy);
+    state::config::set_priority_fee_policy(policy);
+    Ok(())
+}
+
+/// Changes the threshold key used for every canister-controlled Solana account.
+/// Accounts derived from the old key keep their funds but are no longer used.
+#[update(guard = "caller_is_admin")]
+pub fn set_ed25519_key_name(key_name: Ed25519KeyName) -> MarketplaceResult<()> {
+    log!(Priority::Info, "Ed25519 key name set to {:?}", key_name);
+    state::config::set_ed25519_key_name(key_name);
+    Ok(())
+}
+
+/// First step of an admin transfer. The current admin stays in place until
+/// `new_admin` calls `accept_admin`; proposing again replaces the pending admin.
+#[update(guard = "caller_is_admin")]
+pub fn propose_admin(new_admin: Principal) -> MarketplaceResult<()> {
+    if new_admin == Principal::anonymous() {
+        return Err(MarketplaceError::invalid_input("Admin cannot be the anonymous principal"));
+    }
+    state::config::set_pending_admin(Some(new_admin));
+    log!(Priority::Info, "Admin transfer to {} proposed", new_admin);
+    Ok(())
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn cancel_admin_transfer() -> MarketplaceResult<()> {
+    if state::config::get_pending_admin().is_none() {
+        return Err(MarketplaceError::invalid_state("No admin transfer is pending"));
+    }
+    state::config::set_pending_admin(None);
+    Ok(())
+}
+
+/// Second step of an admin transfer, called by the proposed admin.
+#[update]
+pub fn accept_admin() -> MarketplaceResult<()> {
+    let caller = msg_caller();
+    let previous = state::config::accept_pending_admin(caller).ok_or(MarketplaceError::Unauthorized)?;
+    log!(Priority::Info, "Admin changed from {} to {}", previous, caller);
+    Ok(())
+}
+
+/// Most verbose log priority to record from now on.
+#[update(guard = "caller_is_admin")]
+pub fn set_log_verbosity(verbosity: Priority) -> MarketplaceResult<()> {
+    state::config::set_log_verbosity(verbosity);
+    log!(Priority::Info, "Log verbosity set to {:?}", verbosity);
+    Ok(())
+}
+
+/// Stops `target` until `resume` is called or, if given, until `resume_at`
+/// (nanoseconds since epoch). Pausing again replaces the reason and resume time.
+#[update(guard = "caller_is_admin")]
+pub fn pause(target: PauseTarget, reason: String, resume_at: Option<u64>) -> MarketplaceResult<()> {
+    if reason.trim().is_empty() {
+        return Err(MarketplaceError::invalid_input("A pause reason is required"));
+    }
+    if resume_at.is_some_and(|resume_at| resume_at <= crate::time::get_current_time()) {
+        return Err(MarketplaceError::invalid_input("Resume time must be in the future"));
+    }
+    log!(Priority::Info, "Paused {:?} until {:?}: {}", target, resume_at, reason);
+    state::pause(target, reason, msg_caller(), resume_at);
+    Ok(())
+}
+
+#[update(guard = "caller_is_admin")]
+pub fn resume(target: PauseTarget) -> MarketplaceResult<()> {
+    if !state::resume(target) {
+        return Err(MarketplaceError::invalid_state(format!("{:?} is not paused", target)));
+    }
+    log!(Priority::Info, "Resumed {:?}", target);
+    Ok(())
+}
+
+/// Restarts pending migrations after a failed batch.
+#[update(guard = "caller_is_admin")]
+pub fn resume_migrations() -> MarketplaceResult<()> {
+    if !migrations::is_migrating() {
+        return Err(MarketplaceError::invalid_state("No pending migrations"));
+    }
+    migrations::start();
+    Ok(())
 }
@@ -0,0 +1,338 @@
+//! The canister's certified data.
+//!
+//! The certified root is a labeled hash tree, so each certified value can be
+//! proven with a witness that prunes the others:
+//!
+//! ```text
+//! collections/<collection_id>      -> digest of the collection
+//! last_block_hash                  -> ICRC-3 tip block hash
+//! last_block_index                 -> ICRC-3 tip block index (LEB128)
+//! listings/<collection_id>/<id>    -> digest of an active listing
+//! ```
+//!
+//! Collection and listing digests are the representation-independent hash of the
+//! ICRC-3 value maps built by [`collection_digest`] and [`listing_digest`], so a
+//! client can recompute them from a query result without matching our Candid
+//! encoding. The maps live on the heap and are rebuilt in batches after an upgrade;
+//! until [`start_rebuild`] finishes, witnesses can prove absent a value that exists.
+
+use candid::{Nat, Principal};
+use ic_certification::{
+    fork, labeled, labeled_hash, leaf, leaf_hash, merge_hash_trees, pruned, AsHashTree, Hash,
+    HashTree, RbTree,
+};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use serde_bytes::ByteBuf;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use crate::state;
+use crate::types::{ChainData, Collection, Listing, ListingStatus};
+
+#[cfg(test)]
+mod tests;
+
+const COLLECTIONS_LABEL: &[u8] = b"collections";
+const LAST_BLOCK_HASH_LABEL: &[u8] = b"last_block_hash";
+const LAST_BLOCK_INDEX_LABEL: &[u8] = b"last_block_index";
+const LISTINGS_LABEL: &[u8] = b"listings";
+
+#[derive(Default)]
+struct CertifiedMaps {
+    collections: RbTree<String, Hash>,
+    /// Collection id -> listing id -> digest. Active listings only.
+    listings: RbTree<String, RbTree<String, Hash>>,
+}
+
+thread_local! {
+    static CERTIFIED_MAPS: RefCell<CertifiedMaps> = RefCell::new(CertifiedMaps::default());
+}
+
+/// Which certified values a witness reveals. Everything else is pruned.
+#[derive(Clone, Copy)]
+enum Reveal<'a> {
+    Nothing,
+    BlockTip,
+    Collection(&'a str),
+    Listings(&'a str, &'a [String]),
+}
+
+fn leb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn text(value: &str) -> ICRC3Value {
+    ICRC3Value::Text(value.to_string())
+}
+
+fn principal(value: &Principal) -> ICRC3Value {
+    ICRC3Value::Blob(ByteBuf::from(value.as_slice().to_vec()))
+}
+
+fn nat(value: u64) -> ICRC3Value {
+    ICRC3Value::Nat(Nat::from(value))
+}
+
+/// Digest of the collection fields a buyer relies on. Stats such as floor price
+/// are derived from listings and are not covered.
+pub fn collection_digest(collection: &Collection) -> Hash {
+    let mut fields = BTreeMap::new();
+    fields.insert("id".to_string(), text(&collection.id));
+    fields.insert("blockchain".to_string(), text(collection.blockchain.as_str()));
+    fields.insert("creator".to_string(), principal(&collection.creator));
+    fields.insert("name".to_string(), text(&collection.name));
+    fields.insert("symbol".to_string(), text(&collection.symbol));
+    fields.insert("total_supply".to_string(), nat(collection.total_supply));
+    fields.insert("royalty_bps".to_string(), nat(collection.royalty_bps as u64));
+    fields.insert("status".to_string(), text(&format!("{:?}", collection.status)));
+    if let ChainData::Solana(data) = &collection.chain_data {
+        if let Some(address) = &data.candy_machine_address {
+            fields.insert("candy_machine_address".to_string(), text(address));
+        }
+        if let Some(mint) = &data.collection_mint {
+            fields.insert("collection_mint".to_string(), text(mint));
+        }
+    }
+    ICRC3Value::Map(fields).hash()
+}
+
+/// Digest of the listing fields that determine what a purchase pays and to whom.
+pub fn listing_digest(listing: &Listing) -> Hash {
+    let mut fields = BTreeMap::new();
+    fields.insert("id".to_string(), text(&listing.id));
+    fields.insert("collection_id".to_string(), text(&listing.collection_id));
+    fields.insert("nft_id".to_string(), text(&listing.nft_id));
+    fields.insert("blockchain".to_string(), text(listing.blockchain.as_str()));
+    fields.insert("seller".to_string(), principal(&listing.seller));
+    fields.insert("seller_address".to_string(), text(&listing.seller_address));
+    fields.insert("price".to_string(), nat(listing.price));
+    fields.insert("currency".to_string(), text(&listing.currency));
+    fields.insert("status".to_string(), text(&format!("{:?}", listing.status)));
+    if let Some(escrow_address) = &listing.escrow_address {
+        fields.insert("escrow_address".to_string(), text(escrow_address));
+    }
+    if let Some(expires_at) = listing.expires_at {
+        fields.insert("expires_at".to_string(), nat(expires_at));
+    }
+    // A reservation decides who may buy and at what price
+    if let Some(bidder) = &listing.reserved_for {
+        fields.insert("reserved_for".to_string(), principal(bidder));
+    }
+    if let Some(price) = listing.reserved_price {
+        fields.insert("reserved_price".to_string(), nat(price));
+    }
+    if let Some(reserved_until) = listing.reserved_until {
+        fields.insert("reserved_until".to_string(), nat(reserved_until));
+    }
+    ICRC3Value::Map(fields).hash()
+}
+
+fn insert_collection(maps: &mut CertifiedMaps, collection: &Collection) {
+    maps.collections.insert(collection.id.clone(), collection_digest(collection));
+}
+
+fn insert_listing(maps: &mut CertifiedMaps, listing: &Listing) {
+    let digest = listing_digest(listing);
+    let collection_key = listing.collection_id.as_bytes();
+    if maps.listings.get(collection_key).is_some() {
+        maps.listings.modify(collection_key, |listings| {
+            listings.insert(listing.id.clone(), digest);
+        });
+    } else {
+        let mut listings = RbTree::new();
+        listings.insert(listing.id.clone(), digest);
+        maps.listings.insert(listing.collection_id.clone(), listings);
+    }
+}
+
+/// Certifies the collection's current digest.
+pub fn certify_collection(collection: &Collection) {
+    CERTIFIED_MAPS.with(|m| insert_collection(&mut m.borrow_mut(), collection));
+    update_certified_data();
+}
+
+/// Certifies an active listing. Listings in any other status are uncertified,
+/// so a client can never verify a price that is no longer on offer.
+pub fn certify_listing(listing: &Listing) {
+    if listing.status != ListingStatus::Active {
+        uncertify_listing(&listing.collection_id, &listing.id);
+        return;
+    }
+    CERTIFIED_MAPS.with(|m| insert_listing(&mut m.borrow_mut(), listing));
+    update_certified_data();
+}
+
+pub fn uncertify_listing(collection_id: &str, listing_id: &str) {
+    CERTIFIED_MAPS.with(|m| {
+        let mut maps = m.borrow_mut();
+        let collection_key = collection_id.as_bytes();
+        let mut now_empty = false;
+        maps.listings.modify(collection_key, |listings| {
+            listings.delete(listing_id.as_bytes());
+            now_empty = listings.is_empty();
+        });
+        if now_empty {
+            maps.listings.delete(collection_key);
+        }
+    });
+    update_certified_data();
+}
+
+/// Records certified per message while rebuilding the maps.
+const REBUILD_BATCH_SIZE: usize = 500;
+
+/// Where a rebuild resumes: the last collection id or listing key certified.
+enum RebuildStep {
+    Collections(Option<String>),
+    Listings(Option<String>),
+}
+
+/// Rebuilds the certified maps from stable memory, one batch per timer message so a
+/// large marketplace fits the instruction limit. Heap state does not survive an
+/// upgrade, so this starts in `post_upgrade`. Changes made meanwhile certify
+/// themselves as usual.
+pub fn start_rebuild() {
+    CERTIFIED_MAPS.with(|m| *m.borrow_mut() = CertifiedMaps::default());
+    update_certified_data();
+    schedule_rebuild(RebuildStep::Collections(None));
+}
+
+fn schedule_rebuild(step: RebuildStep) {
+    ic_cdk_timers::set_timer(Duration::ZERO, move || rebuild_batch(step));
+}
+
+fn rebuild_batch(step: RebuildStep) {
+    let next = match step {
+        RebuildStep::Collections(cursor) => {
+            let collections = state::collections_after(cursor, REBUILD_BATCH_SIZE);
+            CERTIFIED_MAPS.with(|m| {
+                let mut maps = m.borrow_mut();
+                for (_, collection) in collections.iter() {
+                    insert_collection(&mut maps, collection);
+                }
+            });
+            match collections.last() {
+                Some((id, _)) if collections.len() == REBUILD_BATCH_SIZE => {
+                    Some(RebuildStep::Collections(Some(id.clone())))
+                }
+                _ => Some(RebuildStep::Listings(None)),
+            }
+        }
+        RebuildStep::Listings(cursor) => {
+            let listings = state::active_listings_after(cursor, REBUILD_BATCH_SIZE);
+            CERTIFIED_MAPS.with(|m| {
+                let mut maps = m.borrow_mut();
+                for (_, listing) in listings.iter() {
+                    insert_listing(&mut maps, listing);
+                }
+            });
+            match listings.last() {
+                Some((key, _)) if listings.len() == REBUILD_BATCH_SIZE => {
+                    Some(RebuildStep::Listings(Some(key.clone())))
+                }
+                _ => None,
+            }
+        }
+    };
+    update_certified_data();
+    if let Some(step) = next {
+        schedule_rebuild(step);
+    }
+}
+
+/// A labeled subtree: revealed in full, or pruned down to its hash.
+fn node(label: &[u8], reveal: bool, content: impl FnOnce() -> HashTree, hash: Hash) -> HashTree {
+    if reveal {
+        labeled(label, content())
+    } else {
+        pruned(labeled_hash(label, &hash))
+    }
+}
+
+fn certified_tree(reveal: Reveal) -> HashTree {
+    CERTIFIED_MAPS.with(|m| {
+        let maps = m.borrow();
+        // Top-level labels, in sorted order
+        let mut nodes = vec![
+            node(
+                COLLECTIONS_LABEL,
+                matches!(reveal, Reveal::Collection(_)),
+                || match reveal {
+                    Reveal::Collection(id) => maps.collections.witness(id.as_bytes()),
+                    _ => unreachable!(),
+                },
+                maps.collections.root_hash(),
+            ),
+        ];
+        // The ICRC-3 labels sit at the root, where `icrc3_get_tip_certificate` clients look for them.
+        if let Some((index, hash)) = state::get_block_tip() {
+            let index = leb128(index);
+            let reveal_tip = matches!(reveal, Reveal::BlockTip);
+            nodes.push(node(LAST_BLOCK_HASH_LABEL, reveal_tip, || leaf(hash.to_vec()), leaf_hash(&hash)));
+            nodes.push(node(LAST_BLOCK_INDEX_LABEL, reveal_tip, || leaf(index.clone()), leaf_hash(&index)));
+        }
+        nodes.push(node(
+            LISTINGS_LABEL,
+            matches!(reveal, Reveal::Listings(..)),
+            || match reveal {
+                Reveal::Listings(collection_id, listing_ids) => {
+                    maps.listings.nested_witness(collection_id.as_bytes(), |listings| {
+                        listing_ids
+                            .iter()
+                            .map(|id| listings.witness(id.as_bytes()))
+                            .reduce(merge_hash_trees)
+                            .unwrap_or_else(|| pruned(listings.root_hash()))
+                    })
+                }
+                _ => unreachable!(),
+            },
+            maps.listings.root_hash(),
+        ));
+
+        nodes
+            .into_iter()
+            .rev()
+            .reduce(|right, left| fork(left, right))
+            .expect("certified tree has at least one label")
+    })
+}
+
+/// Recomputes the root hash and hands it to the IC. Call after any change to a
+/// certified value, and after an upgrade, since certified data is not persisted.
+pub fn update_certified_data() {
+    // Only a canister has certified data to set
+    #[cfg(target_arch = "wasm32")]
+    ic_cdk::api::certified_data_set(certified_tree(Reveal::Nothing).digest());
+}
+
+fn encode_witness(tree: &HashTree) -> ByteBuf {
+    let mut bytes = vec![];
+    ciborium::into_writer(tree, &mut bytes).expect("failed to CBOR-encode hash tree");
+    ByteBuf::from(bytes)
+}
+
+/// CBOR-encoded witness for the ICRC-3 tip, or `None` before the first block.
+pub fn block_tip_witness() -> Option<ByteBuf> {
+    state::get_block_tip()?;
+    Some(encode_witness(&certified_tree(Reveal::BlockTip)))
+}
+
+/// CBOR-encoded witness for `collections/<collection_id>`, or its absence.
+pub fn collection_witness(collection_id: &str) -> ByteBuf {
+    encode_witness(&certified_tree(Reveal::Collection(collection_id)))
+}
+
+/// CBOR-encoded witness for `listings/<collection_id>/<id>` for each listing id.
+/// Ids of listings that are not active are proven absent.
+pub fn listings_witness(collection_id: &str, listing_ids: &[String]) -> ByteBuf {
+    encode_witness(&certified_tree(Reveal::Listings(collection_id, listing_ids)))
+}
@@ -0,0 +1,163 @@
+use super::*;
+use crate::state::fixtures;
+use crate::types::CollectionStatus;
+use ic_certification::LookupResult;
+
+fn root_hash() -> Hash {
+    certified_tree(Reveal::Nothing).digest()
+}
+
+fn found(tree: &HashTree, path: &[&str]) -> Option<Vec<u8>> {
+    match tree.lookup_path(path) {
+        LookupResult::Found(value) => Some(value.to_vec()),
+        _ => None,
+    }
+}
+
+mod digests {
+    use super::*;
+
+    #[test]
+    fn should_cover_what_a_purchase_pays_and_to_whom() {
+        let listing = fixtures::listing("collection", "listing", 1_000);
+        let digest = listing_digest(&listing);
+
+        let repriced = Listing { price: 900, ..listing.clone() };
+        let redirected = Listing { seller_address: "elsewhere".to_string(), ..listing.clone() };
+        let reserved = Listing {
+            reserved_for: Some(fixtures::principal(10)),
+            reserved_price: Some(800),
+            reserved_until: Some(1),
+            ..listing.clone()
+        };
+
+        assert_ne!(listing_digest(&repriced), digest);
+        assert_ne!(listing_digest(&redirected), digest);
+        assert_ne!(listing_digest(&reserved), digest);
+    }
+
+    #[test]
+    fn should_change_with_each_reservation_field() {
+        let listing = fixtures::listing("collection", "listing", 1_000);
+        let reserved = Listing {
+            reserved_for: Some(fixtures::principal(10)),
+            reserved_price: Some(800),
+            reserved_until: Some(1),
+            ..listing
+        };
+        let digest = listing_digest(&reserved);
+
+        let other_bidder = Listing { reserved_for: Some(fixtures::principal(11)), ..reserved.clone() };
+        let other_price = Listing { reserved_price: Some(700), ..reserved.clone() };
+        let other_deadline = Listing { reserved_until: Some(2), ..reserved.clone() };
+
+        assert_ne!(listing_digest(&other_bidder), digest);
+        assert_ne!(listing_digest(&other_price), digest);
+        assert_ne!(listing_digest(&other_deadline), digest);
+    }
+
+    #[test]
+    fn should_ignore_fields_a_purchase_does_not_depend_on() {
+        let listing = fixtures::listing("collection", "listing", 1_000);
+        let touched = Listing { updated_at: listing.updated_at + 1, ..listing.clone() };
+
+        assert_eq!(listing_digest(&touched), listing_digest(&listing));
+    }
+
+    #[test]
+    fn should_leave_collection_stats_out() {
+        let collection = fixtures::collection("collection");
+        let traded = Collection { floor_price: 500, total_volume: 10_000, ..collection.clone() };
+        let paused = Collection { status: CollectionStatus::Paused, ..collection.clone() };
+
+        assert_eq!(collection_digest(&traded), collection_digest(&collection));
+        assert_ne!(collection_digest(&paused), collection_digest(&collection));
+    }
+}
+
+mod certified_maps {
+    use super::*;
+
+    #[test]
+    fn should_change_the_root_hash_with_each_listing() {
+        let empty = root_hash();
+        let listing = fixtures::listing("collection", "listing", 1_000);
+
+        certify_listing(&listing);
+        let listed = root_hash();
+        certify_listing(&Listing { price: 900, ..listing.clone() });
+
+        assert_ne!(listed, empty);
+        assert_ne!(root_hash(), listed);
+    }
+
+    #[test]
+    fn should_restore_the_root_hash_when_a_listing_is_uncertified() {
+        let empty = root_hash();
+        let listing = fixtures::listing("collection", "listing", 1_000);
+
+        certify_listing(&listing);
+        uncertify_listing(&listing.collection_id, &listing.id);
+
+        assert_eq!(root_hash(), empty);
+    }
+
+    #[test]
+    fn should_uncertify_a_listing_that_is_no_longer_active() {
+        let empty = root_hash();
+        let listing = fixtures::listing("collection", "listing", 1_000);
+
+        certify_listing(&listing);
+        certify_listing(&Listing { status: ListingStatus::Sold, ..listing });
+
+        assert_eq!(root_hash(), empty);
+    }
+}
+
+mod witnesses {
+    use super::*;
+
+    #[test]
+    fn should_prove_a_listing_against_the_root_hash() {
+        let listing = fixtures::listing("collection", "listing", 1_000);
+        certify_listing(&listing);
+        certify_listing(&fixtures::listing("collection", "other", 2_000));
+        certify_collection(&fixtures::collection("collection"));
+
+        let tree = certified_tree(Reveal::Listings("collection", &["listing".to_string()]));
+
+        assert_eq!(tree.digest(), root_hash());
+        assert_eq!(
+            found(&tree, &["listings", "collection", "listing"]),
+            Some(listing_digest(&listing).to_vec())
+        );
+        assert_eq!(found(&tree, &["listings", "collection", "other"]), None);
+    }
+
+    #[test]
+    fn should_prove_a_collection_against_the_root_hash() {
+        let collection = fixtures::collection("collection");
+        certify_collection(&collection);
+        certify_listing(&fixtures::listing("collection", "listing", 1_000));
+
+        let tree = certified_tree(Reveal::Collection("collection"));
+
+        assert_eq!(tree.digest(), root_hash());
+        assert_eq!(
+            found(&tree, &["collections", "collection"]),
+            Some(collection_digest(&collection).to_vec())
+        );
+    }
+}
+
+mod block_tip {
+    use super::*;
+
+    #[test]
+    fn should_encode_the_block_index_as_leb128() {
+        assert_eq!(leb128(0), vec![0x00]);
+        assert_eq!(leb128(127), vec![0x7f]);
+        assert_eq!(leb128(128), vec![0x80, 0x01]);
+        assert_eq!(leb128(624_485), vec![0xe5, 0x8e, 0x26]);
+    }
+}
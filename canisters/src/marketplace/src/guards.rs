@@ -0,0 +1,109 @@
+//! Authorization checks shared by every marketplace update endpoint.
+//!
+//! The `caller_*` functions have the signature expected by `#[update(guard = "...")]`;
+//! the `authorize_*` functions check the caller against a specific record and return
+//! [`MarketplaceError::Unauthorized`]. [`ensure_not_paused`] checks the admin's
+//! pause switches.
+
+use candid::Principal;
+use ic_cdk::api::msg_caller;
+use crate::state;
+use crate::types::{Collection, Listing, MarketplaceError, MarketplaceResult, Offer, PauseTarget};
+
+pub const UNAUTHORIZED: &str = "Unauthorized";
+
+pub fn unauthorized() -> String {
+    UNAUTHORIZED.to_string()
+}
+
+pub fn is_admin(principal: &Principal) -> bool {
+    *principal == state::get_admin()
+}
+
+pub fn is_moderator(principal: &Principal) -> bool {
+    is_admin(principal) || state::is_moderator(principal)
+}
+
+pub fn caller_is_admin() -> Result<(), String> {
+    if is_admin(&msg_caller()) {
+        Ok(())
+    } else {
+        Err(unauthorized())
+    }
+}
+
+pub fn caller_is_not_anonymous() -> Result<(), String> {
+    if msg_caller() == Principal::anonymous() {
+        Err(unauthorized())
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails while the admin has `target` paused.
+pub fn ensure_not_paused(target: PauseTarget) -> MarketplaceResult<()> {
+    match state::get_pause(target) {
+        Some(pause) => Err(MarketplaceError::Paused {
+            target,
+            reason: pause.reason,
+            resume_at: pause.resume_at,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Collection creator or admin.
+pub fn authorize_collection_creator(caller: &Principal, collection: &Collection) -> MarketplaceResult<()> {
+    if &collection.creator == caller || is_admin(caller) {
+        Ok(())
+    } else {
+        Err(MarketplaceError::Unauthorized)
+    }
+}
+
+/// Only the seller may change the terms of a listing.
+pub fn authorize_listing_seller(caller: &Principal, listing: &Listing) -> MarketplaceResult<()> {
+    if &listing.seller == caller {
+        Ok(())
+    } else {
+        Err(MarketplaceError::Unauthorized)
+    }
+}
+
+/// Seller, moderator or admin, for taking a listing down.
+pub fn authorize_listing_moderation(caller: &Principal, listing: &Listing) -> MarketplaceResult<()> {
+    if &listing.seller == caller || is_moderator(caller) {
+        Ok(())
+    } else {
+        Err(MarketplaceError::Unauthorized)
+    }
+}
+
+pub fn authorize_offer_bidder(caller: &Principal, offer: &Offer) -> MarketplaceResult<()> {
+    if &offer.bidder == caller {
+        Ok(())
+    } else {
+        Err(MarketplaceError::Unauthorized)
+    }
+}
+
+/// Seller, buyer or admin.
+pub fn authorize_sale_party(caller: &Principal, listing: &Listing, buyer: &Principal) -> MarketplaceResult<()> {
+    if &listing.seller == caller || buyer == caller || is_admin(caller) {
+        Ok(())
+    } else {
+        Err(MarketplaceError::Unauthorized)
+    }
+}
+
+/// Resolves the wallet owner for the Solana wallet endpoints. Callers may only act on
+/// their own derived wallet; acting on another principal's wallet is admin-only.
+pub fn authorize_wallet_owner(owner: Option<Principal>) -> MarketplaceResult<Principal> {
+    let caller = msg_caller();
+    match owner {
+        Some(owner) if owner == caller || is_admin(&caller) => Ok(owner),
+        Some(_) => Err(MarketplaceError::Unauthorized),
+        None if caller == Principal::anonymous() => Err(MarketplaceError::Unauthorized),
+        None => Ok(caller),
+    }
+}
@@ -0,0 +1,127 @@
+//! Metaplex Token Metadata JSON standard, as read by wallets and Solana explorers.
+//! See <https://developers.metaplex.com/token-metadata/token-standard>.
+
+use serde::Serialize;
+use crate::types::{Collection, NftMetadata};
+
+#[derive(Serialize)]
+pub struct Attribute {
+    pub trait_type: String,
+    pub value: String,
+}
+
+#[derive(Serialize)]
+pub struct File {
+    pub uri: String,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+}
+
+#[derive(Serialize)]
+pub struct Properties {
+    pub files: Vec<File>,
+    pub category: String,
+}
+
+#[derive(Serialize)]
+pub struct CollectionRef {
+    pub name: String,
+    pub family: String,
+}
+
+#[derive(Serialize)]
+pub struct Metadata {
+    pub name: String,
+    pub symbol: String,
+    pub description: String,
+    pub seller_fee_basis_points: u16,
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub external_url: Option<String>,
+    pub attributes: Vec<Attribute>,
+    pub properties: Properties,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collection: Option<CollectionRef>,
+}
+
+/// Best guess from the extension; explorers only use it to pick a renderer.
+fn mime_type(uri: &str) -> String {
+    let extension = uri
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "mp4" => "video/mp4",
+        _ => "image/png",
+    }
+    .to_string()
+}
+
+fn properties(image: &str) -> Properties {
+    Properties {
+        files: vec![File {
+            uri: image.to_string(),
+            mime_type: mime_type(image),
+        }],
+        category: "image".to_string(),
+    }
+}
+
+/// The collection's own metadata, e.g. for the collection NFT's URI.
+pub fn collection_metadata(collection: &Collection) -> Metadata {
+    Metadata {
+        name: collection.name.clone(),
+        symbol: collection.symbol.clone(),
+        description: collection.description.clone(),
+        seller_fee_basis_points: collection.royalty_bps,
+        image: collection.image_url.clone(),
+        external_url: collection
+            .metadata
+            .iter()
+            .find(|(key, _)| key == "external_url")
+            .map(|(_, value)| value.clone()),
+        attributes: vec![],
+        properties: properties(&collection.image_url),
+        collection: None,
+    }
+}
+
+/// Metadata of item `index`. Items with a listing use its `NftMetadata`; the
+/// others fall back to the collection's name and image.
+pub fn item_metadata(collection: &Collection, index: u64, nft: Option<&NftMetadata>) -> Metadata {
+    let (name, image, attributes) = match nft {
+        Some(nft) => (
+            nft.name.clone(),
+            nft.image_url.clone(),
+            nft.attributes
+                .iter()
+                .map(|attribute| Attribute {
+                    trait_type: attribute.trait_type.clone(),
+                    value: attribute.value.clone(),
+                })
+                .collect(),
+        ),
+        None => (
+            format!("{} #{}", collection.name, index),
+            collection.image_url.clone(),
+            vec![],
+        ),
+    };
+
+    Metadata {
+        properties: properties(&image),
+        collection: Some(CollectionRef {
+            name: collection.name.clone(),
+            family: collection.symbol.clone(),
+        }),
+        name,
+        image,
+        attributes,
+        ..collection_metadata(collection)
+    }
+}
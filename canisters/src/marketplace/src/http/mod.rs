@@ -0,0 +1,202 @@
+//! HTTP gateway. Serves collections, listings and Metaplex-style NFT metadata as
+//! JSON, so crawlers and metadata URIs can point straight at the canister:
+//!
+//! ```text
+//! GET /collections/{id}                          collection
+//! GET /collections/{id}/listings?page&limit&status
+//! GET /collections/{id}/metadata/collection.json collection metadata
+//! GET /collections/{id}/metadata/{n}.json        metadata of item n
+//! GET /metrics                                   Prometheus metrics
+//! GET /logs?time&sort                            INFO canister logs
+//! GET /icrc3/block-types                         ICRC-3 block type schema (Markdown)
+//! ```
+//!
+//! Responses are not certified, so URIs must use the raw domain
+//! (`https://<canister_id>.raw.icp0.io/...`).
+
+pub mod metaplex;
+
+use ic_http_types::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use canlog::Sort;
+use ic_metrics_encoder::MetricsEncoder;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use std::str::FromStr;
+use crate::logs::Priority;
+use crate::state;
+use crate::types::ListingStatus;
+
+const DEFAULT_PAGE_SIZE: u32 = 20;
+
+fn json_response<T: Serialize>(value: &T) -> HttpResponse {
+    match serde_json::to_vec(value) {
+        Ok(body) => HttpResponseBuilder::ok()
+            .header("Content-Type", "application/json")
+            .header("Access-Control-Allow-Origin", "*")
+            .header("Cache-Control", "public, max-age=60")
+            .with_body_and_content_length(body)
+            .build(),
+        Err(e) => error_response(500, &format!("Failed to encode response: {}", e)),
+    }
+}
+
+fn error_response(status_code: u16, message: &str) -> HttpResponse {
+    let body = serde_json::json!({ "error": message }).to_string();
+    HttpResponse {
+        status_code,
+        headers: vec![
+            ("Content-Type".to_string(), "application/json".to_string()),
+            ("Content-Length".to_string(), body.len().to_string()),
+            ("Access-Control-Allow-Origin".to_string(), "*".to_string()),
+        ],
+        body: ByteBuf::from(body),
+    }
+}
+
+fn not_found(message: &str) -> HttpResponse {
+    error_response(404, message)
+}
+
+fn bad_request(message: &str) -> HttpResponse {
+    error_response(400, message)
+}
+
+fn query_u32(req: &HttpRequest, name: &str, default: u32) -> Result<u32, HttpResponse> {
+    match req.raw_query_param(name) {
+        Some(value) => value
+            .parse::<u32>()
+            .map_err(|_| bad_request(&format!("Invalid {} parameter", name))),
+        None => Ok(default),
+    }
+}
+
+fn listing_status(value: &str) -> Option<ListingStatus> {
+    match value.to_lowercase().as_str() {
+        "active" => Some(ListingStatus::Active),
+        "sold" => Some(ListingStatus::Sold),
+        "cancelled" => Some(ListingStatus::Cancelled),
+        "expired" => Some(ListingStatus::Expired),
+        _ => None,
+    }
+}
+
+fn collection(collection_id: &str) -> HttpResponse {
+    match state::get_collection(collection_id) {
+        Some(collection) => json_response(&collection),
+        None => not_found("Collection not found"),
+    }
+}
+
+fn collection_listings(req: &HttpRequest, collection_id: &str) -> HttpResponse {
+    if state::get_collection(collection_id).is_none() {
+        return not_found("Collection not found");
+    }
+    let page = match query_u32(req, "page", 0) {
+        Ok(page) => page,
+        Err(response) => return response,
+    };
+    let limit = match query_u32(req, "limit", DEFAULT_PAGE_SIZE) {
+        Ok(limit) => limit.min(state::MAX_PAGE_SIZE),
+        Err(response) => return response,
+    };
+    let status = match req.raw_query_param("status") {
+        Some(value) => match listing_status(value) {
+            Some(status) => Some(status),
+            None => return bad_request("Invalid status parameter"),
+        },
+        None => None,
+    };
+
+    json_response(&state::get_collection_listings(collection_id, page, limit, status))
+}
+
+fn metadata(collection_id: &str, file: &str) -> HttpResponse {
+    let Some(collection) = state::get_collection(collection_id) else {
+        return not_found("Collection not found");
+    };
+    let Some(name) = file.strip_suffix(".json") else {
+        return not_found("Metadata files end in .json");
+    };
+
+    if name == "collection" {
+        return json_response(&metaplex::collection_metadata(&collection));
+    }
+
+    let Ok(index) = name.parse::<u64>() else {
+        return not_found("Unknown metadata file");
+    };
+    if index >= collection.total_supply {
+        return not_found("Item index is past the collection's total supply");
+    }
+    let listing = state::find_listing_by_nft(collection_id, name);
+    json_response(&metaplex::item_metadata(
+        &collection,
+        index,
+        listing.as_ref().map(|listing| &listing.nft_metadata),
+    ))
+}
+
+fn metrics() -> HttpResponse {
+    let mut writer = MetricsEncoder::new(vec![], (crate::time::get_current_time() / 1_000_000) as i64);
+    match crate::metrics::encode_metrics(&mut writer) {
+        Ok(()) => HttpResponseBuilder::ok()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .header("Cache-Control", "no-store")
+            .with_body_and_content_length(writer.into_inner())
+            .build(),
+        Err(e) => error_response(500, &format!("Failed to encode metrics: {}", e)),
+    }
+}
+
+/// Only `INFO` entries are public; `DEBUG` and `TRACE_HTTP` carry provider responses
+/// and are served to admins by the `get_logs` query.
+fn logs(req: &HttpRequest) -> HttpResponse {
+    match req.raw_query_param("priority").map(Priority::from_str) {
+        Some(Ok(Priority::Info)) | None => {}
+        Some(Ok(_)) => return error_response(403, "Only INFO logs are public"),
+        Some(Err(e)) => return bad_request(&e),
+    }
+    let since = match req.raw_query_param("time") {
+        Some(value) => match value.parse::<u64>() {
+            Ok(time) => time,
+            Err(_) => return bad_request("Invalid time parameter"),
+        },
+        None => 0,
+    };
+    let sort = match req.raw_query_param("sort") {
+        Some("desc") => Sort::Descending,
+        Some("asc") | None => Sort::Ascending,
+        Some(_) => return bad_request("Invalid sort parameter"),
+    };
+
+    HttpResponseBuilder::ok()
+        .header("Content-Type", "application/json; charset=utf-8")
+        .header("Cache-Control", "no-store")
+        .with_body_and_content_length(crate::logs::export_logs(Some(Priority::Info), since, sort))
+        .build()
+}
+
+/// Schema of the ICRC-3 blocks, linked from `icrc3_supported_block_types`.
+fn block_types() -> HttpResponse {
+    HttpResponseBuilder::ok()
+        .header("Content-Type", "text/markdown; charset=utf-8")
+        .with_body_and_content_length(include_str!("../../docs/icrc3-block-types.md").as_bytes().to_vec())
+        .build()
+}
+
+pub fn handle(req: HttpRequest) -> HttpResponse {
+    if req.method != "GET" && req.method != "HEAD" {
+        return error_response(405, "Method not allowed");
+    }
+
+    let segments: Vec<&str> = req.path().trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["collections", id] => collection(id),
+        ["collections", id, "listings"] => collection_listings(&req, id),
+        ["collections", id, "metadata", file] => metadata(id, file),
+        ["metrics"] => metrics(),
+        ["logs"] => logs(&req),
+        ["icrc3", "block-types"] => block_types(),
+        _ => not_found("Not found"),
+    }
+}
@@ -0,0 +1,46 @@
+use std::cell::Cell;
+use std::time::Duration;
+use crate::state;
+use crate::x_chain::solana::tracker;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(20);
+
+thread_local! {
+    static POLLING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Clears the polling flag when the poll ends, including when it traps.
+struct PollGuard;
+
+impl PollGuard {
+    fn new() -> Option<Self> {
+        if POLLING.with(|p| p.replace(true)) {
+            return None;
+        }
+        Some(PollGuard)
+    }
+}
+
+impl Drop for PollGuard {
+    fn drop(&mut self) {
+        POLLING.with(|p| p.set(false));
+    }
+}
+
+pub fn start() {
+    ic_cdk_timers::set_timer_interval(POLL_INTERVAL, poll_deployment_transactions);
+}
+
+pub fn poll_deployment_transactions() {
+    if state::count_pending_deployment_transactions() == 0 {
+        return;
+    }
+    // A poll spans several RPC calls and may outlast the interval
+    let Some(guard) = PollGuard::new() else {
+        return;
+    };
+    ic_cdk::futures::spawn(async move {
+        let _guard = guard;
+        tracker::poll_deployment_transactions().await;
+    });
+}
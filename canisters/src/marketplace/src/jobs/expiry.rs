@@ -0,0 +1,42 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+use crate::state;
+use crate::logs::{log, Priority};
+
+#[cfg(test)]
+mod tests;
+
+const EXPIRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Upper bound on records expired per run, to stay well inside the instruction limit.
+const EXPIRY_BATCH_SIZE: usize = 200;
+
+pub fn start() {
+    ic_cdk_timers::set_timer_interval(EXPIRY_INTERVAL, expire_stale_records);
+}
+
+pub fn expire_stale_records() {
+    let now = crate::time::get_current_time();
+
+    let listings = state::expire_due_listings(now, EXPIRY_BATCH_SIZE);
+    let mut affected_collections = BTreeSet::new();
+    for listing in listings.iter() {
+        affected_collections.insert(listing.collection_id.clone());
+    }
+    for collection_id in affected_collections.iter() {
+        state::refresh_collection_listing_stats(collection_id);
+    }
+
+    let offers = state::expire_due_offers(now, EXPIRY_BATCH_SIZE);
+    let reservations = state::release_due_reservations(now, EXPIRY_BATCH_SIZE);
+
+    if !listings.is_empty() || !offers.is_empty() || !reservations.is_empty() {
+        log!(
+            Priority::Info,
+            "Expired {} listings, {} offers and {} reservations",
+            listings.len(),
+            offers.len(),
+            reservations.len()
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use super::*;
+use crate::state::collections::{get_collection, insert_collection};
+use crate::state::fixtures::{self, principal, HOUR_NANOS};
+use crate::state::listings::{get_listing, insert_listing, reserve_listing};
+use crate::state::offers::{get_offer, insert_offer};
+use crate::time::{get_current_time, set_current_time};
+use crate::types::{Listing, ListingStatus, MarketplaceEvent, OfferStatus};
+
+/// A listing of `collection` that expires `expires_in` nanoseconds from now.
+fn listed(listing_id: &str, expires_in: Option<u64>) -> Listing {
+    let mut listing = fixtures::listing("collection", listing_id, 1_000);
+    listing.expires_at = expires_in.map(|expires_in| get_current_time() + expires_in);
+    insert_listing(listing.clone());
+    listing
+}
+
+fn listing_status(listing_id: &str) -> ListingStatus {
+    get_listing("collection", listing_id).unwrap().status
+}
+
+fn offer_status(offer_id: &str) -> OfferStatus {
+    get_offer(offer_id).unwrap().status
+}
+
+fn advance(nanos: u64) {
+    set_current_time(get_current_time() + nanos);
+}
+
+fn recorded_events() -> Vec<MarketplaceEvent> {
+    state::get_events_from(0, 100).into_iter().map(|event| event.event).collect()
+}
+
+mod listings {
+    use super::*;
+
+    #[test]
+    fn should_expire_listings_past_their_expiry() {
+        listed("due", Some(HOUR_NANOS));
+        listed("later", Some(2 * HOUR_NANOS));
+        listed("open", None);
+
+        advance(HOUR_NANOS);
+        expire_stale_records();
+
+        assert_eq!(listing_status("due"), ListingStatus::Expired);
+        assert_eq!(listing_status("later"), ListingStatus::Active);
+        assert_eq!(listing_status("open"), ListingStatus::Active);
+        assert!(recorded_events().iter().any(|event| matches!(
+            event,
+            MarketplaceEvent::ListingExpired { listing_id, .. } if listing_id == "due"
+        )));
+    }
+
+    #[test]
+    fn should_leave_listings_that_are_not_due() {
+        listed("later", Some(HOUR_NANOS));
+
+        advance(HOUR_NANOS - 1);
+        expire_stale_records();
+
+        assert_eq!(listing_status("later"), ListingStatus::Active);
+    }
+
+    #[test]
+    fn should_refresh_the_collection_stats() {
+        insert_collection(fixtures::collection("collection"));
+        listed("due", Some(HOUR_NANOS));
+        listed("open", None);
+        assert_eq!(get_collection("collection").unwrap().listed_count, 2);
+
+        advance(HOUR_NANOS);
+        expire_stale_records();
+
+        assert_eq!(get_collection("collection").unwrap().listed_count, 1);
+    }
+
+    #[test]
+    fn should_expire_at_most_a_batch_per_run() {
+        for id in ["a", "b", "c"] {
+            listed(id, Some(HOUR_NANOS));
+        }
+        let now = get_current_time() + HOUR_NANOS;
+
+        assert_eq!(state::expire_due_listings(now, 2).len(), 2);
+        assert_eq!(state::count_listings_by_status(&ListingStatus::Active), 1);
+        assert_eq!(state::expire_due_listings(now, 2).len(), 1);
+        assert!(state::expire_due_listings(now, 2).is_empty());
+    }
+
+    #[test]
+    fn should_expire_the_offers_on_an_expired_listing() {
+        let listing = listed("due", Some(HOUR_NANOS / 2));
+        insert_offer(fixtures::offer("offer", &listing, principal(10), 800));
+
+        advance(HOUR_NANOS / 2);
+        expire_stale_records();
+
+        assert_eq!(offer_status("offer"), OfferStatus::Expired);
+    }
+}
+
+mod offers {
+    use super::*;
+
+    #[test]
+    fn should_expire_offers_past_their_expiry() {
+        let listing = listed("open", None);
+        insert_offer(fixtures::offer("offer", &listing, principal(10), 800));
+
+        advance(HOUR_NANOS);
+        expire_stale_records();
+
+        assert_eq!(offer_status("offer"), OfferStatus::Expired);
+        assert_eq!(listing_status("open"), ListingStatus::Active);
+        assert!(recorded_events().iter().any(|event| matches!(
+            event,
+            MarketplaceEvent::OfferExpired { offer_id, .. } if offer_id == "offer"
+        )));
+    }
+
+    #[test]
+    fn should_expire_at_most_a_batch_per_run() {
+        let listing = listed("open", None);
+        for i in 0..3 {
+            insert_offer(fixtures::offer(&format!("offer-{}", i), &listing, principal(10 + i), 800));
+        }
+        let now = get_current_time() + HOUR_NANOS;
+
+        assert_eq!(state::expire_due_offers(now, 2).len(), 2);
+        assert_eq!(state::expire_due_offers(now, 2).len(), 1);
+        assert!(state::expire_due_offers(now, 2).is_empty());
+    }
+}
+
+mod reservations {
+    use super::*;
+
+    #[test]
+    fn should_reopen_listings_whose_reservation_lapsed() {
+        listed("reserved", None);
+        reserve_listing("collection", "reserved", principal(10), 800, get_current_time() + HOUR_NANOS).unwrap();
+
+        advance(HOUR_NANOS);
+        expire_stale_records();
+
+        let listing = get_listing("collection", "reserved").unwrap();
+        assert_eq!(listing.status, ListingStatus::Active);
+        assert_eq!(listing.reserved_for, None);
+        assert_eq!(listing.reserved_price, None);
+    }
+}
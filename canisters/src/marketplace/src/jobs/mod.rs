@@ -0,0 +1,10 @@
+//! Periodic canister jobs. Timers do not survive upgrades, so `start` is called from
+//! both `init` and `post_upgrade`.
+
+pub mod deployments;
+pub mod expiry;
+
+pub fn start() {
+    expiry::start();
+    deployments::start();
+}
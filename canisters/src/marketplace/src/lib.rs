@@ -1,14 +1,23 @@
 use candid::{Principal, Nat};
-use ic_cdk_macros::init;
+use ic_cdk_macros::{init, post_upgrade};
 use sol_rpc_types::{CommitmentLevel, TokenAmount};
 
 pub mod types;
 pub mod state;
 pub mod api;
+pub mod guards;
+pub mod certification;
+pub mod http;
+pub mod jobs;
+pub mod logs;
+pub mod metrics;
+pub mod migrations;
+mod time;
 pub mod utils;
 pub mod x_chain;
 
 use types::*;
+use logs::{log, Priority};
 use state::config::{SolanaNetwork, Ed25519KeyName};
 
 #[derive(candid::CandidType, serde::Deserialize)]
@@ -18,6 +27,7 @@ pub struct InitArgs {
     pub solana_network: Option<SolanaNetwork>,
     pub ed25519_key_name: Option<Ed25519KeyName>,
     pub solana_commitment_level: Option<CommitmentLevel>,
+    pub log_verbosity: Option<logs::Priority>,
 }
 
 #[init]
@@ -28,8 +38,18 @@ fn init(args: InitArgs) {
         args.solana_network,
         args.ed25519_key_name,
         args.solana_commitment_level,
+        args.log_verbosity,
     );
-    ic_cdk::println!("Marketplace canister initialized with admin: {}", args.admin);
+    migrations::init_schema_version();
+    jobs::start();
+    log!(Priority::Info, "Marketplace canister initialized with admin: {}", args.admin);
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    certification::start_rebuild();
+    migrations::start();
+    jobs::start();
 }
 
 pub use api::*;
@@ -0,0 +1,79 @@
+//! Structured canister logs, kept in bounded per-priority ring buffers. `INFO` entries
+//! are served at `/logs` by the HTTP gateway, all levels by the admin `get_logs` query.
+//!
+//! Use [`log!`] rather than `ic_cdk::println!`. Entries above the configured
+//! verbosity are dropped before they are formatted.
+
+use candid::CandidType;
+use canlog::{GetLogFilter, Log, LogFilter, LogPriorityLevels, Sort};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use crate::state::config;
+
+/// Log priorities, from least to most verbose.
+#[derive(
+    LogPriorityLevels, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Debug, Copy, Clone,
+)]
+pub enum Priority {
+    #[log_level(capacity = 1000, name = "INFO")]
+    Info,
+    #[log_level(capacity = 1000, name = "DEBUG")]
+    Debug,
+    /// Per-provider RPC results.
+    #[log_level(capacity = 1000, name = "TRACE_HTTP")]
+    TraceHttp,
+}
+
+impl FromStr for Priority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Priority::Info),
+            "debug" => Ok(Priority::Debug),
+            "tracehttp" | "trace_http" => Ok(Priority::TraceHttp),
+            _ => Err(format!("Unknown log priority: {}", s)),
+        }
+    }
+}
+
+impl GetLogFilter for Priority {
+    fn get_log_filter() -> LogFilter {
+        LogFilter::ShowAll
+    }
+}
+
+pub fn is_enabled(priority: Priority) -> bool {
+    priority <= config::get_log_verbosity()
+}
+
+/// `canlog::log!`, skipped when `$priority` is above the configured verbosity.
+macro_rules! log {
+    ($priority:expr, $($arg:tt)*) => {{
+        let priority = $priority;
+        if $crate::logs::is_enabled(priority) {
+            canlog::log!(priority, $($arg)*);
+        }
+    }};
+}
+pub(crate) use log;
+
+/// Largest `/logs` response body; older entries beyond it are cut.
+const MAX_LOGS_BODY_SIZE: usize = 2_000_000;
+
+/// JSON log entries for `/logs` and `get_logs`. `priority` limits the output to one buffer and
+/// `since` (nanoseconds) drops older entries.
+pub fn export_logs(priority: Option<Priority>, since: u64, sort: Sort) -> Vec<u8> {
+    let mut log: Log<Priority> = Default::default();
+    match priority {
+        Some(priority) => log.push_logs(priority),
+        None => {
+            for priority in Priority::get_priorities() {
+                log.push_logs(priority);
+            }
+        }
+    }
+    log.entries.retain(|entry| entry.timestamp >= since);
+    log.sort_logs(sort);
+    log.serialize_logs(MAX_LOGS_BODY_SIZE).into_bytes()
+}
@@ -0,0 +1,135 @@
+//! Prometheus metrics, served at `/metrics` by the HTTP gateway.
+//!
+//! Marketplace gauges are computed from stable memory on each scrape. SOL RPC
+//! counters live on the heap and restart from zero after an upgrade, which
+//! Prometheus `rate()`/`increase()` already handle as a counter reset.
+
+use ic_metrics_encoder::MetricsEncoder;
+use sol_rpc_client::SolRpcEndpoint;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use strum::IntoEnumIterator;
+use crate::state;
+use crate::types::ListingStatus;
+
+const WASM_PAGE_SIZE: u64 = 65536;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RpcOutcome {
+    Consistent,
+    Inconsistent,
+    /// The SOL RPC canister rejected the call or it could not be delivered.
+    Rejected,
+    /// A response that is not a `MultiRpcResult`.
+    Other,
+}
+
+impl RpcOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RpcOutcome::Consistent => "consistent",
+            RpcOutcome::Inconsistent => "inconsistent",
+            RpcOutcome::Rejected => "rejected",
+            RpcOutcome::Other => "other",
+        }
+    }
+}
+
+#[derive(Default)]
+struct RpcStats {
+    calls: u64,
+    cycles: u128,
+}
+
+thread_local! {
+    // (endpoint, outcome) -> stats
+    static RPC_STATS: RefCell<BTreeMap<(String, RpcOutcome), RpcStats>> = RefCell::new(BTreeMap::new());
+}
+
+/// Names the call by its `SolRpcEndpoint` variant, e.g. `GetTransaction`.
+fn endpoint_label(method: &str) -> String {
+    SolRpcEndpoint::iter()
+        .find(|endpoint| endpoint.rpc_method() == method)
+        .map(|endpoint| format!("{:?}", endpoint))
+        .unwrap_or_else(|| method.to_string())
+}
+
+pub fn observe_rpc_call(method: &str, outcome: RpcOutcome, cycles_spent: u128) {
+    RPC_STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        let entry = stats.entry((endpoint_label(method), outcome)).or_default();
+        entry.calls += 1;
+        entry.cycles += cycles_spent;
+    });
+}
+
+pub fn encode_metrics(w: &mut MetricsEncoder<Vec<u8>>) -> std::io::Result<()> {
+    w.encode_gauge(
+        "marketplace_cycles_balance",
+        ic_cdk::api::canister_cycle_balance() as f64,
+        "Cycles balance of the canister.",
+    )?;
+    w.encode_gauge(
+        "marketplace_stable_memory_bytes",
+        (ic_cdk::stable::stable_size() * WASM_PAGE_SIZE) as f64,
+        "Size of the stable memory allocated by the canister.",
+    )?;
+    #[cfg(target_arch = "wasm32")]
+    w.encode_gauge(
+        "marketplace_heap_memory_bytes",
+        (core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE) as f64,
+        "Size of the heap memory allocated by the canister.",
+    )?;
+
+    let mut collections: BTreeMap<(String, String), u64> = BTreeMap::new();
+    state::for_each_collection(|collection| {
+        let key = (
+            collection.blockchain.as_str().to_string(),
+            format!("{:?}", collection.status).to_lowercase(),
+        );
+        *collections.entry(key).or_default() += 1;
+    });
+    let mut gauge = w.gauge_vec("marketplace_collections", "Number of collections by chain and status.")?;
+    for ((chain, status), count) in collections.iter() {
+        gauge = gauge.value(&[("chain", chain.as_str()), ("status", status.as_str())], *count as f64)?;
+    }
+
+    w.encode_gauge(
+        "marketplace_active_listings",
+        state::count_listings_by_status(&ListingStatus::Active) as f64,
+        "Number of active listings.",
+    )?;
+
+    w.encode_gauge(
+        "marketplace_pending_solana_transactions",
+        state::count_pending_deployment_transactions() as f64,
+        "Number of canister-signed Solana transactions not finalized yet.",
+    )?;
+
+    let mut counter = w.counter_vec(
+        "marketplace_sales_volume",
+        "Total sale volume per currency, in the currency's smallest unit.",
+    )?;
+    for (currency, volume) in state::sales_volume_by_currency().iter() {
+        counter = counter.value(&[("currency", currency.as_str())], *volume as f64)?;
+    }
+
+    RPC_STATS.with(|s| -> std::io::Result<()> {
+        let stats = s.borrow();
+        let mut calls = w.counter_vec(
+            "marketplace_sol_rpc_calls",
+            "SOL RPC calls by endpoint and outcome.",
+        )?;
+        for ((endpoint, outcome), stat) in stats.iter() {
+            calls = calls.value(&[("endpoint", endpoint.as_str()), ("outcome", outcome.as_str())], stat.calls as f64)?;
+        }
+        let mut cycles = w.counter_vec(
+            "marketplace_sol_rpc_cycles_spent",
+            "Cycles spent on SOL RPC calls, after refunds, by endpoint and outcome.",
+        )?;
+        for ((endpoint, outcome), stat) in stats.iter() {
+            cycles = cycles.value(&[("endpoint", endpoint.as_str()), ("outcome", outcome.as_str())], stat.cycles as f64)?;
+        }
+        Ok(())
+    })
+}
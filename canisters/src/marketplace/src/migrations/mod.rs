@@ -0,0 +1,266 @@
+//! Versioned stable-memory schema migrations.
+//!
+//! Each [`Migration`] moves the stored schema from `version - 1` to `version`. Pending
+//! migrations start automatically in `post_upgrade` and run one batch per timer message,
+//! so a large store never has to fit in a single message's instruction limit. Progress is
+//! kept in stable memory, so an upgrade in the middle of a migration resumes where it
+//! stopped.
+//!
+//! Records are Candid-encoded, so adding a field as an `Option` (Candid `opt`) needs no
+//! migration: records written before the field existed decode it as `None`. This is how
+//! the `Listing::reserved_*` fields and `SolanaCollectionData::candy_machine_state` and
+//! `candy_guard_*` were added. A migration is only registered when stored data has to be
+//! rewritten or derived, e.g. a changed layout or a new secondary index.
+
+use candid::{CandidType, Decode, Encode};
+use ic_stable_structures::{StableCell, Storable, storable::Bound};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::time::Duration;
+use crate::state;
+use crate::state::memory::{get_memory, Memory, MIGRATION_STATE_MEMORY_ID};
+use crate::logs::{log, Priority};
+
+#[cfg(test)]
+mod tests;
+
+/// Records processed per message.
+const MIGRATION_BATCH_SIZE: usize = 100;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationTarget {
+    Collection,
+    Listing,
+    Offer,
+    Sale,
+    Event,
+    Config,
+}
+
+pub struct BatchProgress {
+    pub processed: u64,
+    /// Where the next batch resumes, for migrations that rewrite records in place.
+    pub next_cursor: Option<String>,
+    pub done: bool,
+}
+
+pub struct Migration {
+    pub version: u32,
+    pub target: MigrationTarget,
+    pub description: &'static str,
+    pub run_batch: fn(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String>,
+}
+
+/// Registered migrations, in version order.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        target: MigrationTarget::Collection,
+        description: "Move collections from the V0 layout into the current collections map",
+        run_batch: state::collections::migrate_v0_collections_batch,
+    },
+    Migration {
+        version: state::blocks::BLOCKS_SCHEMA_VERSION,
+        target: MigrationTarget::Event,
+        description: "Backfill ICRC-3 blocks for existing activity events",
+        run_batch: state::blocks::backfill_blocks_batch,
+    },
+    Migration {
+        version: 3,
+        target: MigrationTarget::Collection,
+        description: "Rebuild the collection indexes",
+        run_batch: state::collections::reindex_collections_batch,
+    },
+    Migration {
+        version: 4,
+        target: MigrationTarget::Listing,
+        description: "Rebuild the listing indexes, including status, NFT and reservation",
+        run_batch: state::listings::reindex_listings_batch,
+    },
+    Migration {
+        version: 5,
+        target: MigrationTarget::Offer,
+        description: "Rebuild the offer indexes",
+        run_batch: state::offers::reindex_offers_batch,
+    },
+    Migration {
+        version: 6,
+        target: MigrationTarget::Sale,
+        description: "Rebuild the sale indexes and transaction signature map",
+        run_batch: state::sales::reindex_sales_batch,
+    },
+    Migration {
+        version: state::sales::SALES_VOLUME_SCHEMA_VERSION,
+        target: MigrationTarget::Sale,
+        description: "Backfill the per-currency sales volume",
+        run_batch: state::sales::backfill_sales_volume_batch,
+    },
+];
+
+pub fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RunningMigration {
+    pub version: u32,
+    pub target: MigrationTarget,
+    pub description: String,
+    pub cursor: Option<String>,
+    pub processed: u64,
+    pub started_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct MigrationState {
+    pub schema_version: u32,
+    pub running: Option<RunningMigration>,
+    pub last_error: Option<String>,
+    pub last_completed_at: Option<u64>,
+}
+
+impl Storable for MigrationState {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        Encode!(&self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MigrationStatus {
+    pub schema_version: u32,
+    pub target_version: u32,
+    pub running: Option<RunningMigration>,
+    pub last_error: Option<String>,
+    pub last_completed_at: Option<u64>,
+}
+
+thread_local! {
+    static MIGRATION_STATE: RefCell<StableCell<MigrationState, Memory>> = RefCell::new(
+        StableCell::init(get_memory(MIGRATION_STATE_MEMORY_ID), MigrationState::default())
+    );
+}
+
+fn read_state<R>(f: impl FnOnce(&MigrationState) -> R) -> R {
+    MIGRATION_STATE.with(|s| f(s.borrow().get()))
+}
+
+fn mutate_state<R>(f: impl FnOnce(&mut MigrationState) -> R) -> R {
+    MIGRATION_STATE.with(|s| {
+        let mut cell = s.borrow_mut();
+        let mut state = cell.get().clone();
+        let result = f(&mut state);
+        cell.set(state);
+        result
+    })
+}
+
+/// A fresh install starts at the latest schema; there is nothing to migrate.
+pub fn init_schema_version() {
+    mutate_state(|s| s.schema_version = latest_schema_version());
+}
+
+pub fn get_migration_status() -> MigrationStatus {
+    read_state(|s| MigrationStatus {
+        schema_version: s.schema_version,
+        target_version: latest_schema_version(),
+        running: s.running.clone(),
+        last_error: s.last_error.clone(),
+        last_completed_at: s.last_completed_at,
+    })
+}
+
+pub fn schema_version() -> u32 {
+    read_state(|s| s.schema_version)
+}
+
+pub fn is_migrating() -> bool {
+    read_state(|s| s.schema_version < latest_schema_version())
+}
+
+/// Schedules the next batch if any migration is pending. Called from `post_upgrade`
+/// and by the admin `resume_migrations` endpoint after a failure.
+pub fn start() {
+    mutate_state(|s| s.last_error = None);
+    if is_migrating() {
+        schedule_next_batch();
+    }
+}
+
+fn schedule_next_batch() {
+    ic_cdk_timers::set_timer(Duration::ZERO, run_next_batch);
+}
+
+fn run_next_batch() {
+    if run_batch(MIGRATIONS, MIGRATION_BATCH_SIZE) {
+        schedule_next_batch();
+    }
+}
+
+/// Runs one batch of the next migration in `migrations` and returns whether another
+/// batch is pending.
+fn run_batch(migrations: &[Migration], batch_size: usize) -> bool {
+    let schema_version = read_state(|s| s.schema_version);
+    let Some(migration) = migrations.iter().find(|m| m.version == schema_version + 1) else {
+        return false;
+    };
+
+    let running = read_state(|s| s.running.clone())
+        .filter(|r| r.version == migration.version)
+        .unwrap_or_else(|| RunningMigration {
+            version: migration.version,
+            target: migration.target,
+            description: migration.description.to_string(),
+            cursor: None,
+            processed: 0,
+            started_at: crate::time::get_current_time(),
+        });
+
+    match (migration.run_batch)(running.cursor.clone(), batch_size) {
+        Ok(progress) => {
+            let processed = running.processed + progress.processed;
+            if progress.done {
+                log!(
+                    Priority::Info,
+                    "Migration v{} complete ({} records): {}",
+                    migration.version,
+                    processed,
+                    migration.description
+                );
+                mutate_state(|s| {
+                    s.schema_version = migration.version;
+                    s.running = None;
+                    s.last_completed_at = Some(crate::time::get_current_time());
+                });
+            } else {
+                mutate_state(|s| {
+                    s.running = Some(RunningMigration {
+                        cursor: progress.next_cursor,
+                        processed,
+                        ..running
+                    });
+                });
+            }
+            let latest = migrations.last().map_or(0, |m| m.version);
+            read_state(|s| s.schema_version) < latest
+        }
+        Err(error) => {
+            log!(Priority::Info, "Migration v{} failed: {}", migration.version, error);
+            mutate_state(|s| {
+                s.running = Some(running);
+                s.last_error = Some(error);
+            });
+            false
+        }
+    }
+}
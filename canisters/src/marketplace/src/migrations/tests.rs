@@ -0,0 +1,119 @@
+use super::*;
+use crate::time::get_current_time;
+
+const RECORDS: usize = 5;
+
+/// Walks `RECORDS` numbered records; the cursor is the number of records done.
+fn walk_records(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    let start: usize = cursor.map_or(0, |cursor| cursor.parse().unwrap());
+    let end = (start + limit).min(RECORDS);
+    Ok(BatchProgress {
+        processed: (end - start) as u64,
+        next_cursor: Some(end.to_string()),
+        done: end == RECORDS,
+    })
+}
+
+fn nothing_to_do(_cursor: Option<String>, _limit: usize) -> Result<BatchProgress, String> {
+    Ok(BatchProgress {
+        processed: 0,
+        next_cursor: None,
+        done: true,
+    })
+}
+
+fn corrupt_store(_cursor: Option<String>, _limit: usize) -> Result<BatchProgress, String> {
+    Err("record 3 does not decode".to_string())
+}
+
+const fn migration(
+    version: u32,
+    run_batch: fn(Option<String>, usize) -> Result<BatchProgress, String>,
+) -> Migration {
+    Migration {
+        version,
+        target: MigrationTarget::Listing,
+        description: "test migration",
+        run_batch,
+    }
+}
+
+const TWO_MIGRATIONS: &[Migration] = &[migration(1, walk_records), migration(2, nothing_to_do)];
+
+const FAILING_SECOND: &[Migration] = &[migration(1, nothing_to_do), migration(2, corrupt_store)];
+
+fn running() -> Option<RunningMigration> {
+    read_state(|s| s.running.clone())
+}
+
+mod run_batch {
+    use super::*;
+
+    #[test]
+    fn should_keep_the_cursor_between_batches() {
+        assert!(run_batch(TWO_MIGRATIONS, 2));
+
+        let first = running().unwrap();
+        assert_eq!(first.version, 1);
+        assert_eq!(first.cursor.as_deref(), Some("2"));
+        assert_eq!(first.processed, 2);
+        assert_eq!(schema_version(), 0);
+
+        assert!(run_batch(TWO_MIGRATIONS, 2));
+
+        let second = running().unwrap();
+        assert_eq!(second.cursor.as_deref(), Some("4"));
+        assert_eq!(second.processed, 4);
+        assert_eq!(second.started_at, first.started_at);
+    }
+
+    #[test]
+    fn should_bump_the_schema_version_when_a_migration_completes() {
+        assert!(run_batch(TWO_MIGRATIONS, RECORDS));
+
+        assert_eq!(schema_version(), 1);
+        assert_eq!(running(), None);
+        assert_eq!(read_state(|s| s.last_completed_at), Some(get_current_time()));
+    }
+
+    #[test]
+    fn should_run_each_pending_migration_in_turn() {
+        assert!(run_batch(TWO_MIGRATIONS, RECORDS));
+        assert!(!run_batch(TWO_MIGRATIONS, RECORDS));
+
+        assert_eq!(schema_version(), 2);
+        assert!(!run_batch(TWO_MIGRATIONS, RECORDS));
+        assert_eq!(schema_version(), 2);
+    }
+
+    #[test]
+    fn should_stop_at_a_failing_batch() {
+        assert!(run_batch(FAILING_SECOND, RECORDS));
+        assert!(!run_batch(FAILING_SECOND, RECORDS));
+
+        assert_eq!(schema_version(), 1);
+        assert_eq!(running().map(|r| r.version), Some(2));
+        assert_eq!(read_state(|s| s.last_error.clone()), Some("record 3 does not decode".to_string()));
+    }
+
+    #[test]
+    fn should_not_run_anything_once_up_to_date() {
+        init_schema_version();
+
+        assert!(!run_batch(MIGRATIONS, RECORDS));
+        assert!(!is_migrating());
+        assert_eq!(running(), None);
+    }
+}
+
+mod registry {
+    use super::*;
+
+    #[test]
+    fn should_number_migrations_consecutively_from_one() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as u32 + 1, "{}", migration.description);
+        }
+        assert_eq!(latest_schema_version(), MIGRATIONS.len() as u32);
+    }
+}
@@ -0,0 +1,272 @@
+//! ICRC-3 block log. Listing, offer and sale events from the activity log are
+//! mirrored here as hash-chained ICRC-3 blocks; collection events are not.
+
+use candid::{Nat, Principal};
+use ic_stable_structures::{StableBTreeMap, Storable, storable::Bound};
+use icrc_ledger_types::icrc::generic_value::ICRC3Value;
+use icrc_ledger_types::icrc3::blocks::{BlockWithId, GetBlocksRequest, GetBlocksResult, SupportedBlockType};
+use serde_bytes::ByteBuf;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use crate::migrations::BatchProgress;
+use crate::types::{ActivityEvent, MarketplaceEvent};
+use super::memory::{get_memory, BLOCKS_MEMORY_ID};
+
+#[cfg(test)]
+mod tests;
+
+/// Schema version whose migration backfills blocks for events recorded before the
+/// block log existed. Until it has run, new events are left to the backfill.
+pub const BLOCKS_SCHEMA_VERSION: u32 = 2;
+
+/// Upper bound on blocks returned by one `icrc3_get_blocks` call.
+pub const MAX_BLOCKS_PER_RESPONSE: u64 = 100;
+
+/// Path of the block type schema (`docs/icrc3-block-types.md`) on the HTTP gateway.
+pub const BLOCK_TYPE_DOCS_PATH: &str = "/icrc3/block-types";
+
+pub const BLOCK_TYPES: &[&str] = &[
+    "mkt_list",
+    "mkt_reprice",
+    "mkt_cancel",
+    "mkt_list_status",
+    "mkt_expire",
+    "mkt_sale",
+    "mkt_offer",
+    "mkt_offer_status",
+    "mkt_offer_expire",
+];
+
+pub struct StoredBlock(pub ICRC3Value);
+
+impl Storable for StoredBlock {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(&self.0).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StoredBlock(candid::decode_one(&bytes).unwrap())
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        candid::encode_one(&self.0).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+thread_local! {
+    // Block index -> block
+    static BLOCKS: RefCell<StableBTreeMap<u64, StoredBlock, super::memory::Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(BLOCKS_MEMORY_ID)));
+}
+
+pub fn blocks_enabled() -> bool {
+    crate::migrations::schema_version() >= BLOCKS_SCHEMA_VERSION
+}
+
+fn text(value: &str) -> ICRC3Value {
+    ICRC3Value::Text(value.to_string())
+}
+
+fn principal(value: &Principal) -> ICRC3Value {
+    ICRC3Value::Blob(ByteBuf::from(value.as_slice().to_vec()))
+}
+
+fn nat(value: u64) -> ICRC3Value {
+    ICRC3Value::Nat(Nat::from(value))
+}
+
+/// The block type and `tx` map for an event, or `None` for events that are not
+/// trading transitions.
+fn block_tx(event: &MarketplaceEvent) -> Option<(&'static str, BTreeMap<String, ICRC3Value>)> {
+    let mut tx = BTreeMap::new();
+    let btype = match event {
+        MarketplaceEvent::ItemListed { listing_id, collection_id, nft_id, seller, price, currency } => {
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("nft_id".to_string(), text(nft_id));
+            tx.insert("seller".to_string(), principal(seller));
+            tx.insert("price".to_string(), nat(*price));
+            tx.insert("currency".to_string(), text(currency));
+            "mkt_list"
+        }
+        MarketplaceEvent::ListingPriceChanged { listing_id, collection_id, nft_id, seller, old_price, new_price } => {
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("nft_id".to_string(), text(nft_id));
+            tx.insert("seller".to_string(), principal(seller));
+            tx.insert("old_price".to_string(), nat(*old_price));
+            tx.insert("price".to_string(), nat(*new_price));
+            "mkt_reprice"
+        }
+        MarketplaceEvent::ListingCancelled { listing_id, collection_id, nft_id, seller }
+        | MarketplaceEvent::ListingRemoved { listing_id, collection_id, nft_id, seller } => {
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("nft_id".to_string(), text(nft_id));
+            tx.insert("seller".to_string(), principal(seller));
+            "mkt_cancel"
+        }
+        MarketplaceEvent::ListingStatusChanged { listing_id, collection_id, nft_id, seller, from, to } => {
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("nft_id".to_string(), text(nft_id));
+            tx.insert("seller".to_string(), principal(seller));
+            tx.insert("from".to_string(), text(&format!("{:?}", from)));
+            tx.insert("to".to_string(), text(&format!("{:?}", to)));
+            "mkt_list_status"
+        }
+        MarketplaceEvent::ListingExpired { listing_id, collection_id, nft_id, seller } => {
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("nft_id".to_string(), text(nft_id));
+            tx.insert("seller".to_string(), principal(seller));
+            "mkt_expire"
+        }
+        MarketplaceEvent::ItemSold {
+            sale_id, listing_id, collection_id, nft_id, seller, buyer, price, currency, tx_signature,
+        } => {
+            tx.insert("sale_id".to_string(), text(sale_id));
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("nft_id".to_string(), text(nft_id));
+            tx.insert("seller".to_string(), principal(seller));
+            tx.insert("buyer".to_string(), principal(buyer));
+            tx.insert("price".to_string(), nat(*price));
+            tx.insert("currency".to_string(), text(currency));
+            tx.insert("tx_signature".to_string(), text(tx_signature));
+            "mkt_sale"
+        }
+        MarketplaceEvent::OfferMade { offer_id, listing_id, collection_id, bidder, price, currency } => {
+            tx.insert("offer_id".to_string(), text(offer_id));
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("bidder".to_string(), principal(bidder));
+            tx.insert("price".to_string(), nat(*price));
+            tx.insert("currency".to_string(), text(currency));
+            "mkt_offer"
+        }
+        MarketplaceEvent::OfferStatusChanged { offer_id, listing_id, collection_id, bidder, status } => {
+            tx.insert("offer_id".to_string(), text(offer_id));
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("bidder".to_string(), principal(bidder));
+            tx.insert("status".to_string(), text(&format!("{:?}", status)));
+            "mkt_offer_status"
+        }
+        MarketplaceEvent::OfferExpired { offer_id, listing_id, collection_id, bidder } => {
+            tx.insert("offer_id".to_string(), text(offer_id));
+            tx.insert("listing_id".to_string(), text(listing_id));
+            tx.insert("collection_id".to_string(), text(collection_id));
+            tx.insert("bidder".to_string(), principal(bidder));
+            "mkt_offer_expire"
+        }
+        MarketplaceEvent::CollectionCreated { .. }
+        | MarketplaceEvent::CollectionStatusChanged { .. }
+        | MarketplaceEvent::SolanaStageUpdated { .. } => return None,
+    };
+    Some((btype, tx))
+}
+
+/// Index and hash of the latest block.
+pub fn get_block_tip() -> Option<(u64, [u8; 32])> {
+    BLOCKS.with(|b| {
+        b.borrow()
+            .last_key_value()
+            .map(|(index, block)| (index, block.0.hash()))
+    })
+}
+
+/// Appends the event's block, chained to the current tip. Returns the block index.
+pub fn append_event_block(event: &ActivityEvent) -> Option<u64> {
+    let (btype, tx) = block_tx(&event.event)?;
+    let tip = get_block_tip();
+
+    let mut block = BTreeMap::new();
+    block.insert("btype".to_string(), text(btype));
+    block.insert("ts".to_string(), nat(event.timestamp));
+    block.insert("activity_id".to_string(), nat(event.id));
+    block.insert("tx".to_string(), ICRC3Value::Map(tx));
+    if let Some((_, parent_hash)) = tip {
+        block.insert("phash".to_string(), ICRC3Value::Blob(ByteBuf::from(parent_hash.to_vec())));
+    }
+
+    let index = tip.map_or(0, |(index, _)| index + 1);
+    BLOCKS.with(|b| {
+        b.borrow_mut().insert(index, StoredBlock(ICRC3Value::Map(block)));
+    });
+    Some(index)
+}
+
+pub fn block_count() -> u64 {
+    BLOCKS.with(|b| b.borrow().len())
+}
+
+fn nat_to_u64(value: &Nat) -> u64 {
+    u64::try_from(&value.0).unwrap_or(u64::MAX)
+}
+
+pub fn get_blocks(requests: Vec<GetBlocksRequest>) -> GetBlocksResult {
+    let mut remaining = MAX_BLOCKS_PER_RESPONSE;
+    let mut blocks = Vec::new();
+
+    BLOCKS.with(|b| {
+        let stored = b.borrow();
+        for request in requests {
+            let start = nat_to_u64(&request.start);
+            let length = nat_to_u64(&request.length).min(remaining);
+            for entry in stored.range(start..start.saturating_add(length)) {
+                blocks.push(BlockWithId {
+                    id: Nat::from(*entry.key()),
+                    block: entry.value().0,
+                });
+            }
+            remaining -= length;
+        }
+    });
+
+    GetBlocksResult {
+        log_length: Nat::from(block_count()),
+        blocks,
+        archived_blocks: vec![],
+    }
+}
+
+/// Each block type with a link to its section of the schema served by this canister.
+pub fn supported_block_types() -> Vec<SupportedBlockType> {
+    let docs_url = format!("https://{}.raw.icp0.io{}", ic_cdk::api::canister_self(), BLOCK_TYPE_DOCS_PATH);
+    BLOCK_TYPES
+        .iter()
+        .map(|btype| SupportedBlockType {
+            block_type: btype.to_string(),
+            url: format!("{}#{}", docs_url, btype),
+        })
+        .collect()
+}
+
+/// Migration step: appends blocks for activity events recorded before the block
+/// log existed. The cursor is the next event id to process.
+pub fn backfill_blocks_batch(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    let start = match cursor {
+        Some(cursor) => cursor.parse::<u64>().map_err(|e| format!("Invalid cursor {}: {}", cursor, e))?,
+        None => 0,
+    };
+
+    let events = super::events::get_events_from(start, limit);
+    for event in events.iter() {
+        append_event_block(event);
+    }
+
+    let next = start + events.len() as u64;
+    let done = next >= super::events::event_count();
+    if done {
+        crate::certification::update_certified_data();
+    }
+    Ok(BatchProgress {
+        processed: events.len() as u64,
+        next_cursor: Some(next.to_string()),
+        done,
+    })
+}
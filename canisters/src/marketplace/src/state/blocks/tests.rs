@@ -0,0 +1,159 @@
+use super::*;
+use crate::migrations::init_schema_version;
+use crate::state::events::record_event;
+use crate::state::fixtures;
+use crate::time::get_current_time;
+use crate::types::Blockchain;
+
+fn listed(listing_id: &str) -> MarketplaceEvent {
+    MarketplaceEvent::ItemListed {
+        listing_id: listing_id.to_string(),
+        collection_id: "collection".to_string(),
+        nft_id: "nft".to_string(),
+        seller: fixtures::principal(2),
+        price: 1_000,
+        currency: "ICP".to_string(),
+    }
+}
+
+fn collection_created() -> MarketplaceEvent {
+    MarketplaceEvent::CollectionCreated {
+        collection_id: "collection".to_string(),
+        creator: fixtures::principal(1),
+        name: "Collection".to_string(),
+        blockchain: Blockchain::ICP,
+    }
+}
+
+fn block(index: u64) -> ICRC3Value {
+    BLOCKS.with(|b| b.borrow().get(&index)).unwrap().0
+}
+
+fn field(value: &ICRC3Value, key: &str) -> Option<ICRC3Value> {
+    match value {
+        ICRC3Value::Map(map) => map.get(key).cloned(),
+        _ => None,
+    }
+}
+
+fn request(start: u64, length: u64) -> GetBlocksRequest {
+    GetBlocksRequest {
+        start: Nat::from(start),
+        length: Nat::from(length),
+    }
+}
+
+fn block_ids(result: &GetBlocksResult) -> Vec<u64> {
+    result.blocks.iter().map(|block| nat_to_u64(&block.id)).collect()
+}
+
+mod append {
+    use super::*;
+
+    #[test]
+    fn should_leave_events_to_the_backfill_until_it_ran() {
+        record_event(listed("a"));
+
+        assert_eq!(block_count(), 0);
+        assert_eq!(get_block_tip(), None);
+    }
+
+    #[test]
+    fn should_chain_each_block_to_its_parent() {
+        init_schema_version();
+        for id in ["a", "b", "c"] {
+            record_event(listed(id));
+        }
+
+        assert_eq!(block_count(), 3);
+        assert_eq!(field(&block(0), "phash"), None);
+        for index in 1..3 {
+            let parent_hash = block(index - 1).hash().to_vec();
+            assert_eq!(field(&block(index), "phash"), Some(ICRC3Value::Blob(ByteBuf::from(parent_hash))));
+        }
+        assert_eq!(get_block_tip(), Some((2, block(2).hash())));
+    }
+
+    #[test]
+    fn should_describe_the_event() {
+        init_schema_version();
+        let activity_id = record_event(listed("a"));
+
+        let block = block(0);
+        let tx = field(&block, "tx").unwrap();
+        assert_eq!(field(&block, "btype"), Some(text("mkt_list")));
+        assert_eq!(field(&block, "ts"), Some(nat(get_current_time())));
+        assert_eq!(field(&block, "activity_id"), Some(nat(activity_id)));
+        assert_eq!(field(&tx, "listing_id"), Some(text("a")));
+        assert_eq!(field(&tx, "seller"), Some(principal(&fixtures::principal(2))));
+        assert_eq!(field(&tx, "price"), Some(nat(1_000)));
+    }
+
+    #[test]
+    fn should_not_add_blocks_for_collection_events() {
+        init_schema_version();
+
+        record_event(collection_created());
+
+        assert_eq!(block_count(), 0);
+    }
+}
+
+mod get_blocks {
+    use super::*;
+
+    #[test]
+    fn should_serve_each_requested_range() {
+        init_schema_version();
+        for i in 0..5 {
+            record_event(listed(&i.to_string()));
+        }
+
+        let result = get_blocks(vec![request(0, 2), request(3, 10)]);
+
+        assert_eq!(block_ids(&result), vec![0, 1, 3, 4]);
+        assert_eq!(result.log_length, Nat::from(5_u64));
+        assert!(result.archived_blocks.is_empty());
+    }
+
+    #[test]
+    fn should_cap_the_blocks_per_response() {
+        init_schema_version();
+        for i in 0..MAX_BLOCKS_PER_RESPONSE + 1 {
+            record_event(listed(&i.to_string()));
+        }
+
+        let result = get_blocks(vec![request(0, MAX_BLOCKS_PER_RESPONSE), request(MAX_BLOCKS_PER_RESPONSE, 1)]);
+
+        assert_eq!(result.blocks.len() as u64, MAX_BLOCKS_PER_RESPONSE);
+    }
+}
+
+mod backfill {
+    use super::*;
+
+    #[test]
+    fn should_append_blocks_for_earlier_events_in_batches() {
+        record_event(collection_created());
+        record_event(listed("a"));
+        record_event(listed("b"));
+
+        let first = backfill_blocks_batch(None, 2).unwrap();
+        assert_eq!(first.processed, 2);
+        assert_eq!(first.next_cursor.as_deref(), Some("2"));
+        assert!(!first.done);
+
+        let second = backfill_blocks_batch(first.next_cursor, 2).unwrap();
+        assert_eq!(second.processed, 1);
+        assert!(second.done);
+
+        assert_eq!(block_count(), 2);
+        assert_eq!(field(&block(0), "activity_id"), Some(nat(1)));
+        assert_eq!(field(&block(1), "activity_id"), Some(nat(2)));
+    }
+
+    #[test]
+    fn should_reject_a_malformed_cursor() {
+        assert!(backfill_blocks_batch(Some("two".to_string()), 2).is_err());
+    }
+}
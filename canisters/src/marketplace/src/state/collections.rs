@@ -1,14 +1,21 @@
-use ic_cdk::{query, update, api::msg_caller};
 use ic_stable_structures::StableBTreeMap;
 use canister_uuid::get_uuid;
 use std::cell::RefCell;
 use crate::types::{
-    Blockchain, ChainData, ChainDataV0, Collection, CollectionStatus, CollectionV0, CreateCollectionArgs, SolanaCollectionData, UpdateCollectionStatusArgs, UpdateSolanaStageArgs
+    Blockchain, CandyGuardConfig, CandyMachineState, ChainData, Collection, CollectionStatus, CollectionV0,
+    CreateCollectionArgs, Entity, MarketplaceError, MarketplaceEvent, MarketplaceResult,
+    UpdateCollectionStatusArgs, UpdateSolanaStageArgs,
 };
-use super::memory::{ get_memory, COLLECTIONS_MEMORY_ID, COLLECTIONS_MEMORY_ID_OLD };
-use super::config::get_admin;
+use super::memory::{
+    get_memory, Memory, COLLECTIONS_BY_BLOCKCHAIN_MEMORY_ID, COLLECTIONS_BY_CREATOR_MEMORY_ID,
+    COLLECTIONS_BY_STATUS_MEMORY_ID, COLLECTIONS_MEMORY_ID, COLLECTIONS_MEMORY_ID_OLD,
+};
+use crate::migrations::BatchProgress;
 use candid::Principal;
 
+#[cfg(test)]
+mod tests;
+
 thread_local! {
     static COLLECTIONS_OLD: RefCell<
         StableBTreeMap<String, CollectionV0, super::memory::Memory>
@@ -17,97 +24,149 @@ thread_local! {
     static COLLECTIONS: RefCell<
         StableBTreeMap<String, Collection, super::memory::Memory>
     > = RefCell::new(StableBTreeMap::init(get_memory(COLLECTIONS_MEMORY_ID)));
+
+    // Secondary indexes, keyed "<value>:<collection_id>"
+    static COLLECTIONS_BY_CREATOR: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(COLLECTIONS_BY_CREATOR_MEMORY_ID)));
+
+    static COLLECTIONS_BY_BLOCKCHAIN: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(COLLECTIONS_BY_BLOCKCHAIN_MEMORY_ID)));
+
+    static COLLECTIONS_BY_STATUS: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(COLLECTIONS_BY_STATUS_MEMORY_ID)));
 }
 
-#[update]
-pub async fn migrate_collections() {
-    COLLECTIONS_OLD.with(|old_collections| {
-        COLLECTIONS.with(|new_collections| {
-            let old = old_collections.borrow_mut();
-            let mut new = new_collections.borrow_mut();
-
-            for entry in old.iter() {
-                let old_collection = entry.value();
-
-                let chain_data = match &old_collection.chain_data {
-                    ChainDataV0::Solana(data) =>
-                        ChainData::Solana(SolanaCollectionData {
-                            deployment_stage: data.deployment_stage.clone(),
-                            candy_machine_address: data.candy_machine_address.clone(),
-                            collection_mint: data.collection_mint.clone(),
-                            candy_machine_authority: data.candy_machine_authority.clone(),
-                            manifest_url: data.manifest_url.clone(),
-                            files_uploaded: data.files_uploaded,
-                            metadata_created: data.metadata_created,
-                            candy_machine_items_uploaded: false,
-                            candy_machine_config: data.candy_machine_config.clone(),
-                        }),
-                    ChainDataV0::ICP(data) => ChainData::ICP(data.clone()),
-                    ChainDataV0::Ethereum(data) => ChainData::Ethereum(data.clone()),
-                    ChainDataV0::Bitcoin(data) => ChainData::Bitcoin(data.clone()),
-                };
-                let new_collection = Collection {
-                    id: old_collection.id.clone(),
-                    blockchain: old_collection.blockchain.clone(),
-                    creator: old_collection.creator.clone(),
-                    name: old_collection.name.clone(),
-                    symbol: old_collection.symbol.clone(),
-                    description: old_collection.description.clone(),
-                    image_url: old_collection.image_url.clone(),
-                    banner_url: old_collection.banner_url.clone(),
-                    total_supply: old_collection.total_supply,
-                    floor_price: old_collection.floor_price,
-                    total_volume: old_collection.total_volume,
-                    owner_count: old_collection.owner_count,
-                    listed_count: old_collection.listed_count,
-                    royalty_bps: old_collection.royalty_bps,
-                    metadata: old_collection.metadata.clone(),
-                    status: old_collection.status.clone(),
-                    chain_data,
-                    created_at: old_collection.created_at,
-                    updated_at: old_collection.updated_at,
-                };
-
-                new.insert(new_collection.id.clone(), new_collection);
-            }
-        });
+fn creator_index_key(creator: &Principal, collection_id: &str) -> String {
+    format!("{}:{}", creator.to_text(), collection_id)
+}
+
+fn blockchain_index_key(blockchain: &Blockchain, collection_id: &str) -> String {
+    format!("{}:{}", blockchain.as_str(), collection_id)
+}
+
+fn status_index_key(status: &CollectionStatus, collection_id: &str) -> String {
+    format!("{:?}:{}", status, collection_id)
+}
+
+fn index_collection(collection: &Collection) {
+    crate::certification::certify_collection(collection);
+    COLLECTIONS_BY_CREATOR.with(|i| {
+        i.borrow_mut().insert(creator_index_key(&collection.creator, &collection.id), ());
+    });
+    COLLECTIONS_BY_BLOCKCHAIN.with(|i| {
+        i.borrow_mut().insert(blockchain_index_key(&collection.blockchain, &collection.id), ());
+    });
+    COLLECTIONS_BY_STATUS.with(|i| {
+        i.borrow_mut().insert(status_index_key(&collection.status, &collection.id), ());
     });
 }
 
-#[update]
-pub fn clear_old_collections() -> Result<(), String> {
-    let caller = msg_caller();
-    let admin = get_admin();
+fn unindex_collection(collection: &Collection) {
+    COLLECTIONS_BY_CREATOR.with(|i| {
+        i.borrow_mut().remove(&creator_index_key(&collection.creator, &collection.id));
+    });
+    COLLECTIONS_BY_BLOCKCHAIN.with(|i| {
+        i.borrow_mut().remove(&blockchain_index_key(&collection.blockchain, &collection.id));
+    });
+    COLLECTIONS_BY_STATUS.with(|i| {
+        i.borrow_mut().remove(&status_index_key(&collection.status, &collection.id));
+    });
+}
 
-    if caller != admin {
-        return Err("Only admin can clear old collections".to_string());
-    }
+/// Collection ids under `prefix` in an index, paginated.
+fn index_page(
+    index: &'static std::thread::LocalKey<RefCell<StableBTreeMap<String, (), Memory>>>,
+    prefix: &str,
+    page: u32,
+    limit: u32,
+) -> Vec<String> {
+    let prefix = format!("{}:", prefix);
+    index.with(|i| {
+        i.borrow()
+            .range(prefix.clone()..)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .skip(page.saturating_mul(limit) as usize)
+            .take(limit as usize)
+            .map(|entry| entry.key()[prefix.len()..].to_string())
+            .collect()
+    })
+}
+
+fn collections_by_ids(ids: Vec<String>) -> Vec<Collection> {
+    COLLECTIONS.with(|c| {
+        let collections = c.borrow();
+        ids.into_iter().filter_map(|id| collections.get(&id)).collect()
+    })
+}
 
-    COLLECTIONS_OLD.with(|old_collections| {
-        let mut collections = old_collections.borrow_mut();
-        let keys: Vec<String> = collections.iter().map(|entry| entry.key().clone()).collect();
-        for key in keys {
-            collections.remove(&key);
+/// Migration step: rebuilds the secondary indexes, `limit` collections per batch. The
+/// first batch clears them; the cursor is the last collection id indexed.
+pub fn reindex_collections_batch(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    if cursor.is_none() {
+        for index in [&COLLECTIONS_BY_CREATOR, &COLLECTIONS_BY_BLOCKCHAIN, &COLLECTIONS_BY_STATUS] {
+            index.with(|i| i.borrow_mut().clear_new());
         }
-    });
+    }
+
+    let collections = COLLECTIONS.with(|c| super::entries_after(&c.borrow(), cursor, limit));
+    for (_, collection) in collections.iter() {
+        index_collection(collection);
+    }
+    Ok(super::batch_progress(&collections, limit))
+}
 
-    Ok(())
+/// Up to `limit` collections after the collection id `cursor`, with their ids.
+pub fn collections_after(cursor: Option<String>, limit: usize) -> Vec<(String, Collection)> {
+    COLLECTIONS.with(|c| super::entries_after(&c.borrow(), cursor, limit))
 }
 
-#[query]
-pub fn get_old_collections_count() -> u64 {
-    COLLECTIONS_OLD.with(|old_collections| {
-        old_collections.borrow().len()
+/// Migration step: moves up to `limit` collections from the V0 layout into the current
+/// map. Entries already present in the current map are kept as they are.
+pub fn migrate_v0_collections_batch(
+    _cursor: Option<String>,
+    limit: usize,
+) -> Result<BatchProgress, String> {
+    let old_collections: Vec<CollectionV0> = COLLECTIONS_OLD.with(|old| {
+        old.borrow().iter().take(limit).map(|entry| entry.value()).collect()
+    });
+
+    for old_collection in old_collections.iter() {
+        let id = old_collection.id.clone();
+        if get_collection(&id).is_none() {
+            let collection = Collection::from(old_collection.clone());
+            index_collection(&collection);
+            COLLECTIONS.with(|c| {
+                c.borrow_mut().insert(id.clone(), collection);
+            });
+        }
+        COLLECTIONS_OLD.with(|old| {
+            old.borrow_mut().remove(&id);
+        });
+    }
+
+    let remaining = COLLECTIONS_OLD.with(|old| old.borrow().len());
+    Ok(BatchProgress {
+        processed: old_collections.len() as u64,
+        next_cursor: None,
+        done: remaining == 0,
     })
 }
 
+pub fn for_each_collection(mut f: impl FnMut(&Collection)) {
+    COLLECTIONS.with(|c| {
+        for entry in c.borrow().iter() {
+            f(&entry.value());
+        }
+    });
+}
+
 pub async fn add_collection(
     args: CreateCollectionArgs,
     creator: Principal
-) -> Result<String, String> {
+) -> MarketplaceResult<String> {
     let collection_id = get_uuid().await;
 
-    let collection = Collection {
+    insert_collection(Collection {
         id: collection_id.clone(),
         blockchain: args.blockchain,
         creator,
@@ -125,15 +184,27 @@ pub async fn add_collection(
         metadata: args.metadata,
         status: CollectionStatus::Draft,
         chain_data: args.chain_data,
-        created_at: ic_cdk::api::time(),
-        updated_at: ic_cdk::api::time(),
+        created_at: crate::time::get_current_time(),
+        updated_at: crate::time::get_current_time(),
+    });
+
+    Ok(collection_id)
+}
+
+/// Stores a new collection and records that it was created.
+pub(crate) fn insert_collection(collection: Collection) {
+    let event = MarketplaceEvent::CollectionCreated {
+        collection_id: collection.id.clone(),
+        creator: collection.creator,
+        name: collection.name.clone(),
+        blockchain: collection.blockchain.clone(),
     };
 
+    index_collection(&collection);
     COLLECTIONS.with(|c| {
-        c.borrow_mut().insert(collection_id.clone(), collection);
+        c.borrow_mut().insert(collection.id.clone(), collection);
     });
-
-    Ok(collection_id)
+    super::events::record_event(event);
 }
 
 pub fn get_collection(collection_id: &str) -> Option<Collection> {
@@ -144,7 +215,7 @@ pub fn get_all_collections(page: u32, limit: u32) -> Vec<Collection> {
     COLLECTIONS.with(|c| {
         c.borrow()
             .iter()
-            .skip((page * limit) as usize)
+            .skip(page.saturating_mul(limit) as usize)
             .take(limit as usize)
             .map(|entry| entry.value())
             .collect()
@@ -156,15 +227,7 @@ pub fn get_collections_by_blockchain(
     page: u32,
     limit: u32
 ) -> Vec<Collection> {
-    COLLECTIONS.with(|c| {
-        c.borrow()
-            .iter()
-            .filter(|entry| &entry.value().blockchain == blockchain)
-            .skip((page * limit) as usize)
-            .take(limit as usize)
-            .map(|entry| entry.value())
-            .collect()
-    })
+    collections_by_ids(index_page(&COLLECTIONS_BY_BLOCKCHAIN, blockchain.as_str(), page, limit))
 }
 
 pub fn update_collection_stats(
@@ -173,7 +236,7 @@ pub fn update_collection_stats(
     total_volume: Option<u64>,
     owner_count: Option<u32>,
     listed_count: Option<u32>
-) -> Result<(), String> {
+) -> MarketplaceResult<()> {
     COLLECTIONS.with(|c| {
         let mut collections = c.borrow_mut();
 
@@ -190,32 +253,44 @@ pub fn update_collection_stats(
             if let Some(lc) = listed_count {
                 collection.listed_count = lc;
             }
-            collection.updated_at = ic_cdk::api::time();
+            collection.updated_at = crate::time::get_current_time();
 
             collections.insert(collection_id.to_string(), collection);
             Ok(())
         } else {
-            Err("Collection not found".to_string())
+            Err(MarketplaceError::not_found(Entity::Collection, collection_id))
         }
     })
 }
 
-pub fn update_collection_status(args: UpdateCollectionStatusArgs) -> Result<(), String> {
+pub fn update_collection_status(args: UpdateCollectionStatusArgs) -> MarketplaceResult<()> {
     COLLECTIONS.with(|c| {
         let mut collections = c.borrow_mut();
 
         if let Some(mut collection) = collections.get(&args.collection_id) {
+            let previous_status = collection.status.clone();
+            unindex_collection(&collection);
             collection.status = args.status;
-            collection.updated_at = ic_cdk::api::time();
+            collection.updated_at = crate::time::get_current_time();
+            index_collection(&collection);
+
+            if collection.status != previous_status {
+                super::events::record_event(MarketplaceEvent::CollectionStatusChanged {
+                    collection_id: args.collection_id.clone(),
+                    creator: collection.creator,
+                    from: previous_status,
+                    to: collection.status.clone(),
+                });
+            }
             collections.insert(args.collection_id, collection);
             Ok(())
         } else {
-            Err("Collection not found".to_string())
+            Err(MarketplaceError::not_found(Entity::Collection, args.collection_id))
         }
     })
 }
 
-pub fn update_solana_stage(args: UpdateSolanaStageArgs) -> Result<(), String> {
+pub fn update_solana_stage(args: UpdateSolanaStageArgs) -> MarketplaceResult<()> {
     COLLECTIONS.with(|c| {
         let mut collections = c.borrow_mut();
 
@@ -243,51 +318,82 @@ pub fn update_solana_stage(args: UpdateSolanaStageArgs) -> Result<(), String> {
                 if let Some(config) = args.candy_machine_config {
                     data.candy_machine_config = Some(config);
                 }
-                collection.updated_at = ic_cdk::api::time();
+                collection.updated_at = crate::time::get_current_time();
+
+                super::events::record_event(MarketplaceEvent::SolanaStageUpdated {
+                    collection_id: args.collection_id.clone(),
+                    creator: collection.creator,
+                    stage: data.deployment_stage.clone(),
+                });
+                crate::certification::certify_collection(&collection);
                 collections.insert(args.collection_id, collection);
                 Ok(())
             } else {
-                Err("Collection is not a Solana collection".to_string())
+                Err(MarketplaceError::invalid_state("Collection is not a Solana collection"))
             }
         } else {
-            Err("Collection not found".to_string())
+            Err(MarketplaceError::not_found(Entity::Collection, args.collection_id))
         }
     })
 }
 
-pub fn get_user_collections(creator: &Principal, page: u32, limit: u32) -> Vec<Collection> {
+/// Stores a candy machine read from chain and reconciles the fields it supersedes.
+/// The deployment stage is left alone.
+pub fn update_candy_machine_state(collection_id: &str, state: CandyMachineState) -> MarketplaceResult<()> {
     COLLECTIONS.with(|c| {
-        c.borrow()
-            .iter()
-            .filter(|entry| &entry.value().creator == creator)
-            .skip((page * limit) as usize)
-            .take(limit as usize)
-            .map(|entry| entry.value())
-            .collect()
+        let mut collections = c.borrow_mut();
+        let mut collection = collections
+            .get(&collection_id.to_string())
+            .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+        let ChainData::Solana(ref mut data) = collection.chain_data else {
+            return Err(MarketplaceError::invalid_state("Collection is not a Solana collection"));
+        };
+
+        data.candy_machine_items_uploaded = state.items_loaded >= state.items_available;
+        data.candy_machine_authority = Some(state.authority.clone());
+        data.collection_mint = Some(state.collection_mint.clone());
+        data.candy_machine_state = Some(state);
+        collection.updated_at = crate::time::get_current_time();
+
+        crate::certification::certify_collection(&collection);
+        collections.insert(collection_id.to_string(), collection);
+        Ok(())
     })
 }
 
-pub fn get_draft_collections(creator: &Principal) -> Vec<Collection> {
+/// Records the candy guard wrapping the collection's candy machine and the guards it enforces.
+pub fn update_candy_guard(collection_id: &str, address: String, config: CandyGuardConfig) -> MarketplaceResult<()> {
     COLLECTIONS.with(|c| {
-        c.borrow()
-            .iter()
-            .filter(|entry| {
-                let col = entry.value();
-                &col.creator == creator && col.status == CollectionStatus::Draft
-            })
-            .map(|entry| entry.value())
-            .collect()
+        let mut collections = c.borrow_mut();
+        let mut collection = collections
+            .get(&collection_id.to_string())
+            .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+        let ChainData::Solana(ref mut data) = collection.chain_data else {
+            return Err(MarketplaceError::invalid_state("Collection is not a Solana collection"));
+        };
+
+        data.candy_guard_address = Some(address);
+        data.candy_guard_config = Some(config);
+        collection.updated_at = crate::time::get_current_time();
+
+        crate::certification::certify_collection(&collection);
+        collections.insert(collection_id.to_string(), collection);
+        Ok(())
     })
 }
 
+pub fn get_user_collections(creator: &Principal, page: u32, limit: u32) -> Vec<Collection> {
+    collections_by_ids(index_page(&COLLECTIONS_BY_CREATOR, &creator.to_text(), page, limit))
+}
+
+pub fn get_draft_collections(creator: &Principal) -> Vec<Collection> {
+    collections_by_ids(index_page(&COLLECTIONS_BY_CREATOR, &creator.to_text(), 0, u32::MAX))
+        .into_iter()
+        .filter(|col| col.status == CollectionStatus::Draft)
+        .collect()
+}
+
 pub fn get_all_draft_collections(page: u32, limit: u32) -> Vec<Collection> {
-    COLLECTIONS.with(|c| {
-        c.borrow()
-            .iter()
-            .filter(|entry| entry.value().status == CollectionStatus::Draft)
-            .skip((page * limit) as usize)
-            .take(limit as usize)
-            .map(|entry| entry.value())
-            .collect()
-    })
+    let prefix = format!("{:?}", CollectionStatus::Draft);
+    collections_by_ids(index_page(&COLLECTIONS_BY_STATUS, &prefix, page, limit))
 }
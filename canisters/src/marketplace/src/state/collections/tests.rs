@@ -0,0 +1,128 @@
+use super::*;
+use crate::state::fixtures::{self, principal};
+
+fn stored(collection_id: &str, creator: Principal, blockchain: Blockchain, status: CollectionStatus) -> Collection {
+    let mut collection = fixtures::collection(collection_id);
+    collection.creator = creator;
+    collection.blockchain = blockchain;
+    collection.status = status;
+    insert_collection(collection.clone());
+    collection
+}
+
+fn ids(collections: Vec<Collection>) -> Vec<String> {
+    collections.into_iter().map(|collection| collection.id).collect()
+}
+
+mod queries {
+    use super::*;
+
+    #[test]
+    fn should_page_a_creators_collections() {
+        for id in ["a", "b", "c"] {
+            stored(id, principal(1), Blockchain::ICP, CollectionStatus::Active);
+        }
+        stored("d", principal(2), Blockchain::ICP, CollectionStatus::Active);
+
+        assert_eq!(ids(get_user_collections(&principal(1), 0, 2)), vec!["a", "b"]);
+        assert_eq!(ids(get_user_collections(&principal(1), 1, 2)), vec!["c"]);
+        assert!(get_user_collections(&principal(1), 2, 2).is_empty());
+        assert_eq!(ids(get_user_collections(&principal(2), 0, 10)), vec!["d"]);
+    }
+
+    #[test]
+    fn should_page_collections_by_blockchain() {
+        stored("a", principal(1), Blockchain::ICP, CollectionStatus::Active);
+        stored("b", principal(1), Blockchain::Solana, CollectionStatus::Active);
+        stored("c", principal(2), Blockchain::Solana, CollectionStatus::Active);
+
+        assert_eq!(
+            ids(get_collections_by_blockchain(&Blockchain::Solana, 0, 10)),
+            vec!["b", "c"]
+        );
+        assert_eq!(ids(get_collections_by_blockchain(&Blockchain::Solana, 1, 1)), vec!["c"]);
+        assert_eq!(ids(get_collections_by_blockchain(&Blockchain::ICP, 0, 10)), vec!["a"]);
+        assert!(get_collections_by_blockchain(&Blockchain::Ethereum, 0, 10).is_empty());
+    }
+
+    #[test]
+    fn should_find_drafts() {
+        stored("a", principal(1), Blockchain::ICP, CollectionStatus::Draft);
+        stored("b", principal(1), Blockchain::ICP, CollectionStatus::Active);
+        stored("c", principal(2), Blockchain::ICP, CollectionStatus::Draft);
+
+        assert_eq!(ids(get_draft_collections(&principal(1))), vec!["a"]);
+        assert_eq!(ids(get_all_draft_collections(0, 10)), vec!["a", "c"]);
+        assert_eq!(ids(get_all_draft_collections(1, 1)), vec!["c"]);
+    }
+
+    #[test]
+    fn should_move_a_collection_between_status_entries() {
+        stored("a", principal(1), Blockchain::ICP, CollectionStatus::Draft);
+
+        update_collection_status(UpdateCollectionStatusArgs {
+            collection_id: "a".to_string(),
+            status: CollectionStatus::Active,
+        })
+        .unwrap();
+
+        assert!(get_all_draft_collections(0, 10).is_empty());
+        assert!(get_draft_collections(&principal(1)).is_empty());
+        assert_eq!(ids(get_user_collections(&principal(1), 0, 10)), vec!["a"]);
+    }
+
+    #[test]
+    fn should_not_match_a_creator_by_prefix() {
+        let creator = Principal::from_slice(&[1; 10]);
+        let longer = Principal::from_slice(&[1; 11]);
+        stored("a", creator, Blockchain::ICP, CollectionStatus::Active);
+        stored("b", longer, Blockchain::ICP, CollectionStatus::Active);
+
+        assert_eq!(ids(get_user_collections(&creator, 0, 10)), vec!["a"]);
+    }
+}
+
+mod reindex {
+    use super::*;
+
+    /// Stores a collection without touching the secondary indexes.
+    fn stored_unindexed(collection: Collection) {
+        COLLECTIONS.with(|c| {
+            c.borrow_mut().insert(collection.id.clone(), collection);
+        });
+    }
+
+    #[test]
+    fn should_index_collections_in_batches() {
+        for id in ["a", "b", "c"] {
+            stored_unindexed(fixtures::collection(id));
+        }
+        assert!(get_user_collections(&principal(1), 0, 10).is_empty());
+
+        let first = reindex_collections_batch(None, 2).unwrap();
+        assert_eq!(first.processed, 2);
+        assert_eq!(first.next_cursor.as_deref(), Some("b"));
+        assert!(!first.done);
+        assert_eq!(ids(get_user_collections(&principal(1), 0, 10)), vec!["a", "b"]);
+
+        let second = reindex_collections_batch(first.next_cursor, 2).unwrap();
+        assert_eq!(second.processed, 1);
+        assert!(second.done);
+        assert_eq!(ids(get_user_collections(&principal(1), 0, 10)), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn should_drop_stale_entries_on_the_first_batch() {
+        stored("a", principal(1), Blockchain::ICP, CollectionStatus::Draft);
+        let mut active = get_collection("a").unwrap();
+        active.status = CollectionStatus::Active;
+        stored_unindexed(active);
+        assert_eq!(ids(get_all_draft_collections(0, 10)), vec!["a"]);
+
+        let progress = reindex_collections_batch(None, 10).unwrap();
+
+        assert!(progress.done);
+        assert!(get_all_draft_collections(0, 10).is_empty());
+        assert_eq!(ids(get_user_collections(&principal(1), 0, 10)), vec!["a"]);
+    }
+}
@@ -5,6 +5,7 @@ use candid::{CandidType, Decode, Encode, Principal};
 use serde::{Deserialize, Serialize};
 use sol_rpc_types::CommitmentLevel;
 use super::memory::{get_memory, CONFIG_MEMORY_ID};
+use crate::logs::Priority;
 
 thread_local! {
     static CONFIG: RefCell<StableCell<Config, super::memory::Memory>> = RefCell::new(
@@ -20,6 +21,13 @@ pub struct Config {
     pub solana_commitment_level: CommitmentLevel,
     pub ed25519_key_name: Ed25519KeyName,
     pub ed25519_public_key: Option<Ed25519ExtendedPublicKey>,
+    /// Most verbose log priority that is recorded. Defaults to `Info`.
+    pub log_verbosity: Option<Priority>,
+    /// Principal proposed by the admin; becomes admin once it calls `accept_admin`.
+    pub pending_admin: Option<Principal>,
+    /// How priority fees are chosen for canister-signed Solana transactions.
+    /// Defaults to [`PriorityFeePolicy::default`].
+    pub priority_fee_policy: Option<PriorityFeePolicy>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
@@ -44,6 +52,28 @@ pub struct Ed25519ExtendedPublicKey {
     pub chain_code: [u8; 32],
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PriorityFeePolicy {
+    /// Percentile, 0 to 100, of the recent prioritization fees paid to write the
+    /// transaction's accounts.
+    pub percentile: u8,
+    /// Cap on the compute unit price, in micro-lamports.
+    pub max_compute_unit_price: u64,
+    /// Compute unit price, in micro-lamports, when the recent prioritization fees
+    /// cannot be fetched. Defaults to the cap.
+    pub fallback_compute_unit_price: Option<u64>,
+}
+
+impl Default for PriorityFeePolicy {
+    fn default() -> Self {
+        Self {
+            percentile: 75,
+            max_compute_unit_price: 100_000,
+            fallback_compute_unit_price: None,
+        }
+    }
+}
+
 use sol_rpc_types::RpcEndpoint;
 
 impl Default for Config {
@@ -55,6 +85,9 @@ impl Default for Config {
             solana_commitment_level: CommitmentLevel::default(),
             ed25519_key_name: Ed25519KeyName::default(),
             ed25519_public_key: None,
+            log_verbosity: None,
+            pending_admin: None,
+            priority_fee_policy: None,
         }
     }
 }
@@ -81,6 +114,7 @@ pub fn init_config(
     solana_network: Option<SolanaNetwork>,
     ed25519_key_name: Option<Ed25519KeyName>,
     solana_commitment_level: Option<CommitmentLevel>,
+    log_verbosity: Option<Priority>,
 ) {
     CONFIG.with(|c| {
         c.borrow_mut().set(Config {
@@ -90,6 +124,9 @@ pub fn init_config(
             solana_commitment_level: solana_commitment_level.unwrap_or_default(),
             ed25519_key_name: ed25519_key_name.unwrap_or_default(),
             ed25519_public_key: None,
+            log_verbosity,
+            pending_admin: None,
+            priority_fee_policy: None,
         });
     });
 }
@@ -138,3 +175,62 @@ pub fn get_ed25519_public_key() -> Option<Ed25519ExtendedPublicKey> {
 pub fn set_ed25519_public_key(key: Ed25519ExtendedPublicKey) {
     mutate_config(|c| c.ed25519_public_key = Some(key));
 }
+
+pub fn get_log_verbosity() -> Priority {
+    read_config(|c| c.log_verbosity.unwrap_or(Priority::Info))
+}
+
+pub fn set_log_verbosity(verbosity: Priority) {
+    mutate_config(|c| c.log_verbosity = Some(verbosity));
+}
+
+pub fn set_sol_rpc_canister_id(canister_id: Option<Principal>) {
+    mutate_config(|c| c.sol_rpc_canister_id = canister_id);
+}
+
+pub fn set_solana_network(network: SolanaNetwork) {
+    mutate_config(|c| c.solana_network = network);
+}
+
+pub fn set_solana_commitment_level(commitment_level: CommitmentLevel) {
+    mutate_config(|c| c.solana_commitment_level = commitment_level);
+}
+
+/// The cached public key belongs to the old key, so it is dropped and fetched
+/// again on next use.
+pub fn set_ed25519_key_name(key_name: Ed25519KeyName) {
+    mutate_config(|c| {
+        if c.ed25519_key_name != key_name {
+            c.ed25519_key_name = key_name;
+            c.ed25519_public_key = None;
+        }
+    });
+}
+
+pub fn get_priority_fee_policy() -> PriorityFeePolicy {
+    read_config(|c| c.priority_fee_policy.unwrap_or_default())
+}
+
+pub fn set_priority_fee_policy(policy: PriorityFeePolicy) {
+    mutate_config(|c| c.priority_fee_policy = Some(policy));
+}
+
+pub fn get_pending_admin() -> Option<Principal> {
+    read_config(|c| c.pending_admin)
+}
+
+pub fn set_pending_admin(pending_admin: Option<Principal>) {
+    mutate_config(|c| c.pending_admin = pending_admin);
+}
+
+/// Makes the pending admin the admin. Returns the previous admin, or `None` if
+/// `principal` is not the pending admin.
+pub fn accept_pending_admin(principal: Principal) -> Option<Principal> {
+    mutate_config(|c| {
+        if c.pending_admin != Some(principal) {
+            return None;
+        }
+        c.pending_admin = None;
+        Some(std::mem::replace(&mut c.admin, principal))
+    })
+}
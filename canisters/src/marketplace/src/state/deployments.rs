@@ -0,0 +1,181 @@
+use ic_stable_structures::StableBTreeMap;
+use serde_bytes::ByteBuf;
+use std::cell::RefCell;
+use crate::types::{DeploymentStep, DeploymentTransaction, DeploymentTransactionStatus, PriorityFee};
+use super::memory::{
+    get_memory, Memory, DEPLOYMENT_TRANSACTIONS_BY_COLLECTION_MEMORY_ID,
+    DEPLOYMENT_TRANSACTIONS_MEMORY_ID, DEPOSIT_SIGNATURES_MEMORY_ID, LAMPORTS_DEPOSITED_MEMORY_ID,
+    LAMPORTS_SPENT_MEMORY_ID, PENDING_DEPLOYMENT_TRANSACTIONS_MEMORY_ID,
+};
+
+thread_local! {
+    static DEPLOYMENT_TRANSACTIONS: RefCell<StableBTreeMap<u64, DeploymentTransaction, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(DEPLOYMENT_TRANSACTIONS_MEMORY_ID)));
+
+    // "<collection id>:<zero-padded transaction id>"
+    static DEPLOYMENT_TRANSACTIONS_BY_COLLECTION: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(DEPLOYMENT_TRANSACTIONS_BY_COLLECTION_MEMORY_ID)));
+
+    // Ids of transactions that have not settled yet
+    static PENDING_DEPLOYMENT_TRANSACTIONS: RefCell<StableBTreeMap<u64, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(PENDING_DEPLOYMENT_TRANSACTIONS_MEMORY_ID)));
+
+    // Lamports the canister payer has committed to each collection's transactions
+    static LAMPORTS_SPENT: RefCell<StableBTreeMap<String, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LAMPORTS_SPENT_MEMORY_ID)));
+
+    // Lamports sent to the canister payer for each collection
+    static LAMPORTS_DEPOSITED: RefCell<StableBTreeMap<String, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LAMPORTS_DEPOSITED_MEMORY_ID)));
+
+    // Deposit transaction signature -> collection id, so a deposit is credited once
+    static DEPOSIT_SIGNATURES: RefCell<StableBTreeMap<String, String, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(DEPOSIT_SIGNATURES_MEMORY_ID)));
+}
+
+fn collection_key(collection_id: &str, id: u64) -> String {
+    format!("{}:{:020}", collection_id, id)
+}
+
+/// Starts tracking a transaction that was just sent. Returns its id.
+pub fn track_deployment_transaction(
+    collection_id: &str,
+    step: DeploymentStep,
+    signature: String,
+    message: Vec<u8>,
+    priority_fee: Option<PriorityFee>,
+    lamports_charged: u64,
+    last_valid_block_height: Option<u64>,
+) -> u64 {
+    let now = crate::time::get_current_time();
+    let id = DEPLOYMENT_TRANSACTIONS.with(|t| {
+        let mut transactions = t.borrow_mut();
+        let id = transactions.last_key_value().map_or(0, |(id, _)| id + 1);
+        transactions.insert(
+            id,
+            DeploymentTransaction {
+                id,
+                collection_id: collection_id.to_string(),
+                step,
+                status: DeploymentTransactionStatus::Pending,
+                signature,
+                replaced_signatures: vec![],
+                priority_fee,
+                lamports_charged: Some(lamports_charged),
+                created_at: now,
+                last_valid_block_height,
+                submitted_at: now,
+                updated_at: now,
+                message: Some(ByteBuf::from(message)),
+            },
+        );
+        id
+    });
+    DEPLOYMENT_TRANSACTIONS_BY_COLLECTION.with(|i| {
+        i.borrow_mut().insert(collection_key(collection_id, id), ());
+    });
+    PENDING_DEPLOYMENT_TRANSACTIONS.with(|p| {
+        p.borrow_mut().insert(id, ());
+    });
+    id
+}
+
+/// Stores `transaction`. Once it settles it leaves the pending set and its message is dropped.
+pub fn update_deployment_transaction(mut transaction: DeploymentTransaction) {
+    transaction.updated_at = crate::time::get_current_time();
+    if transaction.status.is_settled() {
+        transaction.message = None;
+        PENDING_DEPLOYMENT_TRANSACTIONS.with(|p| {
+            p.borrow_mut().remove(&transaction.id);
+        });
+    }
+    DEPLOYMENT_TRANSACTIONS.with(|t| {
+        t.borrow_mut().insert(transaction.id, transaction);
+    });
+}
+
+/// Every transaction sent for the collection, oldest first.
+pub fn get_deployment_timeline(collection_id: &str) -> Vec<DeploymentTransaction> {
+    let start = collection_key(collection_id, 0);
+    let end = collection_key(collection_id, u64::MAX);
+    let ids: Vec<u64> = DEPLOYMENT_TRANSACTIONS_BY_COLLECTION.with(|i| {
+        i.borrow()
+            .range(start..=end)
+            .filter_map(|entry| entry.key().rsplit_once(':').and_then(|(_, id)| id.parse().ok()))
+            .collect()
+    });
+    DEPLOYMENT_TRANSACTIONS.with(|t| {
+        let transactions = t.borrow();
+        ids.into_iter().filter_map(|id| transactions.get(&id)).collect()
+    })
+}
+
+/// Up to `max` unsettled transactions, oldest first.
+pub fn get_pending_deployment_transactions(max: usize) -> Vec<DeploymentTransaction> {
+    let ids: Vec<u64> = PENDING_DEPLOYMENT_TRANSACTIONS.with(|p| {
+        p.borrow().keys().take(max).collect()
+    });
+    DEPLOYMENT_TRANSACTIONS.with(|t| {
+        let transactions = t.borrow();
+        ids.into_iter().filter_map(|id| transactions.get(&id)).collect()
+    })
+}
+
+pub fn count_pending_deployment_transactions() -> u64 {
+    PENDING_DEPLOYMENT_TRANSACTIONS.with(|p| p.borrow().len())
+}
+
+pub fn get_lamports_spent(collection_id: &str) -> u64 {
+    LAMPORTS_SPENT.with(|s| s.borrow().get(&collection_id.to_string()).unwrap_or_default())
+}
+
+pub fn add_lamports_spent(collection_id: &str, lamports: u64) {
+    LAMPORTS_SPENT.with(|s| {
+        let mut spent = s.borrow_mut();
+        let total = spent.get(&collection_id.to_string()).unwrap_or_default().saturating_add(lamports);
+        spent.insert(collection_id.to_string(), total);
+    });
+}
+
+/// Gives back a charge for a transaction that was never accepted by the network.
+pub fn release_lamports_spent(collection_id: &str, lamports: u64) {
+    LAMPORTS_SPENT.with(|s| {
+        let mut spent = s.borrow_mut();
+        let total = spent.get(&collection_id.to_string()).unwrap_or_default().saturating_sub(lamports);
+        spent.insert(collection_id.to_string(), total);
+    });
+}
+
+/// Replaces the `charged` estimate of a transaction that landed with what it `actual`ly cost.
+pub fn correct_lamports_spent(collection_id: &str, charged: u64, actual: u64) {
+    LAMPORTS_SPENT.with(|s| {
+        let mut spent = s.borrow_mut();
+        let total = spent
+            .get(&collection_id.to_string())
+            .unwrap_or_default()
+            .saturating_sub(charged)
+            .saturating_add(actual);
+        spent.insert(collection_id.to_string(), total);
+    });
+}
+
+pub fn get_lamports_deposited(collection_id: &str) -> u64 {
+    LAMPORTS_DEPOSITED.with(|d| d.borrow().get(&collection_id.to_string()).unwrap_or_default())
+}
+
+pub fn is_deposit_recorded(tx_signature: &str) -> bool {
+    DEPOSIT_SIGNATURES.with(|s| s.borrow().contains_key(&tx_signature.to_string()))
+}
+
+/// Credits a verified deposit to the collection. Returns the collection's total deposits.
+pub fn record_lamports_deposit(collection_id: &str, tx_signature: &str, lamports: u64) -> u64 {
+    DEPOSIT_SIGNATURES.with(|s| {
+        s.borrow_mut().insert(tx_signature.to_string(), collection_id.to_string());
+    });
+    LAMPORTS_DEPOSITED.with(|d| {
+        let mut deposited = d.borrow_mut();
+        let total = deposited.get(&collection_id.to_string()).unwrap_or_default().saturating_add(lamports);
+        deposited.insert(collection_id.to_string(), total);
+        total
+    })
+}
@@ -0,0 +1,203 @@
+use candid::Principal;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use crate::types::{ActivityEvent, ActivityFilter, ActivityPage, MarketplaceEvent};
+use super::memory::{
+    get_memory, Memory, EVENTS_BY_COLLECTION_MEMORY_ID, EVENTS_BY_PRINCIPAL_MEMORY_ID,
+    EVENTS_MEMORY_ID,
+};
+
+#[cfg(test)]
+mod tests;
+
+pub const MAX_ACTIVITY_PAGE_SIZE: u32 = 100;
+
+/// Most index entries one `get_activity` call examines when a second filter is
+/// applied on top of an index.
+const MAX_ACTIVITY_SCAN: usize = 1_000;
+
+thread_local! {
+    // Append-only activity log keyed by sequence number
+    static EVENTS: RefCell<StableBTreeMap<u64, ActivityEvent, super::memory::Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(EVENTS_MEMORY_ID)));
+
+    // Secondary indexes, keyed "<value>:<zero-padded event id>"
+    static EVENTS_BY_COLLECTION: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(EVENTS_BY_COLLECTION_MEMORY_ID)));
+
+    static EVENTS_BY_PRINCIPAL: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(EVENTS_BY_PRINCIPAL_MEMORY_ID)));
+}
+
+fn index_key(prefix: &str, id: u64) -> String {
+    format!("{}:{:020}", prefix, id)
+}
+
+fn index_event(event: &ActivityEvent) {
+    EVENTS_BY_COLLECTION.with(|i| {
+        i.borrow_mut().insert(index_key(event.event.collection_id(), event.id), ());
+    });
+    EVENTS_BY_PRINCIPAL.with(|i| {
+        let mut index = i.borrow_mut();
+        for principal in event.event.principals() {
+            index.insert(index_key(&principal.to_text(), event.id), ());
+        }
+    });
+}
+
+/// Appends an event to the log and adds its ICRC-3 block if it has one.
+pub fn record_event(event: MarketplaceEvent) -> u64 {
+    let recorded = EVENTS.with(|e| {
+        let mut events = e.borrow_mut();
+        let id = events.last_key_value().map_or(0, |(id, _)| id + 1);
+        let recorded = ActivityEvent {
+            id,
+            timestamp: crate::time::get_current_time(),
+            event,
+        };
+        events.insert(id, recorded.clone());
+        recorded
+    });
+
+    index_event(&recorded);
+    if super::blocks::blocks_enabled() {
+        super::blocks::append_event_block(&recorded);
+        crate::certification::update_certified_data();
+    }
+    recorded.id
+}
+
+/// Events with ids in `[start, start + limit)`, oldest first.
+pub fn get_events_from(start: u64, limit: usize) -> Vec<ActivityEvent> {
+    EVENTS.with(|e| {
+        e.borrow()
+            .range(start..)
+            .take(limit)
+            .map(|entry| entry.value())
+            .collect()
+    })
+}
+
+pub fn event_count() -> u64 {
+    EVENTS.with(|e| e.borrow().len())
+}
+
+pub fn get_event(id: u64) -> Option<ActivityEvent> {
+    EVENTS.with(|e| e.borrow().get(&id))
+}
+
+/// Id of the first event at or after `timestamp`. Event ids are dense and
+/// timestamps never decrease, so this is a binary search over ids.
+fn first_event_at_or_after(timestamp: u64) -> u64 {
+    let (mut low, mut high) = EVENTS.with(|e| (0, e.borrow().len()));
+    while low < high {
+        let mid = low + (high - low) / 2;
+        let mid_timestamp = get_event(mid).map_or(u64::MAX, |event| event.timestamp);
+        if mid_timestamp < timestamp {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// Event ids under `prefix` in an index, newest first, below `before`.
+fn index_ids_desc(
+    index: &'static std::thread::LocalKey<RefCell<StableBTreeMap<String, (), Memory>>>,
+    prefix: &str,
+    before: u64,
+    after: u64,
+    limit: usize,
+) -> Vec<u64> {
+    let start = index_key(prefix, after);
+    let end = index_key(prefix, before);
+    index.with(|i| {
+        i.borrow()
+            .range(start..end)
+            .rev()
+            .take(limit)
+            .filter_map(|entry| entry.key().rsplit_once(':').and_then(|(_, id)| id.parse().ok()))
+            .collect()
+    })
+}
+
+/// Pages through the log newest first. `cursor` is the `next_cursor` of the
+/// previous page; every event in the page has an id below it. With both a
+/// collection and a principal filter a page may come back short, or empty, with
+/// a `next_cursor` when the scan budget ran out before the page filled.
+pub fn get_activity(filter: ActivityFilter, cursor: Option<u64>, limit: u32) -> ActivityPage {
+    let limit = limit.clamp(1, MAX_ACTIVITY_PAGE_SIZE) as usize;
+
+    // Narrow the time range to an id range [after, before)
+    let after = filter.from.map_or(0, first_event_at_or_after);
+    let mut before = filter.to.map_or(u64::MAX, first_event_at_or_after);
+    if let Some(cursor) = cursor {
+        before = before.min(cursor);
+    }
+    if after >= before {
+        return ActivityPage { events: vec![], next_cursor: None };
+    }
+
+    let principal_matches = |event: &ActivityEvent, principal: &Principal| {
+        event.event.principals().contains(principal)
+    };
+
+    // Where a scan cut short by MAX_ACTIVITY_SCAN resumes
+    let mut scan_cursor = None;
+
+    // Fetch one extra event to learn whether another page exists
+    let mut events: Vec<ActivityEvent> = match (&filter.collection_id, &filter.principal) {
+        (Some(collection_id), principal) => {
+            // Index entries are exact; the principal filter, if any, is applied on top.
+            // The scan stops after MAX_ACTIVITY_SCAN entries and resumes from the cursor.
+            let mut events = Vec::new();
+            let mut page_before = before;
+            let mut scanned = 0;
+            loop {
+                let batch = (limit + 1).min(MAX_ACTIVITY_SCAN - scanned);
+                let ids = index_ids_desc(&EVENTS_BY_COLLECTION, collection_id, page_before, after, batch);
+                scanned += ids.len();
+                let exhausted = ids.len() < batch;
+                let Some(&oldest) = ids.last() else { break };
+                events.extend(
+                    ids.into_iter()
+                        .filter_map(get_event)
+                        .filter(|event| principal.as_ref().map_or(true, |p| principal_matches(event, p))),
+                );
+                if exhausted || events.len() > limit {
+                    break;
+                }
+                if scanned >= MAX_ACTIVITY_SCAN {
+                    scan_cursor = Some(oldest);
+                    break;
+                }
+                page_before = oldest;
+            }
+            events
+        }
+        (None, Some(principal)) => {
+            index_ids_desc(&EVENTS_BY_PRINCIPAL, &principal.to_text(), before, after, limit + 1)
+                .into_iter()
+                .filter_map(get_event)
+                .collect()
+        }
+        (None, None) => EVENTS.with(|e| {
+            e.borrow()
+                .range(after..before)
+                .rev()
+                .take(limit + 1)
+                .map(|entry| entry.value())
+                .collect()
+        }),
+    };
+
+    let next_cursor = if events.len() > limit {
+        events.truncate(limit);
+        events.last().map(|event| event.id)
+    } else {
+        scan_cursor
+    };
+
+    ActivityPage { events, next_cursor }
+}
@@ -0,0 +1,150 @@
+use super::*;
+use crate::state::fixtures::principal;
+use crate::time::{get_current_time, set_current_time};
+
+fn expired(collection_id: &str, seller: Principal) -> MarketplaceEvent {
+    MarketplaceEvent::ListingExpired {
+        listing_id: "listing".to_string(),
+        collection_id: collection_id.to_string(),
+        nft_id: "nft".to_string(),
+        seller,
+    }
+}
+
+fn sold(collection_id: &str, seller: Principal, buyer: Principal) -> MarketplaceEvent {
+    MarketplaceEvent::ItemSold {
+        sale_id: "sale".to_string(),
+        listing_id: "listing".to_string(),
+        collection_id: collection_id.to_string(),
+        nft_id: "nft".to_string(),
+        seller,
+        buyer,
+        price: 1_000,
+        currency: "ICP".to_string(),
+        tx_signature: "tx".to_string(),
+    }
+}
+
+fn ids(page: &ActivityPage) -> Vec<u64> {
+    page.events.iter().map(|event| event.id).collect()
+}
+
+mod record_event {
+    use super::*;
+
+    #[test]
+    fn should_number_events_in_order() {
+        assert_eq!(record_event(expired("a", principal(2))), 0);
+        assert_eq!(record_event(expired("a", principal(2))), 1);
+
+        assert_eq!(event_count(), 2);
+        assert_eq!(get_event(1).unwrap().timestamp, get_current_time());
+        assert_eq!(get_events_from(1, 10).len(), 1);
+    }
+}
+
+mod get_activity {
+    use super::*;
+
+    #[test]
+    fn should_page_newest_first() {
+        for _ in 0..5 {
+            record_event(expired("a", principal(2)));
+        }
+
+        let first = get_activity(ActivityFilter::default(), None, 2);
+        assert_eq!(ids(&first), vec![4, 3]);
+        assert_eq!(first.next_cursor, Some(3));
+
+        let second = get_activity(ActivityFilter::default(), first.next_cursor, 2);
+        assert_eq!(ids(&second), vec![2, 1]);
+
+        let last = get_activity(ActivityFilter::default(), second.next_cursor, 2);
+        assert_eq!(ids(&last), vec![0]);
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn should_filter_by_collection() {
+        record_event(expired("a", principal(2)));
+        record_event(expired("b", principal(2)));
+        record_event(expired("a", principal(2)));
+
+        let by_collection = ActivityFilter {
+            collection_id: Some("a".to_string()),
+            ..Default::default()
+        };
+        let page = get_activity(by_collection, None, 10);
+
+        assert_eq!(ids(&page), vec![2, 0]);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn should_filter_by_every_principal_of_an_event() {
+        record_event(expired("a", principal(2)));
+        record_event(expired("a", principal(3)));
+        record_event(sold("b", principal(2), principal(3)));
+
+        let by_buyer = get_activity(ActivityFilter { principal: Some(principal(3)), ..Default::default() }, None, 10);
+        let by_seller = get_activity(ActivityFilter { principal: Some(principal(2)), ..Default::default() }, None, 10);
+
+        assert_eq!(ids(&by_buyer), vec![2, 1]);
+        assert_eq!(ids(&by_seller), vec![2, 0]);
+    }
+
+    #[test]
+    fn should_combine_collection_and_principal_filters() {
+        record_event(expired("a", principal(2)));
+        record_event(expired("a", principal(3)));
+        record_event(expired("b", principal(2)));
+
+        let both = ActivityFilter {
+            collection_id: Some("a".to_string()),
+            principal: Some(principal(2)),
+            ..Default::default()
+        };
+
+        assert_eq!(ids(&get_activity(both, None, 10)), vec![0]);
+    }
+
+    #[test]
+    fn should_limit_to_a_time_range() {
+        let start = get_current_time();
+        for offset in [0, 10, 20] {
+            set_current_time(start + offset);
+            record_event(expired("a", principal(2)));
+        }
+
+        let range = ActivityFilter {
+            from: Some(start + 10),
+            to: Some(start + 20),
+            ..Default::default()
+        };
+        let from_only = ActivityFilter { from: Some(start + 5), ..Default::default() };
+
+        assert_eq!(ids(&get_activity(range, None, 10)), vec![1]);
+        assert_eq!(ids(&get_activity(from_only, None, 10)), vec![2, 1]);
+    }
+
+    #[test]
+    fn should_resume_a_scan_that_ran_out_of_budget() {
+        record_event(expired("a", principal(9)));
+        for _ in 0..MAX_ACTIVITY_SCAN + 4 {
+            record_event(expired("a", principal(2)));
+        }
+        let rare = ActivityFilter {
+            collection_id: Some("a".to_string()),
+            principal: Some(principal(9)),
+            ..Default::default()
+        };
+
+        let first = get_activity(rare.clone(), None, 10);
+        assert!(first.events.is_empty());
+        assert!(first.next_cursor.is_some());
+
+        let second = get_activity(rare, first.next_cursor, 10);
+        assert_eq!(ids(&second), vec![0]);
+        assert_eq!(second.next_cursor, None);
+    }
+}
@@ -0,0 +1,88 @@
+//! Records for unit tests, stored the way the endpoints store them.
+
+use candid::Principal;
+use crate::time::get_current_time;
+use crate::types::{
+    Blockchain, ChainData, Collection, CollectionStatus, ICPCollectionData, ICPDeploymentStage, Listing,
+    ListingStatus, NftMetadata, Offer, OfferStatus,
+};
+
+pub const HOUR_NANOS: u64 = 60 * 60 * 1_000_000_000;
+
+pub fn principal(id: u8) -> Principal {
+    Principal::from_slice(&[id; 29])
+}
+
+pub fn collection(collection_id: &str) -> Collection {
+    let now = get_current_time();
+    Collection {
+        id: collection_id.to_string(),
+        blockchain: Blockchain::ICP,
+        creator: principal(1),
+        name: format!("Collection {}", collection_id),
+        symbol: "COL".to_string(),
+        description: String::new(),
+        image_url: "https://example.com/collection.png".to_string(),
+        banner_url: None,
+        total_supply: 100,
+        floor_price: 0,
+        total_volume: 0,
+        owner_count: 0,
+        listed_count: 0,
+        royalty_bps: 500,
+        metadata: vec![],
+        status: CollectionStatus::Active,
+        chain_data: ChainData::ICP(ICPCollectionData {
+            deployment_stage: ICPDeploymentStage::CanisterCreating,
+            canister_id: None,
+        }),
+        created_at: now,
+        updated_at: now,
+    }
+}
+
+/// An active listing of `nft-<listing_id>` by `principal(2)`.
+pub fn listing(collection_id: &str, listing_id: &str, price: u64) -> Listing {
+    let now = get_current_time();
+    Listing {
+        id: listing_id.to_string(),
+        collection_id: collection_id.to_string(),
+        nft_id: format!("nft-{}", listing_id),
+        blockchain: Blockchain::ICP,
+        seller: principal(2),
+        seller_address: "seller-address".to_string(),
+        price,
+        currency: "ICP".to_string(),
+        escrow_address: None,
+        reserved_for: None,
+        reserved_price: None,
+        reserved_until: None,
+        status: ListingStatus::Active,
+        listed_at: now,
+        expires_at: None,
+        updated_at: now,
+        nft_metadata: NftMetadata {
+            name: format!("NFT {}", listing_id),
+            image_url: "https://example.com/nft.png".to_string(),
+            attributes: vec![],
+        },
+    }
+}
+
+/// An active offer on `listing` that expires in an hour.
+pub fn offer(offer_id: &str, listing: &Listing, bidder: Principal, price: u64) -> Offer {
+    let now = get_current_time();
+    Offer {
+        id: offer_id.to_string(),
+        listing_id: listing.id.clone(),
+        collection_id: listing.collection_id.clone(),
+        nft_id: listing.nft_id.clone(),
+        bidder,
+        price,
+        currency: listing.currency.clone(),
+        status: OfferStatus::Active,
+        expires_at: now + HOUR_NANOS,
+        created_at: now,
+        updated_at: now,
+    }
+}
@@ -1,26 +1,234 @@
 use canister_uuid::get_uuid;
 use ic_stable_structures::StableBTreeMap;
 use std::cell::RefCell;
-use crate::types::{Listing, CreateListingArgs, ListingStatus, UpdateListingArgs};
-use super::memory::{get_memory, LISTINGS_MEMORY_ID};
+use crate::types::{
+    Blockchain, CreateListingArgs, Entity, Listing, ListingStatus, MarketplaceError,
+    MarketplaceEvent, MarketplaceResult, OfferStatus, UpdateListingArgs,
+};
+use super::memory::{
+    get_memory, Memory, ACTIVE_LISTINGS_BY_PRICE_MEMORY_ID, LISTINGS_BY_COLLECTION_STATUS_MEMORY_ID,
+    LISTINGS_BY_EXPIRY_MEMORY_ID, LISTINGS_BY_NFT_MEMORY_ID, LISTINGS_BY_RESERVATION_MEMORY_ID,
+    LISTINGS_BY_SELLER_MEMORY_ID, LISTINGS_BY_STATUS_MEMORY_ID, LISTINGS_MEMORY_ID,
+};
 use candid::Principal;
+use crate::migrations::BatchProgress;
+
+#[cfg(test)]
+mod tests;
 
 thread_local! {
     static LISTINGS: RefCell<StableBTreeMap<String, Listing, super::memory::Memory>> =
         RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_MEMORY_ID)));
+
+    // Secondary indexes, keyed "<value>:<listing key>"
+    static LISTINGS_BY_SELLER: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_BY_SELLER_MEMORY_ID)));
+
+    static LISTINGS_BY_STATUS: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_BY_STATUS_MEMORY_ID)));
+
+    // Keyed "<collection_id>:<status>:<listing_id>"
+    static LISTINGS_BY_COLLECTION_STATUS: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_BY_COLLECTION_STATUS_MEMORY_ID)));
+
+    // Keyed "<collection_id>:<nft_id>:<listing_id>"
+    static LISTINGS_BY_NFT: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_BY_NFT_MEMORY_ID)));
+
+    // Active listings only, keyed "<collection_id>:<zero-padded price>:<listing_id>"
+    static ACTIVE_LISTINGS_BY_PRICE: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(ACTIVE_LISTINGS_BY_PRICE_MEMORY_ID)));
+
+    // Active listings with an expiry, keyed "<zero-padded expires_at>:<listing key>"
+    static LISTINGS_BY_EXPIRY: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_BY_EXPIRY_MEMORY_ID)));
+
+    // Reserved active listings, keyed "<zero-padded reserved_until>:<listing key>"
+    static LISTINGS_BY_RESERVATION: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(LISTINGS_BY_RESERVATION_MEMORY_ID)));
 }
 
 fn make_listing_key(collection_id: &str, listing_id: &str) -> String {
     format!("{}:{}", collection_id, listing_id)
 }
 
-pub async fn add_listing(args: CreateListingArgs, seller: Principal, blockchain: crate::types::Blockchain) -> Result<String, String> {
+fn seller_index_key(listing: &Listing) -> String {
+    format!("{}:{}", listing.seller.to_text(), make_listing_key(&listing.collection_id, &listing.id))
+}
+
+fn status_index_key(listing: &Listing) -> String {
+    format!("{:?}:{}", listing.status, make_listing_key(&listing.collection_id, &listing.id))
+}
+
+fn collection_status_prefix(collection_id: &str, status: &ListingStatus) -> String {
+    format!("{}:{:?}", collection_id, status)
+}
+
+fn collection_status_index_key(listing: &Listing) -> String {
+    format!("{}:{}", collection_status_prefix(&listing.collection_id, &listing.status), listing.id)
+}
+
+fn nft_prefix(collection_id: &str, nft_id: &str) -> String {
+    format!("{}:{}", collection_id, nft_id)
+}
+
+fn nft_index_key(listing: &Listing) -> String {
+    format!("{}:{}", nft_prefix(&listing.collection_id, &listing.nft_id), listing.id)
+}
+
+fn price_index_key(listing: &Listing) -> String {
+    format!("{}:{:020}:{}", listing.collection_id, listing.price, listing.id)
+}
+
+fn expiry_index_key(listing: &Listing) -> Option<String> {
+    listing.expires_at.map(|expires_at| {
+        format!("{:020}:{}", expires_at, make_listing_key(&listing.collection_id, &listing.id))
+    })
+}
+
+fn reservation_index_key(listing: &Listing) -> Option<String> {
+    listing.reserved_until.map(|reserved_until| {
+        format!("{:020}:{}", reserved_until, make_listing_key(&listing.collection_id, &listing.id))
+    })
+}
+
+fn index_listing(listing: &Listing) {
+    crate::certification::certify_listing(listing);
+    LISTINGS_BY_SELLER.with(|i| {
+        i.borrow_mut().insert(seller_index_key(listing), ());
+    });
+    LISTINGS_BY_STATUS.with(|i| {
+        i.borrow_mut().insert(status_index_key(listing), ());
+    });
+    LISTINGS_BY_COLLECTION_STATUS.with(|i| {
+        i.borrow_mut().insert(collection_status_index_key(listing), ());
+    });
+    LISTINGS_BY_NFT.with(|i| {
+        i.borrow_mut().insert(nft_index_key(listing), ());
+    });
+    if listing.status == ListingStatus::Active {
+        ACTIVE_LISTINGS_BY_PRICE.with(|i| {
+            i.borrow_mut().insert(price_index_key(listing), ());
+        });
+        if let Some(key) = expiry_index_key(listing) {
+            LISTINGS_BY_EXPIRY.with(|i| {
+                i.borrow_mut().insert(key, ());
+            });
+        }
+        if let Some(key) = reservation_index_key(listing) {
+            LISTINGS_BY_RESERVATION.with(|i| {
+                i.borrow_mut().insert(key, ());
+            });
+        }
+    }
+}
+
+fn unindex_listing(listing: &Listing) {
+    crate::certification::uncertify_listing(&listing.collection_id, &listing.id);
+    LISTINGS_BY_SELLER.with(|i| {
+        i.borrow_mut().remove(&seller_index_key(listing));
+    });
+    LISTINGS_BY_STATUS.with(|i| {
+        i.borrow_mut().remove(&status_index_key(listing));
+    });
+    LISTINGS_BY_COLLECTION_STATUS.with(|i| {
+        i.borrow_mut().remove(&collection_status_index_key(listing));
+    });
+    LISTINGS_BY_NFT.with(|i| {
+        i.borrow_mut().remove(&nft_index_key(listing));
+    });
+    ACTIVE_LISTINGS_BY_PRICE.with(|i| {
+        i.borrow_mut().remove(&price_index_key(listing));
+    });
+    if let Some(key) = expiry_index_key(listing) {
+        LISTINGS_BY_EXPIRY.with(|i| {
+            i.borrow_mut().remove(&key);
+        });
+    }
+    if let Some(key) = reservation_index_key(listing) {
+        LISTINGS_BY_RESERVATION.with(|i| {
+            i.borrow_mut().remove(&key);
+        });
+    }
+}
+
+/// Index key suffixes (after `prefix:`), paginated.
+fn index_page(
+    index: &'static std::thread::LocalKey<RefCell<StableBTreeMap<String, (), Memory>>>,
+    prefix: &str,
+    page: u32,
+    limit: u32,
+) -> Vec<String> {
+    let prefix = format!("{}:", prefix);
+    index.with(|i| {
+        i.borrow()
+            .range(prefix.clone()..)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .skip(page.saturating_mul(limit) as usize)
+            .take(limit as usize)
+            .map(|entry| entry.key()[prefix.len()..].to_string())
+            .collect()
+    })
+}
+
+fn listings_by_keys(keys: Vec<String>) -> Vec<Listing> {
+    LISTINGS.with(|l| {
+        let listings = l.borrow();
+        keys.into_iter().filter_map(|key| listings.get(&key)).collect()
+    })
+}
+
+/// Migration step: rebuilds the secondary indexes, `limit` listings per batch. The
+/// first batch clears them; the cursor is the last listing key indexed.
+pub fn reindex_listings_batch(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    if cursor.is_none() {
+        for index in [
+            &LISTINGS_BY_SELLER,
+            &LISTINGS_BY_STATUS,
+            &LISTINGS_BY_COLLECTION_STATUS,
+            &LISTINGS_BY_NFT,
+            &ACTIVE_LISTINGS_BY_PRICE,
+            &LISTINGS_BY_EXPIRY,
+            &LISTINGS_BY_RESERVATION,
+        ] {
+            index.with(|i| i.borrow_mut().clear_new());
+        }
+    }
+
+    let listings = LISTINGS.with(|l| super::entries_after(&l.borrow(), cursor, limit));
+    for (_, listing) in listings.iter() {
+        index_listing(listing);
+    }
+    Ok(super::batch_progress(&listings, limit))
+}
+
+/// Up to `limit` active listings after the listing key `cursor`, with their keys.
+pub fn active_listings_after(cursor: Option<String>, limit: usize) -> Vec<(String, Listing)> {
+    let prefix = format!("{:?}:", ListingStatus::Active);
+    let start = format!("{}{}", prefix, cursor.unwrap_or_default());
+    let keys: Vec<String> = LISTINGS_BY_STATUS.with(|i| {
+        i.borrow()
+            .range(start.clone()..)
+            .skip_while(|entry| *entry.key() == start)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .take(limit)
+            .map(|entry| entry.key()[prefix.len()..].to_string())
+            .collect()
+    });
+    LISTINGS.with(|l| {
+        let listings = l.borrow();
+        keys.into_iter()
+            .filter_map(|key| listings.get(&key).map(|listing| (key, listing)))
+            .collect()
+    })
+}
+
+pub async fn add_listing(args: CreateListingArgs, seller: Principal, blockchain: Blockchain) -> MarketplaceResult<String> {
     let listing_id = get_uuid().await;
-    let key = make_listing_key(&args.collection_id, &listing_id);
 
-    let listing = Listing {
+    insert_listing(Listing {
         id: listing_id.clone(),
-        collection_id: args.collection_id.clone(),
+        collection_id: args.collection_id,
         nft_id: args.nft_id,
         blockchain,
         seller,
@@ -28,20 +236,39 @@ pub async fn add_listing(args: CreateListingArgs, seller: Principal, blockchain:
         price: args.price,
         currency: args.currency,
         escrow_address: None,
+        reserved_for: None,
+        reserved_price: None,
+        reserved_until: None,
         status: ListingStatus::Active,
-        listed_at: ic_cdk::api::time(),
+        listed_at: crate::time::get_current_time(),
         expires_at: args.expires_at,
-        updated_at: ic_cdk::api::time(),
+        updated_at: crate::time::get_current_time(),
         nft_metadata: args.nft_metadata,
+    });
+
+    Ok(listing_id)
+}
+
+/// Stores a new listing and records that it was listed.
+pub(crate) fn insert_listing(listing: Listing) {
+    let key = make_listing_key(&listing.collection_id, &listing.id);
+    let collection_id = listing.collection_id.clone();
+    let event = MarketplaceEvent::ItemListed {
+        listing_id: listing.id.clone(),
+        collection_id: listing.collection_id.clone(),
+        nft_id: listing.nft_id.clone(),
+        seller: listing.seller,
+        price: listing.price,
+        currency: listing.currency.clone(),
     };
 
+    index_listing(&listing);
     LISTINGS.with(|l| {
         l.borrow_mut().insert(key, listing);
     });
+    super::events::record_event(event);
 
-    super::collections::update_collection_stats(&args.collection_id, None, None, None, None).ok();
-
-    Ok(listing_id)
+    refresh_collection_listing_stats(&collection_id);
 }
 
 pub fn get_listing(collection_id: &str, listing_id: &str) -> Option<Listing> {
@@ -55,64 +282,306 @@ pub fn get_collection_listings(
     limit: u32,
     status: Option<ListingStatus>,
 ) -> Vec<Listing> {
-    let prefix = format!("{}:", collection_id);
+    if let Some(status) = status {
+        let prefix = collection_status_prefix(collection_id, &status);
+        let keys = index_page(&LISTINGS_BY_COLLECTION_STATUS, &prefix, page, limit)
+            .into_iter()
+            .map(|listing_id| make_listing_key(collection_id, &listing_id))
+            .collect();
+        return listings_by_keys(keys);
+    }
 
+    let prefix = format!("{}:", collection_id);
     LISTINGS.with(|l| {
         l.borrow()
             .range(prefix.clone()..)
             .take_while(|entry| entry.key().starts_with(&prefix))
-            .filter(|entry| {
-                status.as_ref().map_or(true, |s| &entry.value().status == s)
-            })
-            .skip((page * limit) as usize)
+            .skip(page.saturating_mul(limit) as usize)
             .take(limit as usize)
             .map(|entry| entry.value())
             .collect()
     })
 }
 
-pub fn get_user_listings(seller: &Principal, page: u32, limit: u32) -> Vec<Listing> {
-    LISTINGS.with(|l| {
-        l.borrow()
-            .iter()
-            .filter(|entry| &entry.value().seller == seller)
-            .skip((page * limit) as usize)
-            .take(limit as usize)
-            .map(|entry| entry.value())
+/// Any listing of `nft_id` in the collection, used for its `NftMetadata`.
+pub fn find_listing_by_nft(collection_id: &str, nft_id: &str) -> Option<Listing> {
+    let prefix = format!("{}:", nft_prefix(collection_id, nft_id));
+    let keys: Vec<String> = LISTINGS_BY_NFT.with(|i| {
+        i.borrow()
+            .range(prefix.clone()..)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .map(|entry| make_listing_key(collection_id, &entry.key()[prefix.len()..]))
             .collect()
+    });
+    listings_by_keys(keys)
+        .into_iter()
+        // An NFT id containing ':' can share the prefix of another NFT's entries
+        .find(|listing| listing.nft_id == nft_id)
+}
+
+pub fn get_user_listings(seller: &Principal, page: u32, limit: u32) -> Vec<Listing> {
+    listings_by_keys(index_page(&LISTINGS_BY_SELLER, &seller.to_text(), page, limit))
+}
+
+pub fn get_listings_by_status(status: &ListingStatus, page: u32, limit: u32) -> Vec<Listing> {
+    listings_by_keys(index_page(&LISTINGS_BY_STATUS, &format!("{:?}", status), page, limit))
+}
+
+pub fn count_listings_by_status(status: &ListingStatus) -> u64 {
+    let prefix = format!("{:?}:", status);
+    LISTINGS_BY_STATUS.with(|i| {
+        i.borrow()
+            .range(prefix.clone()..)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .count() as u64
     })
 }
 
-pub fn update_listing(args: UpdateListingArgs, collection_id: &str) -> Result<(), String> {
+/// Active listings of a collection, cheapest first.
+pub fn get_cheapest_listings(collection_id: &str, page: u32, limit: u32) -> Vec<Listing> {
+    let keys = index_page(&ACTIVE_LISTINGS_BY_PRICE, collection_id, page, limit)
+        .into_iter()
+        .filter_map(|suffix| {
+            suffix
+                .split_once(':')
+                .map(|(_, listing_id)| make_listing_key(collection_id, listing_id))
+        })
+        .collect();
+    listings_by_keys(keys)
+}
+
+pub fn get_floor_listing(collection_id: &str) -> Option<Listing> {
+    get_cheapest_listings(collection_id, 0, 1).into_iter().next()
+}
+
+pub fn update_listing(args: UpdateListingArgs, collection_id: &str) -> MarketplaceResult<()> {
     let key = make_listing_key(collection_id, &args.listing_id);
 
-    LISTINGS.with(|l| {
+    let result = LISTINGS.with(|l| {
         let mut listings = l.borrow_mut();
 
         if let Some(mut listing) = listings.get(&key) {
+            let previous = listing.clone();
+            unindex_listing(&listing);
             if let Some(price) = args.price {
                 listing.price = price;
             }
             if let Some(status) = args.status {
                 listing.status = status;
             }
-            listing.updated_at = ic_cdk::api::time();
+            listing.updated_at = crate::time::get_current_time();
+            index_listing(&listing);
 
+            record_listing_changes(&previous, &listing);
+            if previous.status == ListingStatus::Active {
+                close_listing_offers(&listing);
+            }
             listings.insert(key, listing);
             Ok(())
         } else {
-            Err("Listing not found".to_string())
+            Err(MarketplaceError::not_found(Entity::Listing, &args.listing_id))
         }
+    });
+
+    if result.is_ok() {
+        refresh_collection_listing_stats(collection_id);
+    }
+    result
+}
+
+/// Settles the offers still open on a listing that just left `Active`.
+fn close_listing_offers(listing: &Listing) {
+    let status = match listing.status {
+        ListingStatus::Active => return,
+        ListingStatus::Expired => OfferStatus::Expired,
+        ListingStatus::Sold | ListingStatus::Cancelled => OfferStatus::Cancelled,
+    };
+    super::offers::close_listing_offers(&listing.collection_id, &listing.id, status, None);
+}
+
+/// Reserves an active listing for `bidder` at `price` until `reserved_until` once the
+/// seller accepted their offer. The asking price is left as is.
+pub fn reserve_listing(
+    collection_id: &str,
+    listing_id: &str,
+    bidder: Principal,
+    price: u64,
+    reserved_until: u64,
+) -> MarketplaceResult<()> {
+    let key = make_listing_key(collection_id, listing_id);
+
+    LISTINGS.with(|l| {
+        let mut listings = l.borrow_mut();
+        let mut listing = listings
+            .get(&key)
+            .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, listing_id))?;
+        if listing.status != ListingStatus::Active {
+            return Err(MarketplaceError::invalid_state("Listing is not active"));
+        }
+
+        let previous = listing.clone();
+        unindex_listing(&listing);
+        listing.reserved_for = Some(bidder);
+        listing.reserved_price = Some(price);
+        listing.reserved_until = Some(reserved_until);
+        listing.updated_at = crate::time::get_current_time();
+        index_listing(&listing);
+
+        record_listing_changes(&previous, &listing);
+        listings.insert(key, listing);
+        Ok(())
     })
 }
 
-pub fn remove_listing(collection_id: &str, listing_id: &str) -> Result<(), String> {
+/// Records price and status changes made by `update_listing`. Sales are recorded
+/// by `record_sale` as `ItemSold`, so the move to `Sold` is not repeated here.
+fn record_listing_changes(previous: &Listing, listing: &Listing) {
+    if listing.price != previous.price {
+        super::events::record_event(MarketplaceEvent::ListingPriceChanged {
+            listing_id: listing.id.clone(),
+            collection_id: listing.collection_id.clone(),
+            nft_id: listing.nft_id.clone(),
+            seller: listing.seller,
+            old_price: previous.price,
+            new_price: listing.price,
+        });
+    }
+
+    if listing.status == previous.status {
+        return;
+    }
+    let event = match listing.status {
+        ListingStatus::Sold => return,
+        ListingStatus::Cancelled => MarketplaceEvent::ListingCancelled {
+            listing_id: listing.id.clone(),
+            collection_id: listing.collection_id.clone(),
+            nft_id: listing.nft_id.clone(),
+            seller: listing.seller,
+        },
+        _ => MarketplaceEvent::ListingStatusChanged {
+            listing_id: listing.id.clone(),
+            collection_id: listing.collection_id.clone(),
+            nft_id: listing.nft_id.clone(),
+            seller: listing.seller,
+            from: previous.status.clone(),
+            to: listing.status.clone(),
+        },
+    };
+    super::events::record_event(event);
+}
+
+/// Marks up to `max` active listings whose `expires_at` has passed as `Expired` and
+/// returns them. Collection stats are left for the caller to refresh once per batch.
+pub fn expire_due_listings(now: u64, max: usize) -> Vec<Listing> {
+    let due_keys: Vec<String> = LISTINGS_BY_EXPIRY.with(|i| {
+        i.borrow()
+            .iter()
+            .take(max)
+            .take_while(|entry| {
+                entry.key()
+                    .split_once(':')
+                    .and_then(|(expires_at, _)| expires_at.parse::<u64>().ok())
+                    .is_some_and(|expires_at| expires_at <= now)
+            })
+            .map(|entry| entry.key().clone())
+            .collect()
+    });
+
+    LISTINGS.with(|l| {
+        let mut listings = l.borrow_mut();
+        due_keys
+            .into_iter()
+            .filter_map(|index_key| {
+                let key = index_key.split_once(':').map(|(_, key)| key.to_string())?;
+                let Some(mut listing) = listings.get(&key) else {
+                    // Dangling index entry, drop it so it does not block the batch
+                    LISTINGS_BY_EXPIRY.with(|i| i.borrow_mut().remove(&index_key));
+                    return None;
+                };
+                unindex_listing(&listing);
+                listing.status = ListingStatus::Expired;
+                listing.updated_at = now;
+                index_listing(&listing);
+                close_listing_offers(&listing);
+                super::events::record_event(MarketplaceEvent::ListingExpired {
+                    listing_id: listing.id.clone(),
+                    collection_id: listing.collection_id.clone(),
+                    nft_id: listing.nft_id.clone(),
+                    seller: listing.seller,
+                });
+                listings.insert(key, listing.clone());
+                Some(listing)
+            })
+            .collect()
+    })
+}
+
+/// Clears up to `max` reservations whose `reserved_until` has passed, expiring the
+/// accepted offers behind them, and returns the reopened listings.
+pub fn release_due_reservations(now: u64, max: usize) -> Vec<Listing> {
+    let due_keys: Vec<String> = LISTINGS_BY_RESERVATION.with(|i| {
+        i.borrow()
+            .iter()
+            .take(max)
+            .take_while(|entry| {
+                entry.key()
+                    .split_once(':')
+                    .and_then(|(reserved_until, _)| reserved_until.parse::<u64>().ok())
+                    .is_some_and(|reserved_until| reserved_until <= now)
+            })
+            .map(|entry| entry.key().clone())
+            .collect()
+    });
+
+    let released: Vec<Listing> = LISTINGS.with(|l| {
+        let mut listings = l.borrow_mut();
+        due_keys
+            .into_iter()
+            .filter_map(|index_key| {
+                let key = index_key.split_once(':').map(|(_, key)| key.to_string())?;
+                let Some(mut listing) = listings.get(&key) else {
+                    LISTINGS_BY_RESERVATION.with(|i| i.borrow_mut().remove(&index_key));
+                    return None;
+                };
+                unindex_listing(&listing);
+                listing.reserved_for = None;
+                listing.reserved_price = None;
+                listing.reserved_until = None;
+                listing.updated_at = now;
+                index_listing(&listing);
+                listings.insert(key, listing.clone());
+                Some(listing)
+            })
+            .collect()
+    });
+
+    for listing in released.iter() {
+        super::offers::close_listing_offers_with_status(
+            &listing.collection_id,
+            &listing.id,
+            OfferStatus::Accepted,
+            OfferStatus::Expired,
+        );
+    }
+    released
+}
+
+pub fn remove_listing(collection_id: &str, listing_id: &str) -> MarketplaceResult<()> {
     let key = make_listing_key(collection_id, listing_id);
 
     LISTINGS.with(|l| {
-        l.borrow_mut().remove(&key);
+        if let Some(listing) = l.borrow_mut().remove(&key) {
+            unindex_listing(&listing);
+            super::events::record_event(MarketplaceEvent::ListingRemoved {
+                listing_id: listing.id,
+                collection_id: listing.collection_id,
+                nft_id: listing.nft_id,
+                seller: listing.seller,
+            });
+        }
     });
 
+    refresh_collection_listing_stats(collection_id);
     Ok(())
 }
 
@@ -125,4 +594,26 @@ pub fn get_collection_listing_count(collection_id: &str) -> u32 {
             .take_while(|entry| entry.key().starts_with(&prefix))
             .count() as u32
     })
-}
\ No newline at end of file
+}
+
+/// Recomputes `listed_count` and `floor_price` from the collection's active listings.
+pub fn refresh_collection_listing_stats(collection_id: &str) {
+    let prefix = format!("{}:", collection_id);
+
+    let listed_count = ACTIVE_LISTINGS_BY_PRICE.with(|i| {
+        i.borrow()
+            .range(prefix.clone()..)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .count() as u32
+    });
+    let floor_price = get_floor_listing(collection_id).map_or(0, |listing| listing.price);
+
+    super::collections::update_collection_stats(
+        collection_id,
+        Some(floor_price),
+        None,
+        None,
+        Some(listed_count),
+    )
+    .ok();
+}
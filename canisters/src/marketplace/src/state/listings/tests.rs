@@ -0,0 +1,210 @@
+use super::*;
+use crate::state::collections::{get_collection, insert_collection};
+use crate::state::fixtures::{self, principal};
+
+fn listed(listing_id: &str, price: u64) -> Listing {
+    let listing = fixtures::listing("collection", listing_id, price);
+    insert_listing(listing.clone());
+    listing
+}
+
+fn set_status(listing_id: &str, status: ListingStatus) {
+    let args = UpdateListingArgs {
+        listing_id: listing_id.to_string(),
+        price: None,
+        status: Some(status),
+    };
+    update_listing(args, "collection").unwrap();
+}
+
+fn ids(listings: Vec<Listing>) -> Vec<String> {
+    listings.into_iter().map(|listing| listing.id).collect()
+}
+
+mod price_index {
+    use super::*;
+
+    #[test]
+    fn should_order_active_listings_by_price() {
+        listed("a", 300);
+        listed("b", 100);
+        listed("c", 2_000);
+
+        assert_eq!(ids(get_cheapest_listings("collection", 0, 10)), vec!["b", "a", "c"]);
+        assert_eq!(ids(get_cheapest_listings("collection", 1, 1)), vec!["a"]);
+        assert_eq!(get_floor_listing("collection").unwrap().id, "b");
+        assert!(get_cheapest_listings("other", 0, 10).is_empty());
+    }
+
+    #[test]
+    fn should_follow_a_price_change() {
+        listed("a", 300);
+        listed("b", 100);
+
+        let args = UpdateListingArgs {
+            listing_id: "b".to_string(),
+            price: Some(400),
+            status: None,
+        };
+        update_listing(args, "collection").unwrap();
+
+        assert_eq!(ids(get_cheapest_listings("collection", 0, 10)), vec!["a", "b"]);
+        assert_eq!(get_floor_listing("collection").unwrap().id, "a");
+    }
+
+    #[test]
+    fn should_drop_listings_that_are_no_longer_active() {
+        listed("a", 300);
+        listed("b", 100);
+
+        set_status("b", ListingStatus::Cancelled);
+
+        assert_eq!(ids(get_cheapest_listings("collection", 0, 10)), vec!["a"]);
+    }
+
+    #[test]
+    fn should_keep_collection_stats_in_step() {
+        insert_collection(fixtures::collection("collection"));
+        listed("a", 300);
+        listed("b", 100);
+
+        let collection = get_collection("collection").unwrap();
+        assert_eq!((collection.floor_price, collection.listed_count), (100, 2));
+
+        set_status("b", ListingStatus::Cancelled);
+
+        let collection = get_collection("collection").unwrap();
+        assert_eq!((collection.floor_price, collection.listed_count), (300, 1));
+    }
+}
+
+mod status_index {
+    use super::*;
+
+    #[test]
+    fn should_move_a_listing_between_statuses() {
+        listed("a", 100);
+        listed("b", 100);
+
+        set_status("a", ListingStatus::Cancelled);
+
+        assert_eq!(ids(get_listings_by_status(&ListingStatus::Active, 0, 10)), vec!["b"]);
+        assert_eq!(ids(get_listings_by_status(&ListingStatus::Cancelled, 0, 10)), vec!["a"]);
+        assert_eq!(count_listings_by_status(&ListingStatus::Active), 1);
+        assert_eq!(count_listings_by_status(&ListingStatus::Cancelled), 1);
+    }
+
+    #[test]
+    fn should_filter_a_collection_by_status() {
+        listed("a", 100);
+        listed("b", 100);
+        listed("c", 100);
+        set_status("b", ListingStatus::Sold);
+
+        let active = get_collection_listings("collection", 0, 10, Some(ListingStatus::Active));
+        let sold = get_collection_listings("collection", 0, 10, Some(ListingStatus::Sold));
+
+        assert_eq!(ids(active), vec!["a", "c"]);
+        assert_eq!(ids(sold), vec!["b"]);
+        assert_eq!(ids(get_collection_listings("collection", 0, 10, None)), vec!["a", "b", "c"]);
+        assert_eq!(ids(get_collection_listings("collection", 1, 1, Some(ListingStatus::Active))), vec!["c"]);
+    }
+
+    #[test]
+    fn should_list_active_listings_after_a_cursor() {
+        listed("a", 100);
+        listed("b", 100);
+        listed("c", 100);
+        set_status("b", ListingStatus::Expired);
+
+        let keys = |listings: Vec<(String, Listing)>| listings.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+
+        assert_eq!(keys(active_listings_after(None, 10)), vec!["collection:a", "collection:c"]);
+        assert_eq!(keys(active_listings_after(Some("collection:a".to_string()), 10)), vec!["collection:c"]);
+        assert_eq!(keys(active_listings_after(None, 1)), vec!["collection:a"]);
+    }
+}
+
+mod seller_and_nft_index {
+    use super::*;
+
+    #[test]
+    fn should_page_a_sellers_listings() {
+        listed("a", 100);
+        listed("b", 100);
+        let mut other = fixtures::listing("collection", "c", 100);
+        other.seller = principal(3);
+        insert_listing(other);
+
+        assert_eq!(ids(get_user_listings(&principal(2), 0, 10)), vec!["a", "b"]);
+        assert_eq!(ids(get_user_listings(&principal(2), 1, 1)), vec!["b"]);
+        assert_eq!(ids(get_user_listings(&principal(3), 0, 10)), vec!["c"]);
+    }
+
+    #[test]
+    fn should_find_a_listing_by_nft() {
+        listed("a", 100);
+
+        assert_eq!(find_listing_by_nft("collection", "nft-a").unwrap().id, "a");
+        assert!(find_listing_by_nft("collection", "nft").is_none());
+        assert!(find_listing_by_nft("other", "nft-a").is_none());
+    }
+
+    #[test]
+    fn should_forget_a_removed_listing() {
+        listed("a", 100);
+
+        remove_listing("collection", "a").unwrap();
+
+        assert!(get_user_listings(&principal(2), 0, 10).is_empty());
+        assert!(get_cheapest_listings("collection", 0, 10).is_empty());
+        assert!(find_listing_by_nft("collection", "nft-a").is_none());
+        assert_eq!(count_listings_by_status(&ListingStatus::Active), 0);
+    }
+}
+
+mod reindex {
+    use super::*;
+
+    /// Stores a listing without touching the secondary indexes.
+    fn stored_unindexed(listing: Listing) {
+        LISTINGS.with(|l| {
+            l.borrow_mut().insert(make_listing_key(&listing.collection_id, &listing.id), listing);
+        });
+    }
+
+    #[test]
+    fn should_index_listings_in_batches() {
+        for id in ["a", "b", "c"] {
+            stored_unindexed(fixtures::listing("collection", id, 100));
+        }
+        assert!(get_user_listings(&principal(2), 0, 10).is_empty());
+
+        let first = reindex_listings_batch(None, 2).unwrap();
+        assert_eq!(first.processed, 2);
+        assert_eq!(first.next_cursor.as_deref(), Some("collection:b"));
+        assert!(!first.done);
+        assert_eq!(ids(get_user_listings(&principal(2), 0, 10)), vec!["a", "b"]);
+
+        let second = reindex_listings_batch(first.next_cursor, 2).unwrap();
+        assert_eq!(second.processed, 1);
+        assert!(second.done);
+        assert_eq!(ids(get_cheapest_listings("collection", 0, 10)), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn should_drop_stale_entries_on_the_first_batch() {
+        listed("a", 100);
+        let mut repriced = get_listing("collection", "a").unwrap();
+        repriced.price = 500;
+        repriced.status = ListingStatus::Cancelled;
+        stored_unindexed(repriced);
+
+        let progress = reindex_listings_batch(None, 10).unwrap();
+
+        assert!(progress.done);
+        assert!(get_cheapest_listings("collection", 0, 10).is_empty());
+        assert_eq!(ids(get_listings_by_status(&ListingStatus::Cancelled, 0, 10)), vec!["a"]);
+        assert_eq!(count_listings_by_status(&ListingStatus::Active), 0);
+    }
+}
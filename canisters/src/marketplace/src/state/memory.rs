@@ -10,6 +10,39 @@ pub const LISTINGS_MEMORY_ID: MemoryId = MemoryId::new(1);
 pub const SALES_MEMORY_ID: MemoryId = MemoryId::new(2);
 pub const OFFERS_MEMORY_ID: MemoryId = MemoryId::new(3);
 pub const CONFIG_MEMORY_ID: MemoryId = MemoryId::new(4);
+pub const SALE_SIGNATURES_MEMORY_ID: MemoryId = MemoryId::new(6);
+pub const MODERATORS_MEMORY_ID: MemoryId = MemoryId::new(7);
+pub const COLLECTIONS_BY_CREATOR_MEMORY_ID: MemoryId = MemoryId::new(8);
+pub const COLLECTIONS_BY_BLOCKCHAIN_MEMORY_ID: MemoryId = MemoryId::new(9);
+pub const COLLECTIONS_BY_STATUS_MEMORY_ID: MemoryId = MemoryId::new(10);
+pub const LISTINGS_BY_SELLER_MEMORY_ID: MemoryId = MemoryId::new(11);
+pub const LISTINGS_BY_STATUS_MEMORY_ID: MemoryId = MemoryId::new(12);
+pub const ACTIVE_LISTINGS_BY_PRICE_MEMORY_ID: MemoryId = MemoryId::new(13);
+pub const LISTINGS_BY_EXPIRY_MEMORY_ID: MemoryId = MemoryId::new(14);
+pub const OFFERS_BY_EXPIRY_MEMORY_ID: MemoryId = MemoryId::new(15);
+pub const EVENTS_MEMORY_ID: MemoryId = MemoryId::new(16);
+pub const MIGRATION_STATE_MEMORY_ID: MemoryId = MemoryId::new(17);
+pub const EVENTS_BY_COLLECTION_MEMORY_ID: MemoryId = MemoryId::new(18);
+pub const EVENTS_BY_PRINCIPAL_MEMORY_ID: MemoryId = MemoryId::new(19);
+pub const BLOCKS_MEMORY_ID: MemoryId = MemoryId::new(20);
+pub const PAUSES_MEMORY_ID: MemoryId = MemoryId::new(21);
+pub const DEPLOYMENT_TRANSACTIONS_MEMORY_ID: MemoryId = MemoryId::new(22);
+pub const DEPLOYMENT_TRANSACTIONS_BY_COLLECTION_MEMORY_ID: MemoryId = MemoryId::new(23);
+pub const PENDING_DEPLOYMENT_TRANSACTIONS_MEMORY_ID: MemoryId = MemoryId::new(24);
+pub const OFFERS_BY_LISTING_MEMORY_ID: MemoryId = MemoryId::new(25);
+pub const SALES_BY_COLLECTION_MEMORY_ID: MemoryId = MemoryId::new(26);
+pub const SALES_BY_BUYER_MEMORY_ID: MemoryId = MemoryId::new(27);
+pub const SALES_BY_SELLER_MEMORY_ID: MemoryId = MemoryId::new(28);
+pub const SALES_BY_NFT_MEMORY_ID: MemoryId = MemoryId::new(29);
+pub const OFFERS_BY_BIDDER_MEMORY_ID: MemoryId = MemoryId::new(30);
+pub const OFFERS_BY_NFT_MEMORY_ID: MemoryId = MemoryId::new(31);
+pub const LAMPORTS_SPENT_MEMORY_ID: MemoryId = MemoryId::new(32);
+pub const LAMPORTS_DEPOSITED_MEMORY_ID: MemoryId = MemoryId::new(33);
+pub const DEPOSIT_SIGNATURES_MEMORY_ID: MemoryId = MemoryId::new(34);
+pub const LISTINGS_BY_RESERVATION_MEMORY_ID: MemoryId = MemoryId::new(35);
+pub const LISTINGS_BY_COLLECTION_STATUS_MEMORY_ID: MemoryId = MemoryId::new(36);
+pub const LISTINGS_BY_NFT_MEMORY_ID: MemoryId = MemoryId::new(37);
+pub const SALES_VOLUME_BY_CURRENCY_MEMORY_ID: MemoryId = MemoryId::new(38);
 
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
@@ -1,8 +1,54 @@
 pub mod memory;
 pub mod collections;
 pub mod listings;
+pub mod offers;
+pub mod sales;
 pub mod config;
+pub mod moderators;
+pub mod events;
+pub mod blocks;
+pub mod pause;
+pub mod deployments;
+
+#[cfg(test)]
+pub(crate) mod fixtures;
+
+use crate::migrations::BatchProgress;
+use ic_stable_structures::{StableBTreeMap, Storable};
+use std::ops::Bound;
+
+/// Most entries a paginated query returns, whatever `limit` the caller asks for.
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// Up to `limit` entries of `map` after the key `cursor`, for work split across messages.
+fn entries_after<V: Storable>(
+    map: &StableBTreeMap<String, V, memory::Memory>,
+    cursor: Option<String>,
+    limit: usize,
+) -> Vec<(String, V)> {
+    let start = cursor.map_or(Bound::Unbounded, Bound::Excluded);
+    map.range((start, Bound::Unbounded))
+        .take(limit)
+        .map(|entry| (entry.key().clone(), entry.value()))
+        .collect()
+}
+
+/// Progress of a migration batch that walked `entries` of a store in key order.
+fn batch_progress<V>(entries: &[(String, V)], limit: usize) -> BatchProgress {
+    BatchProgress {
+        processed: entries.len() as u64,
+        next_cursor: entries.last().map(|(key, _)| key.clone()),
+        done: entries.len() < limit,
+    }
+}
 
 pub use collections::*;
 pub use listings::*;
+pub use offers::*;
+pub use sales::*;
 pub use config::*;
+pub use moderators::*;
+pub use events::*;
+pub use blocks::*;
+pub use pause::*;
+pub use deployments::*;
@@ -0,0 +1,28 @@
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use super::memory::{get_memory, MODERATORS_MEMORY_ID};
+use candid::Principal;
+
+thread_local! {
+    // moderator -> time the role was granted
+    static MODERATORS: RefCell<StableBTreeMap<Principal, u64, super::memory::Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(MODERATORS_MEMORY_ID)));
+}
+
+pub fn is_moderator(principal: &Principal) -> bool {
+    MODERATORS.with(|m| m.borrow().contains_key(principal))
+}
+
+pub fn add_moderator(principal: Principal) {
+    MODERATORS.with(|m| {
+        m.borrow_mut().insert(principal, crate::time::get_current_time());
+    });
+}
+
+pub fn remove_moderator(principal: &Principal) -> bool {
+    MODERATORS.with(|m| m.borrow_mut().remove(principal).is_some())
+}
+
+pub fn get_moderators() -> Vec<Principal> {
+    MODERATORS.with(|m| m.borrow().iter().map(|entry| *entry.key()).collect())
+}
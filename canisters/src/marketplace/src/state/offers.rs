@@ -0,0 +1,390 @@
+use canister_uuid::get_uuid;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use crate::types::{
+    CreateOfferArgs, Entity, MarketplaceError, MarketplaceEvent, MarketplaceResult, Offer,
+    OfferStatus, UpdateOfferArgs,
+};
+use super::memory::{
+    get_memory, Memory, OFFERS_BY_BIDDER_MEMORY_ID, OFFERS_BY_EXPIRY_MEMORY_ID,
+    OFFERS_BY_LISTING_MEMORY_ID, OFFERS_BY_NFT_MEMORY_ID, OFFERS_MEMORY_ID,
+};
+use super::MAX_PAGE_SIZE;
+use candid::Principal;
+use crate::migrations::BatchProgress;
+
+#[cfg(test)]
+mod tests;
+
+thread_local! {
+    static OFFERS: RefCell<StableBTreeMap<String, Offer, super::memory::Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(OFFERS_MEMORY_ID)));
+
+    // Active offers, keyed "<zero-padded expires_at>:<offer_id>"
+    static OFFERS_BY_EXPIRY: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(OFFERS_BY_EXPIRY_MEMORY_ID)));
+
+    // All offers, keyed "<collection_id>:<listing_id>:<offer_id>"
+    static OFFERS_BY_LISTING: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(OFFERS_BY_LISTING_MEMORY_ID)));
+
+    // All offers, keyed "<bidder>:<offer_id>"
+    static OFFERS_BY_BIDDER: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(OFFERS_BY_BIDDER_MEMORY_ID)));
+
+    // All offers, keyed "<collection_id>:<nft_id>:<offer_id>"
+    static OFFERS_BY_NFT: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(OFFERS_BY_NFT_MEMORY_ID)));
+}
+
+fn expiry_index_key(offer: &Offer) -> String {
+    format!("{:020}:{}", offer.expires_at, offer.id)
+}
+
+fn listing_prefix(collection_id: &str, listing_id: &str) -> String {
+    format!("{}:{}:", collection_id, listing_id)
+}
+
+fn listing_index_key(offer: &Offer) -> String {
+    format!("{}{}", listing_prefix(&offer.collection_id, &offer.listing_id), offer.id)
+}
+
+fn bidder_index_key(offer: &Offer) -> String {
+    format!("{}:{}", offer.bidder.to_text(), offer.id)
+}
+
+fn nft_index_key(offer: &Offer) -> String {
+    format!("{}:{}:{}", offer.collection_id, offer.nft_id, offer.id)
+}
+
+/// Indexes every offer in the indexes kept for all offers, whatever their status.
+fn index_offer(offer: &Offer) {
+    let keys = [
+        (&OFFERS_BY_LISTING, listing_index_key(offer)),
+        (&OFFERS_BY_BIDDER, bidder_index_key(offer)),
+        (&OFFERS_BY_NFT, nft_index_key(offer)),
+    ];
+    for (index, key) in keys {
+        index.with(|i| {
+            i.borrow_mut().insert(key, ());
+        });
+    }
+}
+
+/// Migration step: rebuilds the offer indexes, `limit` offers per batch. The first
+/// batch clears them; the cursor is the last offer id indexed.
+pub fn reindex_offers_batch(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    if cursor.is_none() {
+        for index in [&OFFERS_BY_EXPIRY, &OFFERS_BY_LISTING, &OFFERS_BY_BIDDER, &OFFERS_BY_NFT] {
+            index.with(|i| i.borrow_mut().clear_new());
+        }
+    }
+
+    let offers = OFFERS.with(|o| super::entries_after(&o.borrow(), cursor, limit));
+    for (_, offer) in offers.iter() {
+        if offer.status == OfferStatus::Active {
+            OFFERS_BY_EXPIRY.with(|i| {
+                i.borrow_mut().insert(expiry_index_key(offer), ());
+            });
+        }
+        index_offer(offer);
+    }
+    Ok(super::batch_progress(&offers, limit))
+}
+
+/// Offer ids under `prefix` in an index, paginated.
+fn index_page(
+    index: &'static std::thread::LocalKey<RefCell<StableBTreeMap<String, (), Memory>>>,
+    prefix: &str,
+    page: u32,
+    limit: u32,
+) -> Vec<String> {
+    index.with(|i| {
+        i.borrow()
+            .range(prefix.to_string()..)
+            .take_while(|entry| entry.key().starts_with(prefix))
+            .skip(page.saturating_mul(limit) as usize)
+            .take(limit as usize)
+            .filter_map(|entry| entry.key().rsplit_once(':').map(|(_, offer_id)| offer_id.to_string()))
+            .collect()
+    })
+}
+
+fn listing_offer_ids(collection_id: &str, listing_id: &str) -> Vec<String> {
+    index_page(&OFFERS_BY_LISTING, &listing_prefix(collection_id, listing_id), 0, u32::MAX)
+}
+
+fn offers_by_ids(offer_ids: Vec<String>, now: u64) -> Vec<Offer> {
+    OFFERS.with(|o| {
+        let offers = o.borrow();
+        offer_ids
+            .into_iter()
+            .filter_map(|offer_id| offers.get(&offer_id))
+            .map(|offer| with_effective_status(offer, now))
+            .collect()
+    })
+}
+
+/// Offers past their `expires_at` are reported as `Expired` even before
+/// anything rewrites the stored record.
+fn with_effective_status(mut offer: Offer, now: u64) -> Offer {
+    if offer.status == OfferStatus::Active && offer.expires_at <= now {
+        offer.status = OfferStatus::Expired;
+    }
+    offer
+}
+
+pub async fn add_offer(
+    args: CreateOfferArgs,
+    bidder: Principal,
+    nft_id: String,
+) -> MarketplaceResult<String> {
+    let offer_id = get_uuid().await;
+    let now = crate::time::get_current_time();
+
+    insert_offer(Offer {
+        id: offer_id.clone(),
+        listing_id: args.listing_id,
+        collection_id: args.collection_id,
+        nft_id,
+        bidder,
+        price: args.price,
+        currency: args.currency,
+        status: OfferStatus::Active,
+        expires_at: args.expires_at,
+        created_at: now,
+        updated_at: now,
+    });
+
+    Ok(offer_id)
+}
+
+/// Stores a new offer and records that it was made.
+pub(crate) fn insert_offer(offer: Offer) {
+    let event = MarketplaceEvent::OfferMade {
+        offer_id: offer.id.clone(),
+        listing_id: offer.listing_id.clone(),
+        collection_id: offer.collection_id.clone(),
+        bidder: offer.bidder,
+        price: offer.price,
+        currency: offer.currency.clone(),
+    };
+
+    OFFERS_BY_EXPIRY.with(|i| {
+        i.borrow_mut().insert(expiry_index_key(&offer), ());
+    });
+    index_offer(&offer);
+    OFFERS.with(|o| {
+        o.borrow_mut().insert(offer.id.clone(), offer);
+    });
+    super::events::record_event(event);
+}
+
+pub fn get_offer(offer_id: &str) -> Option<Offer> {
+    let now = crate::time::get_current_time();
+    OFFERS.with(|o| o.borrow().get(&offer_id.to_string()))
+        .map(|offer| with_effective_status(offer, now))
+}
+
+pub fn get_listing_offers(
+    collection_id: &str,
+    listing_id: &str,
+    status: Option<OfferStatus>,
+) -> Vec<Offer> {
+    let offer_ids = listing_offer_ids(collection_id, listing_id);
+    offers_by_ids(offer_ids, crate::time::get_current_time())
+        .into_iter()
+        .filter(|offer| status.as_ref().map_or(true, |s| &offer.status == s))
+        .collect()
+}
+
+pub fn get_bidder_offers(bidder: &Principal, page: u32, limit: u32) -> Vec<Offer> {
+    let prefix = format!("{}:", bidder.to_text());
+    let offer_ids = index_page(&OFFERS_BY_BIDDER, &prefix, page, limit.min(MAX_PAGE_SIZE));
+    offers_by_ids(offer_ids, crate::time::get_current_time())
+}
+
+/// The first [`MAX_PAGE_SIZE`] offers on an NFT, in index order.
+pub fn get_nft_offers(collection_id: &str, nft_id: &str) -> Vec<Offer> {
+    let prefix = format!("{}:{}:", collection_id, nft_id);
+    let offer_ids = index_page(&OFFERS_BY_NFT, &prefix, 0, MAX_PAGE_SIZE);
+    offers_by_ids(offer_ids, crate::time::get_current_time())
+        .into_iter()
+        // An id containing ':' can share the prefix of another NFT's entries
+        .filter(|offer| offer.nft_id == nft_id)
+        .collect()
+}
+
+pub fn update_offer_status(args: UpdateOfferArgs) -> MarketplaceResult<()> {
+    let now = crate::time::get_current_time();
+
+    OFFERS.with(|o| {
+        let mut offers = o.borrow_mut();
+
+        if let Some(offer) = offers.get(&args.offer_id) {
+            let mut offer = with_effective_status(offer, now);
+            if offer.status != OfferStatus::Active {
+                return Err(MarketplaceError::invalid_state(format!(
+                    "Offer is no longer active ({:?})",
+                    offer.status
+                )));
+            }
+            offer.status = args.status;
+            offer.updated_at = now;
+            OFFERS_BY_EXPIRY.with(|i| {
+                i.borrow_mut().remove(&expiry_index_key(&offer));
+            });
+
+            super::events::record_event(MarketplaceEvent::OfferStatusChanged {
+                offer_id: offer.id.clone(),
+                listing_id: offer.listing_id.clone(),
+                collection_id: offer.collection_id.clone(),
+                bidder: offer.bidder,
+                status: offer.status.clone(),
+            });
+            offers.insert(args.offer_id, offer);
+            Ok(())
+        } else {
+            Err(MarketplaceError::not_found(Entity::Offer, args.offer_id))
+        }
+    })
+}
+
+/// How long the bidder has to complete the purchase once their offer is accepted.
+const RESERVATION_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+/// Accepts an active offer: the listing is reserved for the bidder at the offered
+/// price for `RESERVATION_WINDOW_NANOS` and the listing's other open offers are rejected.
+pub fn accept_offer(offer_id: &str) -> MarketplaceResult<()> {
+    let offer = get_offer(offer_id).ok_or_else(|| MarketplaceError::not_found(Entity::Offer, offer_id))?;
+    if offer.status != OfferStatus::Active {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Offer is no longer active ({:?})",
+            offer.status
+        )));
+    }
+
+    let reserved_until = crate::time::get_current_time().saturating_add(RESERVATION_WINDOW_NANOS);
+    super::listings::reserve_listing(
+        &offer.collection_id,
+        &offer.listing_id,
+        offer.bidder,
+        offer.price,
+        reserved_until,
+    )?;
+    update_offer_status(UpdateOfferArgs {
+        offer_id: offer.id.clone(),
+        status: OfferStatus::Accepted,
+    })?;
+    close_listing_offers(&offer.collection_id, &offer.listing_id, OfferStatus::Rejected, Some(&offer.id));
+    Ok(())
+}
+
+/// Moves every open offer on a listing, except `keep`, to `status`. Used when the
+/// listing leaves `Active` or one of its offers is accepted.
+pub fn close_listing_offers(collection_id: &str, listing_id: &str, status: OfferStatus, keep: Option<&str>) {
+    move_listing_offers(collection_id, listing_id, OfferStatus::Active, status, keep);
+}
+
+/// Moves the offers on a listing that are in `from` to `status`, e.g. the accepted
+/// offer to `Expired` when its reservation lapses.
+pub fn close_listing_offers_with_status(
+    collection_id: &str,
+    listing_id: &str,
+    from: OfferStatus,
+    status: OfferStatus,
+) {
+    move_listing_offers(collection_id, listing_id, from, status, None);
+}
+
+fn move_listing_offers(
+    collection_id: &str,
+    listing_id: &str,
+    from: OfferStatus,
+    status: OfferStatus,
+    keep: Option<&str>,
+) {
+    let now = crate::time::get_current_time();
+    let offer_ids = listing_offer_ids(collection_id, listing_id);
+
+    OFFERS.with(|o| {
+        let mut offers = o.borrow_mut();
+        for offer_id in offer_ids {
+            if keep == Some(offer_id.as_str()) {
+                continue;
+            }
+            let Some(mut offer) = offers.get(&offer_id) else {
+                continue;
+            };
+            if offer.status != from {
+                continue;
+            }
+            OFFERS_BY_EXPIRY.with(|i| {
+                i.borrow_mut().remove(&expiry_index_key(&offer));
+            });
+            offer.status = status.clone();
+            offer.updated_at = now;
+
+            let event = match status {
+                OfferStatus::Expired => MarketplaceEvent::OfferExpired {
+                    offer_id: offer.id.clone(),
+                    listing_id: offer.listing_id.clone(),
+                    collection_id: offer.collection_id.clone(),
+                    bidder: offer.bidder,
+                },
+                _ => MarketplaceEvent::OfferStatusChanged {
+                    offer_id: offer.id.clone(),
+                    listing_id: offer.listing_id.clone(),
+                    collection_id: offer.collection_id.clone(),
+                    bidder: offer.bidder,
+                    status: offer.status.clone(),
+                },
+            };
+            super::events::record_event(event);
+            offers.insert(offer_id, offer);
+        }
+    });
+}
+
+/// Marks up to `max` active offers whose `expires_at` has passed as `Expired` and
+/// returns them.
+pub fn expire_due_offers(now: u64, max: usize) -> Vec<Offer> {
+    let due_keys: Vec<String> = OFFERS_BY_EXPIRY.with(|i| {
+        i.borrow()
+            .iter()
+            .take(max)
+            .take_while(|entry| {
+                entry.key()
+                    .split_once(':')
+                    .and_then(|(expires_at, _)| expires_at.parse::<u64>().ok())
+                    .is_some_and(|expires_at| expires_at <= now)
+            })
+            .map(|entry| entry.key().clone())
+            .collect()
+    });
+
+    OFFERS.with(|o| {
+        let mut offers = o.borrow_mut();
+        due_keys
+            .into_iter()
+            .filter_map(|index_key| {
+                OFFERS_BY_EXPIRY.with(|i| i.borrow_mut().remove(&index_key));
+                let offer_id = index_key.split_once(':').map(|(_, id)| id.to_string())?;
+                let mut offer = offers.get(&offer_id)?;
+                if offer.status != OfferStatus::Active {
+                    return None;
+                }
+                offer.status = OfferStatus::Expired;
+                offer.updated_at = now;
+                super::events::record_event(MarketplaceEvent::OfferExpired {
+                    offer_id: offer.id.clone(),
+                    listing_id: offer.listing_id.clone(),
+                    collection_id: offer.collection_id.clone(),
+                    bidder: offer.bidder,
+                });
+                offers.insert(offer_id, offer.clone());
+                Some(offer)
+            })
+            .collect()
+    })
+}
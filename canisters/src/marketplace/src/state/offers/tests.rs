@@ -0,0 +1,187 @@
+use super::*;
+use crate::state::fixtures::{self, principal, HOUR_NANOS};
+use crate::state::listings::{get_listing, insert_listing, release_due_reservations, update_listing};
+use crate::time::{get_current_time, set_current_time};
+use crate::types::{Listing, ListingStatus, UpdateListingArgs};
+
+/// A listing asking 1 000 with an offer `offer-<i>` from `principal(10 + i)` per price.
+fn listing_with_offers(prices: &[u64]) -> Listing {
+    let listing = fixtures::listing("collection", "listing", 1_000);
+    insert_listing(listing.clone());
+    for (i, price) in prices.iter().enumerate() {
+        insert_offer(fixtures::offer(&format!("offer-{}", i), &listing, principal(10 + i as u8), *price));
+    }
+    listing
+}
+
+fn status(offer_id: &str) -> OfferStatus {
+    get_offer(offer_id).unwrap().status
+}
+
+fn set_status(offer_id: &str, status: OfferStatus) -> MarketplaceResult<()> {
+    update_offer_status(UpdateOfferArgs {
+        offer_id: offer_id.to_string(),
+        status,
+    })
+}
+
+mod accept_offer {
+    use super::*;
+
+    #[test]
+    fn should_reserve_the_listing_at_the_offered_price() {
+        let listing = listing_with_offers(&[800]);
+
+        accept_offer("offer-0").unwrap();
+
+        let reserved = get_listing(&listing.collection_id, &listing.id).unwrap();
+        assert_eq!(reserved.reserved_for, Some(principal(10)));
+        assert_eq!(reserved.reserved_price, Some(800));
+        assert_eq!(reserved.reserved_until, Some(get_current_time() + RESERVATION_WINDOW_NANOS));
+        assert_eq!(reserved.price, 1_000);
+        assert_eq!(reserved.sale_price(), 800);
+        assert_eq!(status("offer-0"), OfferStatus::Accepted);
+    }
+
+    #[test]
+    fn should_reject_the_other_open_offers() {
+        listing_with_offers(&[800, 700, 600]);
+        set_status("offer-2", OfferStatus::Cancelled).unwrap();
+
+        accept_offer("offer-0").unwrap();
+
+        assert_eq!(status("offer-0"), OfferStatus::Accepted);
+        assert_eq!(status("offer-1"), OfferStatus::Rejected);
+        assert_eq!(status("offer-2"), OfferStatus::Cancelled);
+    }
+
+    #[test]
+    fn should_refuse_an_expired_offer() {
+        let listing = listing_with_offers(&[800]);
+        set_current_time(get_current_time() + 2 * HOUR_NANOS);
+
+        assert!(matches!(accept_offer("offer-0"), Err(MarketplaceError::InvalidState(_))));
+        assert_eq!(get_listing(&listing.collection_id, &listing.id).unwrap().reserved_for, None);
+    }
+
+    #[test]
+    fn should_refuse_when_the_listing_is_not_active() {
+        let listing = fixtures::listing("collection", "listing", 1_000);
+        insert_listing(Listing {
+            status: ListingStatus::Sold,
+            ..listing.clone()
+        });
+        insert_offer(fixtures::offer("offer-0", &listing, principal(10), 800));
+
+        assert!(matches!(accept_offer("offer-0"), Err(MarketplaceError::InvalidState(_))));
+        assert_eq!(status("offer-0"), OfferStatus::Active);
+    }
+
+    #[test]
+    fn should_refuse_an_unknown_offer() {
+        assert!(matches!(accept_offer("missing"), Err(MarketplaceError::NotFound { .. })));
+    }
+}
+
+mod update_offer_status {
+    use super::*;
+
+    #[test]
+    fn should_reject_an_active_offer() {
+        listing_with_offers(&[800]);
+
+        set_status("offer-0", OfferStatus::Rejected).unwrap();
+
+        assert_eq!(status("offer-0"), OfferStatus::Rejected);
+        // No longer due for expiry
+        assert!(expire_due_offers(get_current_time() + 2 * HOUR_NANOS, 10).is_empty());
+    }
+
+    #[test]
+    fn should_refuse_a_settled_offer() {
+        listing_with_offers(&[800]);
+        set_status("offer-0", OfferStatus::Rejected).unwrap();
+
+        assert!(matches!(
+            set_status("offer-0", OfferStatus::Accepted),
+            Err(MarketplaceError::InvalidState(_))
+        ));
+        assert_eq!(status("offer-0"), OfferStatus::Rejected);
+    }
+
+    #[test]
+    fn should_refuse_an_unknown_offer() {
+        assert!(matches!(
+            set_status("missing", OfferStatus::Rejected),
+            Err(MarketplaceError::NotFound { .. })
+        ));
+    }
+}
+
+mod reservations {
+    use super::*;
+
+    #[test]
+    fn should_hold_the_reservation_until_it_lapses() {
+        let listing = listing_with_offers(&[800]);
+        accept_offer("offer-0").unwrap();
+
+        let released = release_due_reservations(get_current_time() + RESERVATION_WINDOW_NANOS - 1, 10);
+
+        assert!(released.is_empty());
+        let reserved = get_listing(&listing.collection_id, &listing.id).unwrap();
+        assert_eq!(reserved.reserved_for, Some(principal(10)));
+        assert_eq!(status("offer-0"), OfferStatus::Accepted);
+    }
+
+    #[test]
+    fn should_reopen_the_listing_and_expire_the_accepted_offer() {
+        let listing = listing_with_offers(&[800]);
+        accept_offer("offer-0").unwrap();
+
+        let released = release_due_reservations(get_current_time() + RESERVATION_WINDOW_NANOS, 10);
+
+        assert_eq!(released.len(), 1);
+        let reopened = get_listing(&listing.collection_id, &listing.id).unwrap();
+        assert_eq!(reopened.status, ListingStatus::Active);
+        assert_eq!(reopened.reserved_for, None);
+        assert_eq!(reopened.reserved_price, None);
+        assert_eq!(reopened.reserved_until, None);
+        assert_eq!(reopened.sale_price(), 1_000);
+        assert_eq!(get_offer("offer-0").unwrap().status, OfferStatus::Expired);
+    }
+
+    #[test]
+    fn should_release_each_reservation_once() {
+        listing_with_offers(&[800]);
+        accept_offer("offer-0").unwrap();
+        let lapsed = get_current_time() + RESERVATION_WINDOW_NANOS;
+
+        assert_eq!(release_due_reservations(lapsed, 10).len(), 1);
+        assert!(release_due_reservations(lapsed, 10).is_empty());
+    }
+}
+
+mod closing_the_listing {
+    use super::*;
+
+    #[test]
+    fn should_cancel_open_offers_when_the_listing_is_cancelled() {
+        let listing = listing_with_offers(&[800, 700]);
+        set_status("offer-1", OfferStatus::Rejected).unwrap();
+
+        update_listing(
+            UpdateListingArgs {
+                listing_id: listing.id.clone(),
+                price: None,
+                status: Some(ListingStatus::Cancelled),
+            },
+            &listing.collection_id,
+        )
+        .unwrap();
+
+        assert_eq!(status("offer-0"), OfferStatus::Cancelled);
+        assert_eq!(status("offer-1"), OfferStatus::Rejected);
+        assert!(get_listing_offers(&listing.collection_id, &listing.id, Some(OfferStatus::Active)).is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+use ic_stable_structures::StableCell;
+use std::cell::RefCell;
+use candid::Principal;
+use super::memory::{get_memory, PAUSES_MEMORY_ID};
+use crate::types::{Pause, PauseStatus, PauseTarget};
+
+#[cfg(test)]
+mod tests;
+
+thread_local! {
+    static PAUSES: RefCell<StableCell<PauseStatus, super::memory::Memory>> = RefCell::new(
+        StableCell::init(get_memory(PAUSES_MEMORY_ID), PauseStatus::default())
+    );
+}
+
+/// Pauses in effect now. Pauses past their `resume_at` are left in storage and
+/// filtered out here, so auto-resume needs no timer.
+pub fn get_pause_status() -> PauseStatus {
+    PAUSES.with(|p| p.borrow().get().clone()).active(crate::time::get_current_time())
+}
+
+pub fn get_pause(target: PauseTarget) -> Option<Pause> {
+    get_pause_status().get(target).cloned()
+}
+
+pub fn pause(target: PauseTarget, reason: String, paused_by: Principal, resume_at: Option<u64>) {
+    let mut status = get_pause_status();
+    *status.slot(target) = Some(Pause {
+        reason,
+        paused_by,
+        paused_at: crate::time::get_current_time(),
+        resume_at,
+    });
+    PAUSES.with(|p| p.borrow_mut().set(status));
+}
+
+/// Lifts the pause. Returns false if the target was not paused.
+pub fn resume(target: PauseTarget) -> bool {
+    let mut status = get_pause_status();
+    let was_paused = status.slot(target).take().is_some();
+    PAUSES.with(|p| p.borrow_mut().set(status));
+    was_paused
+}
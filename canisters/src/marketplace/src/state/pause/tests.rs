@@ -0,0 +1,98 @@
+use super::*;
+use crate::guards::ensure_not_paused;
+use crate::state::fixtures::{principal, HOUR_NANOS};
+use crate::time::{get_current_time, set_current_time};
+use crate::types::MarketplaceError;
+
+fn pause_sales(resume_at: Option<u64>) {
+    pause(PauseTarget::Sales, "exploit under investigation".to_string(), principal(1), resume_at);
+}
+
+mod pause {
+    use super::*;
+
+    #[test]
+    fn should_pause_only_the_target() {
+        pause_sales(None);
+
+        let status = get_pause_status();
+        assert!(status.sales.is_some());
+        assert_eq!(status.listings, None);
+        assert_eq!(status.offers, None);
+        assert_eq!(status.solana_signing, None);
+    }
+
+    #[test]
+    fn should_record_who_paused_and_why() {
+        pause_sales(None);
+
+        let pause = get_pause(PauseTarget::Sales).unwrap();
+        assert_eq!(pause.reason, "exploit under investigation");
+        assert_eq!(pause.paused_by, principal(1));
+        assert_eq!(pause.paused_at, get_current_time());
+    }
+
+    #[test]
+    fn should_refuse_the_paused_operation() {
+        let resume_at = get_current_time() + HOUR_NANOS;
+        pause_sales(Some(resume_at));
+
+        assert_eq!(
+            ensure_not_paused(PauseTarget::Sales),
+            Err(MarketplaceError::Paused {
+                target: PauseTarget::Sales,
+                reason: "exploit under investigation".to_string(),
+                resume_at: Some(resume_at),
+            })
+        );
+        assert_eq!(ensure_not_paused(PauseTarget::SolanaSigning), Ok(()));
+    }
+}
+
+mod resume {
+    use super::*;
+
+    #[test]
+    fn should_lift_the_pause() {
+        pause_sales(None);
+
+        assert!(resume(PauseTarget::Sales));
+
+        assert_eq!(get_pause(PauseTarget::Sales), None);
+        assert_eq!(ensure_not_paused(PauseTarget::Sales), Ok(()));
+    }
+
+    #[test]
+    fn should_report_a_target_that_was_not_paused() {
+        pause_sales(None);
+
+        assert!(!resume(PauseTarget::Offers));
+        assert!(get_pause(PauseTarget::Sales).is_some());
+    }
+
+    #[test]
+    fn should_resume_by_itself_at_resume_at() {
+        let resume_at = get_current_time() + HOUR_NANOS;
+        pause_sales(Some(resume_at));
+
+        set_current_time(resume_at - 1);
+        assert!(get_pause(PauseTarget::Sales).is_some());
+
+        set_current_time(resume_at);
+        assert_eq!(get_pause(PauseTarget::Sales), None);
+        assert_eq!(get_pause_status(), PauseStatus::default());
+    }
+
+    #[test]
+    fn should_not_resurrect_a_lapsed_pause() {
+        let resume_at = get_current_time() + HOUR_NANOS;
+        pause_sales(Some(resume_at));
+        set_current_time(resume_at);
+
+        pause(PauseTarget::Offers, "maintenance".to_string(), principal(1), None);
+
+        assert_eq!(get_pause(PauseTarget::Sales), None);
+        assert!(!resume(PauseTarget::Sales));
+        assert!(get_pause(PauseTarget::Offers).is_some());
+    }
+}
@@ -0,0 +1,275 @@
+use canister_uuid::get_uuid;
+use ic_stable_structures::StableBTreeMap;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use crate::types::{
+    Entity, Listing, ListingStatus, MarketplaceError, MarketplaceEvent, MarketplaceResult,
+    RecordSaleArgs, Sale, UpdateListingArgs,
+};
+use super::memory::{
+    get_memory, Memory, SALES_BY_BUYER_MEMORY_ID, SALES_BY_COLLECTION_MEMORY_ID, SALES_BY_NFT_MEMORY_ID,
+    SALES_BY_SELLER_MEMORY_ID, SALES_MEMORY_ID, SALES_VOLUME_BY_CURRENCY_MEMORY_ID, SALE_SIGNATURES_MEMORY_ID,
+};
+use super::MAX_PAGE_SIZE;
+use candid::Principal;
+use crate::migrations::BatchProgress;
+
+#[cfg(test)]
+mod tests;
+
+type Index = std::thread::LocalKey<RefCell<StableBTreeMap<String, (), Memory>>>;
+
+thread_local! {
+    static SALES: RefCell<StableBTreeMap<String, Sale, super::memory::Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALES_MEMORY_ID)));
+
+    // tx_signature -> sale id, so the same transaction cannot be recorded twice
+    static SALE_SIGNATURES: RefCell<StableBTreeMap<String, String, super::memory::Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALE_SIGNATURES_MEMORY_ID)));
+
+    // Secondary indexes, keyed "<value>:<sale_id>"
+    static SALES_BY_COLLECTION: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALES_BY_COLLECTION_MEMORY_ID)));
+
+    static SALES_BY_BUYER: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALES_BY_BUYER_MEMORY_ID)));
+
+    static SALES_BY_SELLER: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALES_BY_SELLER_MEMORY_ID)));
+
+    // Keyed "<collection_id>:<nft_id>:<sale_id>"
+    static SALES_BY_NFT: RefCell<StableBTreeMap<String, (), Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALES_BY_NFT_MEMORY_ID)));
+
+    // currency -> total sale price, so metrics do not scan the ledger
+    static SALES_VOLUME_BY_CURRENCY: RefCell<StableBTreeMap<String, u128, Memory>> =
+        RefCell::new(StableBTreeMap::init(get_memory(SALES_VOLUME_BY_CURRENCY_MEMORY_ID)));
+}
+
+/// Schema version whose migration backfills the per-currency volume from existing
+/// sales. Until it has run, new sales are left to the backfill.
+pub const SALES_VOLUME_SCHEMA_VERSION: u32 = 7;
+
+fn sales_volume_enabled() -> bool {
+    crate::migrations::schema_version() >= SALES_VOLUME_SCHEMA_VERSION
+}
+
+fn add_sales_volume(currency: &str, price: u64) {
+    SALES_VOLUME_BY_CURRENCY.with(|v| {
+        let mut volumes = v.borrow_mut();
+        let volume = volumes.get(&currency.to_string()).unwrap_or_default();
+        volumes.insert(currency.to_string(), volume.saturating_add(price as u128));
+    });
+}
+
+fn index_sale(sale: &Sale) {
+    let keys = [
+        (&SALES_BY_COLLECTION, format!("{}:{}", sale.collection_id, sale.id)),
+        (&SALES_BY_BUYER, format!("{}:{}", sale.buyer.to_text(), sale.id)),
+        (&SALES_BY_SELLER, format!("{}:{}", sale.seller.to_text(), sale.id)),
+        (&SALES_BY_NFT, format!("{}:{}:{}", sale.collection_id, sale.nft_id, sale.id)),
+    ];
+    for (index, key) in keys {
+        index.with(|i| {
+            i.borrow_mut().insert(key, ());
+        });
+    }
+}
+
+/// Migration step: rebuilds the sale indexes and the signature dedup map, `limit`
+/// sales per batch. The first batch clears the indexes; the cursor is the last sale
+/// id indexed.
+pub fn reindex_sales_batch(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    if cursor.is_none() {
+        for index in [&SALES_BY_COLLECTION, &SALES_BY_BUYER, &SALES_BY_SELLER, &SALES_BY_NFT] {
+            index.with(|i| i.borrow_mut().clear_new());
+        }
+    }
+
+    let sales = SALES.with(|s| super::entries_after(&s.borrow(), cursor, limit));
+    for (_, sale) in sales.iter() {
+        index_sale(sale);
+        SALE_SIGNATURES.with(|s| {
+            let mut signatures = s.borrow_mut();
+            if !signatures.contains_key(&sale.tx_signature) {
+                signatures.insert(sale.tx_signature.clone(), sale.id.clone());
+            }
+        });
+    }
+    Ok(super::batch_progress(&sales, limit))
+}
+
+/// Sale ids start with the zero-padded sale time so the ledger iterates in
+/// chronological order.
+fn make_sale_id(sold_at: u64, suffix: &str) -> String {
+    format!("{:020}-{}", sold_at, suffix)
+}
+
+pub fn is_sale_recorded(tx_signature: &str) -> bool {
+    SALE_SIGNATURES.with(|s| s.borrow().contains_key(&tx_signature.to_string()))
+}
+
+/// Marks the listing as sold, appends the sale to the ledger and updates the
+/// collection's volume, floor price and listed count.
+///
+/// `verified` is the listing the sale transaction was checked against. The sale is
+/// refused if the listing was repriced, re-reserved or closed since then.
+pub async fn record_sale(args: RecordSaleArgs, verified: &Listing) -> MarketplaceResult<String> {
+    let uuid = get_uuid().await;
+    insert_sale(args, verified, &uuid)
+}
+
+/// The part of [`record_sale`] after the sale id is drawn. It runs without awaiting,
+/// so the checks cannot race another sale.
+fn insert_sale(args: RecordSaleArgs, verified: &Listing, uuid: &str) -> MarketplaceResult<String> {
+    // Taken after the await, so the id sorts after every sale already in the ledger
+    let sold_at = crate::time::get_current_time();
+    let sale_id = make_sale_id(sold_at, uuid);
+
+    let listing = super::listings::get_listing(&args.collection_id, &args.listing_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Listing, &args.listing_id))?;
+    if listing.status != ListingStatus::Active {
+        return Err(MarketplaceError::invalid_state("Listing is not active"));
+    }
+    if listing.sale_price() != verified.sale_price()
+        || listing.status != verified.status
+        || listing.seller != verified.seller
+        || listing.seller_address != verified.seller_address
+        || listing.reserved_for != verified.reserved_for
+    {
+        return Err(MarketplaceError::invalid_state(
+            "Listing changed while the sale transaction was being verified",
+        ));
+    }
+    if listing.reserved_for.is_some_and(|bidder| bidder != args.buyer) {
+        return Err(MarketplaceError::invalid_state(
+            "Listing is reserved for the bidder whose offer was accepted",
+        ));
+    }
+    if is_sale_recorded(&args.tx_signature) {
+        return Err(MarketplaceError::invalid_state("Sale already recorded for this transaction"));
+    }
+
+    super::listings::update_listing(
+        UpdateListingArgs {
+            listing_id: listing.id.clone(),
+            price: None,
+            status: Some(ListingStatus::Sold),
+        },
+        &listing.collection_id,
+    )?;
+
+    let sale = Sale {
+        id: sale_id.clone(),
+        listing_id: listing.id,
+        collection_id: listing.collection_id.clone(),
+        nft_id: listing.nft_id,
+        blockchain: listing.blockchain,
+        seller: listing.seller,
+        buyer: args.buyer,
+        price: verified.sale_price(),
+        currency: listing.currency,
+        tx_signature: args.tx_signature.clone(),
+        sold_at,
+    };
+
+    let event = MarketplaceEvent::ItemSold {
+        sale_id: sale_id.clone(),
+        listing_id: sale.listing_id.clone(),
+        collection_id: sale.collection_id.clone(),
+        nft_id: sale.nft_id.clone(),
+        seller: sale.seller,
+        buyer: sale.buyer,
+        price: sale.price,
+        currency: sale.currency.clone(),
+        tx_signature: sale.tx_signature.clone(),
+    };
+
+    let price = sale.price;
+    if sales_volume_enabled() {
+        add_sales_volume(&sale.currency, price);
+    }
+    index_sale(&sale);
+    SALES.with(|s| {
+        s.borrow_mut().insert(sale_id.clone(), sale);
+    });
+    SALE_SIGNATURES.with(|s| {
+        s.borrow_mut().insert(args.tx_signature, sale_id.clone());
+    });
+    super::events::record_event(event);
+
+    if let Some(collection) = super::collections::get_collection(&listing.collection_id) {
+        super::collections::update_collection_stats(
+            &listing.collection_id,
+            None,
+            Some(collection.total_volume.saturating_add(price)),
+            None,
+            None,
+        )?;
+    }
+
+    Ok(sale_id)
+}
+
+pub fn get_sale(sale_id: &str) -> Option<Sale> {
+    SALES.with(|s| s.borrow().get(&sale_id.to_string()))
+}
+
+/// Sales under `prefix` in an index, oldest first, at most [`MAX_PAGE_SIZE`] per page.
+fn index_page(index: &'static Index, prefix: &str, page: u32, limit: u32) -> Vec<Sale> {
+    let prefix = format!("{}:", prefix);
+    let limit = limit.min(MAX_PAGE_SIZE);
+    let sale_ids: Vec<String> = index.with(|i| {
+        i.borrow()
+            .range(prefix.clone()..)
+            .take_while(|entry| entry.key().starts_with(&prefix))
+            .skip(page.saturating_mul(limit) as usize)
+            .take(limit as usize)
+            .filter_map(|entry| entry.key().rsplit_once(':').map(|(_, sale_id)| sale_id.to_string()))
+            .collect()
+    });
+    SALES.with(|s| {
+        let sales = s.borrow();
+        sale_ids.into_iter().filter_map(|sale_id| sales.get(&sale_id)).collect()
+    })
+}
+
+pub fn sales_volume_by_currency() -> BTreeMap<String, u128> {
+    SALES_VOLUME_BY_CURRENCY.with(|v| {
+        v.borrow()
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value()))
+            .collect()
+    })
+}
+
+/// Migration step: adds existing sales to the per-currency volume. The cursor is the
+/// last sale id counted; sales recorded meanwhile sort after it and are counted too.
+pub fn backfill_sales_volume_batch(cursor: Option<String>, limit: usize) -> Result<BatchProgress, String> {
+    let sales = SALES.with(|s| super::entries_after(&s.borrow(), cursor, limit));
+    for (_, sale) in sales.iter() {
+        add_sales_volume(&sale.currency, sale.price);
+    }
+    Ok(super::batch_progress(&sales, limit))
+}
+
+pub fn get_collection_sales(collection_id: &str, page: u32, limit: u32) -> Vec<Sale> {
+    index_page(&SALES_BY_COLLECTION, collection_id, page, limit)
+}
+
+pub fn get_buyer_sales(buyer: &Principal, page: u32, limit: u32) -> Vec<Sale> {
+    index_page(&SALES_BY_BUYER, &buyer.to_text(), page, limit)
+}
+
+pub fn get_seller_sales(seller: &Principal, page: u32, limit: u32) -> Vec<Sale> {
+    index_page(&SALES_BY_SELLER, &seller.to_text(), page, limit)
+}
+
+/// The first [`MAX_PAGE_SIZE`] sales of an NFT, oldest first.
+pub fn get_nft_sales(collection_id: &str, nft_id: &str) -> Vec<Sale> {
+    // An id containing ':' can share the prefix of another NFT's entries
+    index_page(&SALES_BY_NFT, &format!("{}:{}", collection_id, nft_id), 0, MAX_PAGE_SIZE)
+        .into_iter()
+        .filter(|sale| sale.nft_id == nft_id)
+        .collect()
+}
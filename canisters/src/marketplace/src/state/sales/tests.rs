@@ -0,0 +1,184 @@
+use super::*;
+use crate::migrations::init_schema_version;
+use crate::state::collections::{get_collection, insert_collection};
+use crate::state::fixtures::{self, principal, HOUR_NANOS};
+use crate::state::listings::{get_listing, insert_listing, reserve_listing, update_listing};
+use crate::time::{get_current_time, set_current_time};
+
+fn listed(listing_id: &str, price: u64) -> Listing {
+    let listing = fixtures::listing("collection", listing_id, price);
+    insert_listing(listing.clone());
+    listing
+}
+
+fn sale_args(listing: &Listing, buyer: Principal, tx_signature: &str) -> RecordSaleArgs {
+    RecordSaleArgs {
+        collection_id: listing.collection_id.clone(),
+        listing_id: listing.id.clone(),
+        buyer,
+        buyer_address: None,
+        tx_signature: tx_signature.to_string(),
+    }
+}
+
+fn listing_status(listing: &Listing) -> ListingStatus {
+    get_listing(&listing.collection_id, &listing.id).unwrap().status
+}
+
+mod insert_sale {
+    use super::*;
+
+    #[test]
+    fn should_record_the_sale_and_mark_the_listing_sold() {
+        insert_collection(fixtures::collection("collection"));
+        let listing = listed("listing", 1_000);
+
+        let sale_id = insert_sale(sale_args(&listing, principal(20), "tx-1"), &listing, "uuid").unwrap();
+
+        let sale = get_sale(&sale_id).unwrap();
+        assert_eq!(sale.listing_id, listing.id);
+        assert_eq!(sale.nft_id, listing.nft_id);
+        assert_eq!(sale.seller, listing.seller);
+        assert_eq!(sale.buyer, principal(20));
+        assert_eq!(sale.price, 1_000);
+        assert_eq!(sale.tx_signature, "tx-1");
+        assert_eq!(sale.sold_at, get_current_time());
+        assert_eq!(listing_status(&listing), ListingStatus::Sold);
+        assert!(is_sale_recorded("tx-1"));
+        assert_eq!(get_collection("collection").unwrap().total_volume, 1_000);
+    }
+
+    #[test]
+    fn should_index_the_sale() {
+        let listing = listed("listing", 1_000);
+
+        let sale_id = insert_sale(sale_args(&listing, principal(20), "tx-1"), &listing, "uuid").unwrap();
+
+        let ids = |sales: Vec<Sale>| sales.into_iter().map(|sale| sale.id).collect::<Vec<_>>();
+        assert_eq!(ids(get_collection_sales("collection", 0, 10)), vec![sale_id.clone()]);
+        assert_eq!(ids(get_buyer_sales(&principal(20), 0, 10)), vec![sale_id.clone()]);
+        assert_eq!(ids(get_seller_sales(&listing.seller, 0, 10)), vec![sale_id.clone()]);
+        assert_eq!(ids(get_nft_sales("collection", &listing.nft_id)), vec![sale_id]);
+        assert!(get_buyer_sales(&principal(21), 0, 10).is_empty());
+    }
+
+    #[test]
+    fn should_order_sales_by_sale_time() {
+        let first = listed("first", 1_000);
+        let second = listed("second", 1_000);
+
+        let first_id = insert_sale(sale_args(&first, principal(20), "tx-1"), &first, "b").unwrap();
+        set_current_time(get_current_time() + 1);
+        let second_id = insert_sale(sale_args(&second, principal(20), "tx-2"), &second, "a").unwrap();
+
+        let sales: Vec<String> = get_collection_sales("collection", 0, 10).into_iter().map(|sale| sale.id).collect();
+        assert!(first_id < second_id);
+        assert_eq!(sales, vec![first_id, second_id]);
+    }
+}
+
+mod dedup {
+    use super::*;
+
+    #[test]
+    fn should_refuse_a_transaction_recorded_before() {
+        let first = listed("first", 1_000);
+        let second = listed("second", 1_000);
+        insert_sale(sale_args(&first, principal(20), "tx-1"), &first, "a").unwrap();
+
+        let result = insert_sale(sale_args(&second, principal(20), "tx-1"), &second, "b");
+
+        assert!(matches!(result, Err(MarketplaceError::InvalidState(_))));
+        assert_eq!(listing_status(&second), ListingStatus::Active);
+        assert_eq!(get_collection_sales("collection", 0, 10).len(), 1);
+    }
+
+    #[test]
+    fn should_refuse_a_listing_sold_before() {
+        let listing = listed("listing", 1_000);
+        insert_sale(sale_args(&listing, principal(20), "tx-1"), &listing, "a").unwrap();
+
+        let result = insert_sale(sale_args(&listing, principal(21), "tx-2"), &listing, "b");
+
+        assert!(matches!(result, Err(MarketplaceError::InvalidState(_))));
+        assert!(!is_sale_recorded("tx-2"));
+    }
+}
+
+mod verified_listing {
+    use super::*;
+
+    #[test]
+    fn should_refuse_a_listing_repriced_during_verification() {
+        let verified = listed("listing", 1_000);
+        update_listing(
+            UpdateListingArgs {
+                listing_id: verified.id.clone(),
+                price: Some(2_000),
+                status: None,
+            },
+            &verified.collection_id,
+        )
+        .unwrap();
+
+        let result = insert_sale(sale_args(&verified, principal(20), "tx-1"), &verified, "uuid");
+
+        assert!(matches!(result, Err(MarketplaceError::InvalidState(_))));
+        assert_eq!(listing_status(&verified), ListingStatus::Active);
+        assert!(!is_sale_recorded("tx-1"));
+    }
+
+    #[test]
+    fn should_charge_the_reserved_price() {
+        let listing = listed("listing", 1_000);
+        reserve_listing(&listing.collection_id, &listing.id, principal(20), 800, get_current_time() + HOUR_NANOS)
+            .unwrap();
+        let verified = get_listing(&listing.collection_id, &listing.id).unwrap();
+
+        let sale_id = insert_sale(sale_args(&verified, principal(20), "tx-1"), &verified, "uuid").unwrap();
+
+        assert_eq!(get_sale(&sale_id).unwrap().price, 800);
+    }
+
+    #[test]
+    fn should_refuse_anyone_but_the_reserved_bidder() {
+        let listing = listed("listing", 1_000);
+        reserve_listing(&listing.collection_id, &listing.id, principal(20), 800, get_current_time() + HOUR_NANOS)
+            .unwrap();
+        let verified = get_listing(&listing.collection_id, &listing.id).unwrap();
+
+        let result = insert_sale(sale_args(&verified, principal(21), "tx-1"), &verified, "uuid");
+
+        assert!(matches!(result, Err(MarketplaceError::InvalidState(_))));
+        assert_eq!(listing_status(&verified), ListingStatus::Active);
+    }
+}
+
+mod sales_volume {
+    use super::*;
+
+    #[test]
+    fn should_add_each_sale_to_its_currency() {
+        init_schema_version();
+        let first = listed("first", 1_000);
+        let second = listed("second", 500);
+
+        insert_sale(sale_args(&first, principal(20), "tx-1"), &first, "a").unwrap();
+        insert_sale(sale_args(&second, principal(20), "tx-2"), &second, "b").unwrap();
+
+        assert_eq!(sales_volume_by_currency().get("ICP"), Some(&1_500));
+    }
+
+    #[test]
+    fn should_leave_sales_to_the_backfill_until_it_ran() {
+        let listing = listed("listing", 1_000);
+        insert_sale(sale_args(&listing, principal(20), "tx-1"), &listing, "a").unwrap();
+        assert!(sales_volume_by_currency().is_empty());
+
+        let progress = backfill_sales_volume_batch(None, 10).unwrap();
+
+        assert_eq!(progress.processed, 1);
+        assert!(progress.done);
+        assert_eq!(sales_volume_by_currency().get("ICP"), Some(&1_000));
+    }
+}
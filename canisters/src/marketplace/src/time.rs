@@ -0,0 +1,22 @@
+//! Current time in nanoseconds since the epoch. Native test builds have no IC
+//! clock, so there the time is whatever the test set.
+
+#[cfg(not(test))]
+pub(crate) fn get_current_time() -> u64 {
+    ic_cdk::api::time()
+}
+
+#[cfg(test)]
+thread_local! {
+    static CURRENT_TIME: std::cell::Cell<u64> = const { std::cell::Cell::new(1_700_000_000_000_000_000) };
+}
+
+#[cfg(test)]
+pub(crate) fn get_current_time() -> u64 {
+    CURRENT_TIME.with(|time| time.get())
+}
+
+#[cfg(test)]
+pub(crate) fn set_current_time(nanos: u64) {
+    CURRENT_TIME.with(|time| time.set(nanos));
+}
@@ -0,0 +1,67 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+
+/// Charges `lamports` instead of failing the mint when another guard rejects it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct BotTax {
+    pub lamports: u64,
+    /// Also tax mints that are not the last instruction of their transaction.
+    pub last_instruction: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SolPayment {
+    pub lamports: u64,
+    /// Wallet receiving the payment.
+    pub destination: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TokenPayment {
+    /// In the token's smallest unit.
+    pub amount: u64,
+    pub mint: String,
+    /// Wallet receiving the payment, into its associated token account.
+    pub destination: String,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MintLimit {
+    /// Groups sharing an id share the counter.
+    pub id: u8,
+    pub limit: u16,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AllowList {
+    /// Root of the merkle tree of allowed wallets, 32 bytes.
+    pub merkle_root: ByteBuf,
+}
+
+/// The Candy Guard guards the marketplace configures. Dates are nanoseconds since epoch.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct GuardSet {
+    pub bot_tax: Option<BotTax>,
+    pub sol_payment: Option<SolPayment>,
+    pub token_payment: Option<TokenPayment>,
+    pub start_date: Option<u64>,
+    pub end_date: Option<u64>,
+    pub allow_list: Option<AllowList>,
+    pub mint_limit: Option<MintLimit>,
+}
+
+/// A mint phase, such as an allowlist or a public sale, selected by its label when minting.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GuardGroup {
+    pub label: String,
+    pub guards: GuardSet,
+}
+
+/// Guards of a collection's Candy Guard. The default guards apply to every group;
+/// once there are groups, every mint must name one.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct CandyGuardConfig {
+    pub default: GuardSet,
+    pub groups: Vec<GuardGroup>,
+}
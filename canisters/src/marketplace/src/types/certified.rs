@@ -0,0 +1,32 @@
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use super::collection::Collection;
+use super::listing::Listing;
+
+// To verify a response: check `certificate` against the IC root key, check that
+// its certified data equals the root hash of `witness` (a CBOR hash tree), then
+// recompute each returned value's digest and look it up in the witness under
+// `collections/<id>` or `listings/<collection_id>/<id>`.
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CertifiedCollection {
+    pub collection: Option<Collection>,
+    pub certificate: Option<ByteBuf>,
+    pub witness: ByteBuf,
+}
+
+/// Only active listings are certified; the witness proves any other listing absent.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CertifiedListing {
+    pub listing: Option<Listing>,
+    pub certificate: Option<ByteBuf>,
+    pub witness: ByteBuf,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CertifiedListings {
+    pub listings: Vec<Listing>,
+    pub certificate: Option<ByteBuf>,
+    pub witness: ByteBuf,
+}
@@ -4,6 +4,7 @@ use ic_stable_structures::Storable;
 use ic_stable_structures::storable::Bound;
 use std::borrow::Cow;
 use super::blockchain::Blockchain;
+use super::candy_guard::CandyGuardConfig;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum CollectionStatus {
@@ -41,6 +42,11 @@ pub struct SolanaCollectionData {
     pub candy_machine_items_uploaded: bool,
     pub candy_machine_authority: Option<String>,
     pub candy_machine_config: Option<CandyMachineConfig>,
+    /// The candy machine as last read from chain, including mint progress.
+    pub candy_machine_state: Option<CandyMachineState>,
+    pub candy_guard_address: Option<String>,
+    /// Guards of the candy guard as last finalized on chain.
+    pub candy_guard_config: Option<CandyGuardConfig>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -64,6 +70,34 @@ pub struct CandyMachineConfig {
     pub symbol: String,
 }
 
+/// A candy machine account decoded from chain.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CandyMachineState {
+    pub authority: String,
+    pub mint_authority: String,
+    pub collection_mint: String,
+    pub items_available: u64,
+    pub items_redeemed: u64,
+    /// Config lines written so far.
+    pub items_loaded: u64,
+    pub symbol: String,
+    pub seller_fee_basis_points: u16,
+    pub max_supply: u64,
+    pub is_mutable: bool,
+    pub config_line_settings: Option<ConfigLineSettings>,
+    pub hidden_settings: bool,
+    pub synced_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ConfigLineSettings {
+    pub prefix_name: String,
+    pub name_length: u32,
+    pub prefix_uri: String,
+    pub uri_length: u32,
+    pub is_sequential: bool,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum SolanaDeploymentStage {
     FilesUploading,
@@ -158,6 +192,52 @@ pub struct Collection {
     pub updated_at: u64,
 }
 
+impl From<CollectionV0> for Collection {
+    fn from(old: CollectionV0) -> Self {
+        let chain_data = match old.chain_data {
+            ChainDataV0::Solana(data) => ChainData::Solana(SolanaCollectionData {
+                deployment_stage: data.deployment_stage,
+                candy_machine_address: data.candy_machine_address,
+                collection_mint: data.collection_mint,
+                candy_machine_authority: data.candy_machine_authority,
+                manifest_url: data.manifest_url,
+                files_uploaded: data.files_uploaded,
+                metadata_created: data.metadata_created,
+                candy_machine_items_uploaded: false,
+                candy_machine_config: data.candy_machine_config,
+                candy_machine_state: None,
+                candy_guard_address: None,
+                candy_guard_config: None,
+            }),
+            ChainDataV0::ICP(data) => ChainData::ICP(data),
+            ChainDataV0::Ethereum(data) => ChainData::Ethereum(data),
+            ChainDataV0::Bitcoin(data) => ChainData::Bitcoin(data),
+        };
+
+        Collection {
+            id: old.id,
+            blockchain: old.blockchain,
+            creator: old.creator,
+            name: old.name,
+            symbol: old.symbol,
+            description: old.description,
+            image_url: old.image_url,
+            banner_url: old.banner_url,
+            total_supply: old.total_supply,
+            floor_price: old.floor_price,
+            total_volume: old.total_volume,
+            owner_count: old.owner_count,
+            listed_count: old.listed_count,
+            royalty_bps: old.royalty_bps,
+            metadata: old.metadata,
+            status: old.status,
+            chain_data,
+            created_at: old.created_at,
+            updated_at: old.updated_at,
+        }
+    }
+}
+
 impl Storable for Collection {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
@@ -171,10 +251,7 @@ impl Storable for Collection {
         candid::encode_one(&self).unwrap()
     }
 
-    const BOUND: Bound = Bound::Bounded {
-        max_size: 2048,
-        is_fixed_size: false,
-    };
+    const BOUND: Bound = Bound::Unbounded;
 }
 
 impl Storable for CollectionV0 {
@@ -190,10 +267,7 @@ impl Storable for CollectionV0 {
         candid::encode_one(&self).unwrap()
     }
 
-    const BOUND: Bound = Bound::Bounded {
-        max_size: 2048,
-        is_fixed_size: false,
-    };
+    const BOUND: Bound = Bound::Unbounded;
 }
 
 
@@ -0,0 +1,109 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use super::sale::SaleRejectionReason;
+use super::validation::FieldError;
+use super::pause::PauseTarget;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entity {
+    Collection,
+    Listing,
+    Offer,
+    Sale,
+}
+
+/// One RPC provider's failure, as reported by the SOL RPC canister.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProviderError {
+    pub provider: String,
+    pub message: String,
+}
+
+/// Error returned by every marketplace endpoint.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MarketplaceError {
+    NotFound { entity: Entity, id: String },
+    Unauthorized,
+    /// The record exists but is in the wrong state for the operation.
+    InvalidState(String),
+    /// A single malformed argument outside the field-level validation pipeline.
+    InvalidInput(String),
+    ValidationFailed { fields: Vec<FieldError> },
+    SaleRejected(SaleRejectionReason),
+    SolanaRpc { errors: Vec<ProviderError> },
+    /// Providers disagreed and too few of them succeeded to reach consensus.
+    ConsensusFailed { successes: u32, errors: Vec<ProviderError> },
+    /// The admin has paused this kind of operation.
+    Paused { target: PauseTarget, reason: String, resume_at: Option<u64> },
+    Internal(String),
+}
+
+pub type MarketplaceResult<T> = Result<T, MarketplaceError>;
+
+impl MarketplaceError {
+    pub fn not_found(entity: Entity, id: impl Into<String>) -> Self {
+        MarketplaceError::NotFound { entity, id: id.into() }
+    }
+
+    pub fn invalid_state(message: impl Into<String>) -> Self {
+        MarketplaceError::InvalidState(message.into())
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        MarketplaceError::InvalidInput(message.into())
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        MarketplaceError::Internal(message.into())
+    }
+
+    /// A failure reported by a single RPC call that did not go through provider consensus.
+    pub fn rpc(message: impl Into<String>) -> Self {
+        MarketplaceError::SolanaRpc {
+            errors: vec![ProviderError {
+                provider: "sol_rpc".to_string(),
+                message: message.into(),
+            }],
+        }
+    }
+}
+
+impl std::fmt::Display for MarketplaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarketplaceError::NotFound { entity, id } => write!(f, "{:?} {} not found", entity, id),
+            MarketplaceError::Unauthorized => write!(f, "Unauthorized"),
+            MarketplaceError::InvalidState(message)
+            | MarketplaceError::InvalidInput(message)
+            | MarketplaceError::Internal(message) => write!(f, "{}", message),
+            MarketplaceError::ValidationFailed { fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect();
+                write!(f, "Validation failed: {}", fields.join("; "))
+            }
+            MarketplaceError::SaleRejected(reason) => write!(f, "Sale rejected: {:?}", reason),
+            MarketplaceError::SolanaRpc { errors } => write!(f, "Solana RPC error: {:?}", errors),
+            MarketplaceError::ConsensusFailed { successes, errors } => write!(
+                f,
+                "RPC consensus failed: {} successes, {} failures",
+                successes,
+                errors.len()
+            ),
+            MarketplaceError::Paused { target, reason, .. } => write!(f, "{:?} paused: {}", target, reason),
+        }
+    }
+}
+
+impl From<Vec<FieldError>> for MarketplaceError {
+    fn from(fields: Vec<FieldError>) -> Self {
+        MarketplaceError::ValidationFailed { fields }
+    }
+}
+
+impl From<SaleRejectionReason> for MarketplaceError {
+    fn from(reason: SaleRejectionReason) -> Self {
+        MarketplaceError::SaleRejected(reason)
+    }
+}
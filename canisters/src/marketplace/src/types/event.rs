@@ -0,0 +1,186 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use ic_stable_structures::Storable;
+use ic_stable_structures::storable::Bound;
+use std::borrow::Cow;
+use super::blockchain::Blockchain;
+use super::collection::{CollectionStatus, SolanaDeploymentStage};
+use super::listing::ListingStatus;
+use super::offer::OfferStatus;
+
+/// A state transition in the marketplace. Variant names follow
+/// `types::icp::MarketplaceEvent`; ids are the marketplace's string ids.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum MarketplaceEvent {
+    CollectionCreated {
+        collection_id: String,
+        creator: Principal,
+        name: String,
+        blockchain: Blockchain,
+    },
+    CollectionStatusChanged {
+        collection_id: String,
+        creator: Principal,
+        from: CollectionStatus,
+        to: CollectionStatus,
+    },
+    SolanaStageUpdated {
+        collection_id: String,
+        creator: Principal,
+        stage: SolanaDeploymentStage,
+    },
+    ItemListed {
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+        price: u64,
+        currency: String,
+    },
+    ListingPriceChanged {
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+        old_price: u64,
+        new_price: u64,
+    },
+    ListingCancelled {
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+    },
+    ListingStatusChanged {
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+        from: ListingStatus,
+        to: ListingStatus,
+    },
+    ListingRemoved {
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+    },
+    ListingExpired {
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+    },
+    ItemSold {
+        sale_id: String,
+        listing_id: String,
+        collection_id: String,
+        nft_id: String,
+        seller: Principal,
+        buyer: Principal,
+        price: u64,
+        currency: String,
+        tx_signature: String,
+    },
+    OfferMade {
+        offer_id: String,
+        listing_id: String,
+        collection_id: String,
+        bidder: Principal,
+        price: u64,
+        currency: String,
+    },
+    OfferStatusChanged {
+        offer_id: String,
+        listing_id: String,
+        collection_id: String,
+        bidder: Principal,
+        status: OfferStatus,
+    },
+    OfferExpired {
+        offer_id: String,
+        listing_id: String,
+        collection_id: String,
+        bidder: Principal,
+    },
+}
+
+impl MarketplaceEvent {
+    pub fn collection_id(&self) -> &str {
+        match self {
+            MarketplaceEvent::CollectionCreated { collection_id, .. }
+            | MarketplaceEvent::CollectionStatusChanged { collection_id, .. }
+            | MarketplaceEvent::SolanaStageUpdated { collection_id, .. }
+            | MarketplaceEvent::ItemListed { collection_id, .. }
+            | MarketplaceEvent::ListingPriceChanged { collection_id, .. }
+            | MarketplaceEvent::ListingCancelled { collection_id, .. }
+            | MarketplaceEvent::ListingStatusChanged { collection_id, .. }
+            | MarketplaceEvent::ListingRemoved { collection_id, .. }
+            | MarketplaceEvent::ListingExpired { collection_id, .. }
+            | MarketplaceEvent::ItemSold { collection_id, .. }
+            | MarketplaceEvent::OfferMade { collection_id, .. }
+            | MarketplaceEvent::OfferStatusChanged { collection_id, .. }
+            | MarketplaceEvent::OfferExpired { collection_id, .. } => collection_id,
+        }
+    }
+
+    /// Principals whose activity feed shows this event.
+    pub fn principals(&self) -> Vec<Principal> {
+        match self {
+            MarketplaceEvent::CollectionCreated { creator, .. }
+            | MarketplaceEvent::CollectionStatusChanged { creator, .. }
+            | MarketplaceEvent::SolanaStageUpdated { creator, .. } => vec![*creator],
+            MarketplaceEvent::ItemListed { seller, .. }
+            | MarketplaceEvent::ListingPriceChanged { seller, .. }
+            | MarketplaceEvent::ListingCancelled { seller, .. }
+            | MarketplaceEvent::ListingStatusChanged { seller, .. }
+            | MarketplaceEvent::ListingRemoved { seller, .. }
+            | MarketplaceEvent::ListingExpired { seller, .. } => vec![*seller],
+            MarketplaceEvent::ItemSold { seller, buyer, .. } => vec![*seller, *buyer],
+            MarketplaceEvent::OfferMade { bidder, .. }
+            | MarketplaceEvent::OfferStatusChanged { bidder, .. }
+            | MarketplaceEvent::OfferExpired { bidder, .. } => vec![*bidder],
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ActivityEvent {
+    pub id: u64,
+    pub timestamp: u64,
+    pub event: MarketplaceEvent,
+}
+
+impl Storable for ActivityEvent {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        candid::encode_one(&self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ActivityFilter {
+    pub collection_id: Option<String>,
+    pub principal: Option<Principal>,
+    /// Inclusive lower bound on the event timestamp, in nanoseconds.
+    pub from: Option<u64>,
+    /// Exclusive upper bound on the event timestamp, in nanoseconds.
+    pub to: Option<u64>,
+}
+
+/// Newest events first. Pass `next_cursor` back to fetch the next (older) page;
+/// a page can hold fewer than `limit` events and still have one.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ActivityPage {
+    pub events: Vec<ActivityEvent>,
+    pub next_cursor: Option<u64>,
+}
@@ -24,6 +24,14 @@ pub struct Listing {
     pub price: u64,
     pub currency: String,
     pub escrow_address: Option<String>,
+    /// Bidder whose offer the seller accepted. Only they can buy the listing, at
+    /// `reserved_price`, until `reserved_until`.
+    pub reserved_for: Option<Principal>,
+    /// Price of the accepted offer. `price` keeps the asking price, so the floor
+    /// and price index are unaffected by the reservation.
+    pub reserved_price: Option<u64>,
+    /// When the reservation lapses and the listing is open to everyone again.
+    pub reserved_until: Option<u64>,
     pub status: ListingStatus,
     pub listed_at: u64,
     pub expires_at: Option<u64>,
@@ -31,6 +39,13 @@ pub struct Listing {
     pub nft_metadata: NftMetadata,
 }
 
+impl Listing {
+    /// Price the buyer must pay: the accepted offer's while reserved, else the asking price.
+    pub fn sale_price(&self) -> u64 {
+        self.reserved_price.unwrap_or(self.price)
+    }
+}
+
 impl Storable for Listing {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::Owned(candid::encode_one(self).unwrap())
@@ -44,10 +59,7 @@ impl Storable for Listing {
         candid::encode_one(&self).unwrap()
     }
 
-    const BOUND: Bound = Bound::Bounded {
-        max_size: 1024,
-        is_fixed_size: false,
-    };
+    const BOUND: Bound = Bound::Unbounded;
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
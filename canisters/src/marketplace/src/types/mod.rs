@@ -1,13 +1,25 @@
 pub mod blockchain;
 pub mod collection;
+pub mod candy_guard;
 pub mod listing;
 pub mod sale;
 pub mod offer;
+pub mod event;
+pub mod certified;
+pub mod error;
 pub mod solana_transaction;
+pub mod validation;
+pub mod pause;
 
 pub use blockchain::*;
 pub use collection::*;
+pub use candy_guard::*;
 pub use listing::*;
 pub use sale::*;
 pub use offer::*;
+pub use event::*;
+pub use certified::*;
+pub use error::*;
 pub use solana_transaction::*;
+pub use validation::*;
+pub use pause::*;
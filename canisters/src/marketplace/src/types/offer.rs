@@ -49,6 +49,7 @@ impl Storable for Offer {
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct CreateOfferArgs {
+    pub collection_id: String,
     pub listing_id: String,
     pub price: u64,
     pub currency: String,
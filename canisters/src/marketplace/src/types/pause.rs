@@ -0,0 +1,90 @@
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+use ic_stable_structures::Storable;
+use ic_stable_structures::storable::Bound;
+use std::borrow::Cow;
+
+/// An operation the admin can switch off independently of the others.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PauseTarget {
+    Listings,
+    Sales,
+    Offers,
+    /// Every endpoint that signs a Solana transaction with a canister-derived key.
+    SolanaSigning,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Pause {
+    pub reason: String,
+    pub paused_by: Principal,
+    pub paused_at: u64,
+    /// The pause lifts itself at this time (nanoseconds since epoch).
+    pub resume_at: Option<u64>,
+}
+
+impl Pause {
+    pub fn is_active(&self, now: u64) -> bool {
+        self.resume_at.is_none_or(|resume_at| now < resume_at)
+    }
+}
+
+/// Current pause switches. `None` means the operation is running.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PauseStatus {
+    pub listings: Option<Pause>,
+    pub sales: Option<Pause>,
+    pub offers: Option<Pause>,
+    pub solana_signing: Option<Pause>,
+}
+
+impl PauseStatus {
+    pub fn get(&self, target: PauseTarget) -> Option<&Pause> {
+        match target {
+            PauseTarget::Listings => self.listings.as_ref(),
+            PauseTarget::Sales => self.sales.as_ref(),
+            PauseTarget::Offers => self.offers.as_ref(),
+            PauseTarget::SolanaSigning => self.solana_signing.as_ref(),
+        }
+    }
+
+    pub fn slot(&mut self, target: PauseTarget) -> &mut Option<Pause> {
+        match target {
+            PauseTarget::Listings => &mut self.listings,
+            PauseTarget::Sales => &mut self.sales,
+            PauseTarget::Offers => &mut self.offers,
+            PauseTarget::SolanaSigning => &mut self.solana_signing,
+        }
+    }
+
+    /// Drops pauses whose auto-resume time has passed.
+    pub fn active(mut self, now: u64) -> Self {
+        for slot in [
+            &mut self.listings,
+            &mut self.sales,
+            &mut self.offers,
+            &mut self.solana_signing,
+        ] {
+            if slot.as_ref().is_some_and(|pause| !pause.is_active(now)) {
+                *slot = None;
+            }
+        }
+        self
+    }
+}
+
+impl Storable for PauseStatus {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        candid::encode_one(self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
@@ -41,7 +41,44 @@ impl Storable for Sale {
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct RecordSaleArgs {
+    pub collection_id: String,
     pub listing_id: String,
     pub buyer: Principal,
+    pub buyer_address: Option<String>,
     pub tx_signature: String,
 }
+
+/// Why an on-chain sale transaction was refused.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SaleRejectionReason {
+    InvalidSignature(String),
+    TransactionNotFound,
+    RpcError(String),
+    InconsistentRpcResults,
+    UndecodableTransaction(String),
+    MissingTransactionMeta,
+    TransactionFailed(String),
+    MissingBuyerAddress,
+    NftNotTransferred {
+        mint: String,
+        from: String,
+        to: String,
+    },
+    UnsupportedCurrency(String),
+    /// The seller was not credited exactly the listing price.
+    PriceMismatch {
+        expected: u64,
+        /// Lamports the seller received.
+        paid: u64,
+    },
+    /// The RPC providers did not report when the transaction was processed.
+    MissingBlockTime,
+    /// The transaction was processed before the listing was created, so it cannot
+    /// be a purchase of it.
+    TransactionBeforeListing {
+        /// Seconds since the Unix epoch.
+        block_time: i64,
+        /// Nanoseconds since the Unix epoch.
+        listed_at: u64,
+    },
+}
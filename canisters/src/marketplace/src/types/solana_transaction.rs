@@ -1,5 +1,10 @@
 use candid::CandidType;
 use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use ic_stable_structures::Storable;
+use ic_stable_structures::storable::Bound;
+use std::borrow::Cow;
+use super::candy_guard::CandyGuardConfig;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum TransactionType {
@@ -15,16 +20,106 @@ pub struct SignAndSendTransactionArgs {
     pub transaction_type: TransactionType,
 }
 
+/// One Candy Machine item: the name and metadata URI of the NFT it mints.
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct AccountMeta {
-    pub pubkey: String,
-    pub is_signer: bool,
-    pub is_writable: bool,
+pub struct ConfigLine {
+    pub name: String,
+    pub uri: String,
 }
 
+/// What a canister-signed transaction does for the collection's deployment. Changes
+/// carried by a step are written to the collection once Solana finalizes it.
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct InstructionData {
-    pub program_id: String,
-    pub accounts: Vec<AccountMeta>,
-    pub data: Vec<u8>,
+pub enum DeploymentStep {
+    CreateCollectionNft { collection_mint: String },
+    CreateCandyMachine { candy_machine: String, authority: String },
+    AddItems { index: u32, count: u32 },
+    SetCollection { collection_mint: String },
+    TransferAuthority { new_authority: String },
+    SetCandyGuard { candy_guard: String, config: CandyGuardConfig },
+    /// A client-built transaction signed through `sign_and_send_solana_transaction`.
+    ClientTransaction { transaction_type: TransactionType },
+}
+
+/// Compute budget the canister set on a transaction.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PriorityFee {
+    pub compute_unit_limit: u32,
+    /// In micro-lamports per compute unit.
+    pub compute_unit_price: u64,
+}
+
+/// A wallet transaction the canister signed and sent.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SentTransaction {
+    pub signature: String,
+    pub priority_fee: PriorityFee,
+    /// The account the transaction creates, if it creates one.
+    pub created_account: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DeploymentTransactionStatus {
+    /// Sent, not seen on chain yet.
+    Pending,
+    /// Landed in a block that is not finalized yet.
+    Confirmed,
+    Finalized,
+    Failed { error: String },
+    /// The blockhash expired more times than the canister re-signs for.
+    Expired,
+}
+
+impl DeploymentTransactionStatus {
+    pub fn is_settled(&self) -> bool {
+        matches!(
+            self,
+            DeploymentTransactionStatus::Finalized
+                | DeploymentTransactionStatus::Failed { .. }
+                | DeploymentTransactionStatus::Expired
+        )
+    }
+}
+
+/// A transaction sent for a collection, tracked until it is finalized or given up on.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeploymentTransaction {
+    pub id: u64,
+    pub collection_id: String,
+    pub step: DeploymentStep,
+    pub status: DeploymentTransactionStatus,
+    /// Signature of the latest submission.
+    pub signature: String,
+    /// Signatures of earlier submissions whose blockhash expired before they landed.
+    pub replaced_signatures: Vec<String>,
+    /// `None` for client-built transactions, which set their own compute budget.
+    pub priority_fee: Option<PriorityFee>,
+    /// Lamports charged to the collection for the transaction. Corrected to what it
+    /// actually cost once it lands, and released if it never does.
+    pub lamports_charged: Option<u64>,
+    pub created_at: u64,
+    /// Last block height at which the blockhash of the latest submission is valid.
+    /// `None` for client-built transactions, whose blockhash the canister did not fetch.
+    pub last_valid_block_height: Option<u64>,
+    pub submitted_at: u64,
+    pub updated_at: u64,
+    /// The serialized message, kept until the transaction settles so it can be
+    /// re-signed with a fresh blockhash.
+    pub message: Option<ByteBuf>,
+}
+
+impl Storable for DeploymentTransaction {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        candid::encode_one(self).unwrap()
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
 }
@@ -0,0 +1,9 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+/// A single rejected input field, e.g. `{ field: "nft_metadata.attributes[2].value", .. }`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
@@ -0,0 +1,325 @@
+//! Argument validation for the create/update endpoints.
+//!
+//! Checks run through a [`Validator`], which collects every failing field instead of
+//! stopping at the first one, so a client can fix a form in a single round trip.
+
+use candid::Principal;
+use crate::types::{
+    Blockchain, ChainData, CreateCollectionArgs, CreateListingArgs, FieldError, NftMetadata,
+    UpdateListingArgs, UpdateSolanaStageArgs,
+};
+
+#[cfg(test)]
+mod tests;
+
+// Size limits for free-form fields. Records are stored unbounded, so these are what
+// keep a single collection or listing from growing without limit.
+pub const MAX_ID_LEN: usize = 64;
+pub const MAX_COLLECTION_NAME_LEN: usize = 100;
+pub const MAX_SYMBOL_LEN: usize = 10;
+pub const MAX_DESCRIPTION_LEN: usize = 5_000;
+pub const MAX_URL_LEN: usize = 2_048;
+pub const MAX_ADDRESS_LEN: usize = 128;
+pub const MAX_CURRENCY_LEN: usize = 16;
+pub const MAX_METADATA_ENTRIES: usize = 50;
+pub const MAX_METADATA_KEY_LEN: usize = 64;
+pub const MAX_METADATA_VALUE_LEN: usize = 1_024;
+pub const MAX_NFT_NAME_LEN: usize = 200;
+pub const MAX_NFT_ATTRIBUTES: usize = 50;
+pub const MAX_ATTRIBUTE_LEN: usize = 256;
+pub const MAX_INSCRIPTION_IDS: usize = 1_000;
+
+pub const MAX_TOTAL_SUPPLY: u64 = 1_000_000;
+pub const MAX_ROYALTY_BPS: u16 = 10_000;
+
+/// URL schemes accepted for images, banners and manifests.
+pub const ALLOWED_URL_SCHEMES: &[&str] = &["https://", "ipfs://", "ar://"];
+
+pub fn validate_price(price: u64) -> Result<(), String> {
+    if price == 0 {
+        return Err("Price must be greater than 0".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_collection_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err("Collection name cannot be empty".to_string());
+    }
+    if name.len() > MAX_COLLECTION_NAME_LEN {
+        return Err("Collection name too long".to_string());
+    }
+    Ok(())
+}
+
+pub fn validate_symbol(symbol: &str) -> Result<(), String> {
+    if symbol.is_empty() {
+        return Err("Symbol cannot be empty".to_string());
+    }
+    if symbol.len() > MAX_SYMBOL_LEN {
+        return Err("Symbol too long".to_string());
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn error(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Records a `Result<(), String>` check, e.g. one of the `validate_*` helpers.
+    pub fn check(&mut self, field: &str, result: Result<(), String>) {
+        if let Err(message) = result {
+            self.error(field, message);
+        }
+    }
+
+    pub fn non_empty(&mut self, field: &str, value: &str) {
+        if value.trim().is_empty() {
+            self.error(field, "must not be empty");
+        }
+    }
+
+    pub fn max_len(&mut self, field: &str, value: &str, max: usize) {
+        if value.len() > max {
+            self.error(field, format!("too long ({} bytes, max {})", value.len(), max));
+        }
+    }
+
+    pub fn max_count(&mut self, field: &str, count: usize, max: usize) {
+        if count > max {
+            self.error(field, format!("too many entries ({}, max {})", count, max));
+        }
+    }
+
+    pub fn url(&mut self, field: &str, value: &str) {
+        self.max_len(field, value, MAX_URL_LEN);
+        if !ALLOWED_URL_SCHEMES.iter().any(|scheme| value.starts_with(scheme)) {
+            self.error(
+                field,
+                format!("must start with one of {}", ALLOWED_URL_SCHEMES.join(", ")),
+            );
+        }
+    }
+
+    pub fn address(&mut self, field: &str, blockchain: &Blockchain, value: &str) {
+        if let Err(message) = validate_address(blockchain, value) {
+            self.error(field, message);
+        }
+    }
+
+    pub fn finish(self) -> Result<(), Vec<FieldError>> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+}
+
+/// Checks that `address` is well-formed for `blockchain`. This is a format check
+/// only; it says nothing about whether the account exists.
+pub fn validate_address(blockchain: &Blockchain, address: &str) -> Result<(), String> {
+    if address.len() > MAX_ADDRESS_LEN {
+        return Err(format!("too long ({} bytes, max {})", address.len(), MAX_ADDRESS_LEN));
+    }
+
+    match blockchain {
+        Blockchain::Solana => match bs58::decode(address).into_vec() {
+            Ok(bytes) if bytes.len() == 32 => Ok(()),
+            _ => Err("not a valid Solana address".to_string()),
+        },
+        Blockchain::Ethereum => {
+            let hex = address.strip_prefix("0x").unwrap_or_default();
+            if hex.len() == 40 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                Ok(())
+            } else {
+                Err("not a valid Ethereum address".to_string())
+            }
+        }
+        Blockchain::Bitcoin => {
+            let lower = address.to_lowercase();
+            let is_bech32 = (lower.starts_with("bc1") || lower.starts_with("tb1") || lower.starts_with("bcrt1"))
+                && address.len() <= 90
+                && lower.chars().all(|c| c.is_ascii_alphanumeric());
+            let is_base58 = matches!(address.chars().next(), Some('1' | '3' | 'm' | 'n' | '2'))
+                && (26..=35).contains(&address.len())
+                && bs58::decode(address).into_vec().is_ok();
+            if is_bech32 || is_base58 {
+                Ok(())
+            } else {
+                Err("not a valid Bitcoin address".to_string())
+            }
+        }
+        Blockchain::ICP => {
+            let is_account_id = address.len() == 64 && address.chars().all(|c| c.is_ascii_hexdigit());
+            if is_account_id || Principal::from_text(address).is_ok() {
+                Ok(())
+            } else {
+                Err("not a valid ICP principal or account identifier".to_string())
+            }
+        }
+    }
+}
+
+fn chain_data_blockchain(chain_data: &ChainData) -> Blockchain {
+    match chain_data {
+        ChainData::Solana(_) => Blockchain::Solana,
+        ChainData::ICP(_) => Blockchain::ICP,
+        ChainData::Ethereum(_) => Blockchain::Ethereum,
+        ChainData::Bitcoin(_) => Blockchain::Bitcoin,
+    }
+}
+
+fn validate_optional_address(
+    v: &mut Validator,
+    field: &str,
+    blockchain: &Blockchain,
+    value: &Option<String>,
+) {
+    if let Some(value) = value {
+        v.address(field, blockchain, value);
+    }
+}
+
+fn validate_metadata_entries(v: &mut Validator, metadata: &[(String, String)]) {
+    v.max_count("metadata", metadata.len(), MAX_METADATA_ENTRIES);
+    for (i, (key, value)) in metadata.iter().enumerate() {
+        v.non_empty(&format!("metadata[{}].key", i), key);
+        v.max_len(&format!("metadata[{}].key", i), key, MAX_METADATA_KEY_LEN);
+        v.max_len(&format!("metadata[{}].value", i), value, MAX_METADATA_VALUE_LEN);
+    }
+}
+
+fn validate_nft_metadata(v: &mut Validator, metadata: &NftMetadata) {
+    v.non_empty("nft_metadata.name", &metadata.name);
+    v.max_len("nft_metadata.name", &metadata.name, MAX_NFT_NAME_LEN);
+    v.url("nft_metadata.image_url", &metadata.image_url);
+    v.max_count("nft_metadata.attributes", metadata.attributes.len(), MAX_NFT_ATTRIBUTES);
+    for (i, attribute) in metadata.attributes.iter().enumerate() {
+        let field = format!("nft_metadata.attributes[{}]", i);
+        v.max_len(&format!("{}.trait_type", field), &attribute.trait_type, MAX_ATTRIBUTE_LEN);
+        v.max_len(&format!("{}.value", field), &attribute.value, MAX_ATTRIBUTE_LEN);
+    }
+}
+
+fn validate_chain_data(v: &mut Validator, blockchain: &Blockchain, chain_data: &ChainData) {
+    if &chain_data_blockchain(chain_data) != blockchain {
+        v.error(
+            "chain_data",
+            format!("does not match blockchain {}", blockchain),
+        );
+        return;
+    }
+
+    match chain_data {
+        ChainData::Solana(data) => {
+            let solana = Blockchain::Solana;
+            validate_optional_address(v, "chain_data.candy_machine_address", &solana, &data.candy_machine_address);
+            validate_optional_address(v, "chain_data.collection_mint", &solana, &data.collection_mint);
+            validate_optional_address(v, "chain_data.candy_machine_authority", &solana, &data.candy_machine_authority);
+            if let Some(url) = &data.manifest_url {
+                v.url("chain_data.manifest_url", url);
+            }
+            if let Some(config) = &data.candy_machine_config {
+                v.check("chain_data.candy_machine_config.symbol", validate_symbol(&config.symbol));
+            }
+        }
+        ChainData::Ethereum(data) => {
+            validate_optional_address(v, "chain_data.contract_address", &Blockchain::Ethereum, &data.contract_address);
+        }
+        ChainData::Bitcoin(data) => {
+            v.max_count("chain_data.inscription_ids", data.inscription_ids.len(), MAX_INSCRIPTION_IDS);
+            for (i, id) in data.inscription_ids.iter().enumerate() {
+                v.max_len(&format!("chain_data.inscription_ids[{}]", i), id, MAX_ADDRESS_LEN);
+            }
+        }
+        ChainData::ICP(_) => {}
+    }
+}
+
+pub fn validate_create_collection(args: &CreateCollectionArgs) -> Result<(), Vec<FieldError>> {
+    let mut v = Validator::new();
+
+    v.check("name", validate_collection_name(&args.name));
+    v.check("symbol", validate_symbol(&args.symbol));
+    v.max_len("description", &args.description, MAX_DESCRIPTION_LEN);
+    v.url("image_url", &args.image_url);
+    if let Some(banner_url) = &args.banner_url {
+        v.url("banner_url", banner_url);
+    }
+    if args.total_supply == 0 || args.total_supply > MAX_TOTAL_SUPPLY {
+        v.error("total_supply", format!("must be between 1 and {}", MAX_TOTAL_SUPPLY));
+    }
+    if args.royalty_bps > MAX_ROYALTY_BPS {
+        v.error("royalty_bps", format!("must be at most {}", MAX_ROYALTY_BPS));
+    }
+    validate_metadata_entries(&mut v, &args.metadata);
+    validate_chain_data(&mut v, &args.blockchain, &args.chain_data);
+
+    v.finish()
+}
+
+pub fn validate_create_listing(
+    args: &CreateListingArgs,
+    blockchain: &Blockchain,
+    now: u64,
+) -> Result<(), Vec<FieldError>> {
+    let mut v = Validator::new();
+
+    v.non_empty("collection_id", &args.collection_id);
+    v.max_len("collection_id", &args.collection_id, MAX_ID_LEN);
+    v.non_empty("nft_id", &args.nft_id);
+    v.max_len("nft_id", &args.nft_id, MAX_ADDRESS_LEN);
+    v.address("seller_address", blockchain, &args.seller_address);
+    v.check("price", validate_price(args.price));
+    v.non_empty("currency", &args.currency);
+    v.max_len("currency", &args.currency, MAX_CURRENCY_LEN);
+    if args.expires_at.is_some_and(|expires_at| expires_at <= now) {
+        v.error("expires_at", "must be in the future");
+    }
+    validate_nft_metadata(&mut v, &args.nft_metadata);
+
+    v.finish()
+}
+
+pub fn validate_update_listing(args: &UpdateListingArgs) -> Result<(), Vec<FieldError>> {
+    let mut v = Validator::new();
+
+    v.non_empty("listing_id", &args.listing_id);
+    v.max_len("listing_id", &args.listing_id, MAX_ID_LEN);
+    if let Some(price) = args.price {
+        v.check("price", validate_price(price));
+    }
+
+    v.finish()
+}
+
+pub fn validate_solana_stage_update(args: &UpdateSolanaStageArgs) -> Result<(), Vec<FieldError>> {
+    let mut v = Validator::new();
+    let solana = Blockchain::Solana;
+
+    validate_optional_address(&mut v, "candy_machine_address", &solana, &args.candy_machine_address);
+    validate_optional_address(&mut v, "collection_mint", &solana, &args.collection_mint);
+    validate_optional_address(&mut v, "candy_machine_authority", &solana, &args.candy_machine_authority);
+    if let Some(url) = &args.manifest_url {
+        v.url("manifest_url", url);
+    }
+    if let Some(config) = &args.candy_machine_config {
+        v.check("candy_machine_config.symbol", validate_symbol(&config.symbol));
+    }
+
+    v.finish()
+}
@@ -0,0 +1,214 @@
+use super::*;
+use crate::types::{
+    EthereumCollectionData, EthereumDeploymentStage, ICPCollectionData, ICPDeploymentStage, NftAttribute,
+};
+
+const SOLANA_ADDRESS: &str = "11111111111111111111111111111111";
+const ETHEREUM_ADDRESS: &str = "0x52908400098527886E0F7030069857D2E4169EE7";
+
+fn collection_args() -> CreateCollectionArgs {
+    CreateCollectionArgs {
+        blockchain: Blockchain::ICP,
+        name: "Collection".to_string(),
+        symbol: "COL".to_string(),
+        description: String::new(),
+        image_url: "https://example.com/collection.png".to_string(),
+        banner_url: None,
+        total_supply: 100,
+        royalty_bps: 500,
+        metadata: vec![],
+        chain_data: ChainData::ICP(ICPCollectionData {
+            deployment_stage: ICPDeploymentStage::CanisterCreating,
+            canister_id: None,
+        }),
+    }
+}
+
+fn listing_args() -> CreateListingArgs {
+    CreateListingArgs {
+        collection_id: "collection".to_string(),
+        nft_id: "nft".to_string(),
+        seller_address: SOLANA_ADDRESS.to_string(),
+        price: 1_000,
+        currency: "SOL".to_string(),
+        expires_at: None,
+        nft_metadata: NftMetadata {
+            name: "NFT".to_string(),
+            image_url: "ipfs://nft.png".to_string(),
+            attributes: vec![],
+        },
+    }
+}
+
+fn failing_fields(result: Result<(), Vec<FieldError>>) -> Vec<String> {
+    result.unwrap_err().into_iter().map(|error| error.field).collect()
+}
+
+mod addresses {
+    use super::*;
+
+    #[test]
+    fn should_accept_well_formed_addresses() {
+        assert_eq!(validate_address(&Blockchain::Solana, SOLANA_ADDRESS), Ok(()));
+        assert_eq!(validate_address(&Blockchain::Ethereum, ETHEREUM_ADDRESS), Ok(()));
+        assert_eq!(validate_address(&Blockchain::ICP, "aaaaa-aa"), Ok(()));
+        assert_eq!(validate_address(&Blockchain::ICP, &"a".repeat(64)), Ok(()));
+        assert_eq!(validate_address(&Blockchain::Bitcoin, "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"), Ok(()));
+        assert_eq!(validate_address(&Blockchain::Bitcoin, "1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2"), Ok(()));
+    }
+
+    #[test]
+    fn should_reject_an_address_of_another_chain() {
+        assert!(validate_address(&Blockchain::Solana, ETHEREUM_ADDRESS).is_err());
+        assert!(validate_address(&Blockchain::Ethereum, SOLANA_ADDRESS).is_err());
+        assert!(validate_address(&Blockchain::ICP, ETHEREUM_ADDRESS).is_err());
+        assert!(validate_address(&Blockchain::Bitcoin, ETHEREUM_ADDRESS).is_err());
+    }
+
+    #[test]
+    fn should_reject_a_solana_address_of_the_wrong_length() {
+        assert!(validate_address(&Blockchain::Solana, "1111111111111111").is_err());
+    }
+
+    #[test]
+    fn should_reject_an_oversized_address() {
+        assert!(validate_address(&Blockchain::Bitcoin, &format!("bc1{}", "q".repeat(MAX_ADDRESS_LEN))).is_err());
+    }
+}
+
+mod create_collection {
+    use super::*;
+
+    #[test]
+    fn should_accept_valid_args() {
+        assert_eq!(validate_create_collection(&collection_args()), Ok(()));
+    }
+
+    #[test]
+    fn should_list_every_failing_field() {
+        let args = CreateCollectionArgs {
+            name: String::new(),
+            symbol: "TOOLONGSYMBOL".to_string(),
+            image_url: "http://example.com/collection.png".to_string(),
+            banner_url: Some("javascript:alert(1)".to_string()),
+            total_supply: 0,
+            royalty_bps: MAX_ROYALTY_BPS + 1,
+            ..collection_args()
+        };
+
+        assert_eq!(
+            failing_fields(validate_create_collection(&args)),
+            vec!["name", "symbol", "image_url", "banner_url", "total_supply", "royalty_bps"]
+        );
+    }
+
+    #[test]
+    fn should_bound_total_supply() {
+        let at_limit = CreateCollectionArgs {
+            total_supply: MAX_TOTAL_SUPPLY,
+            ..collection_args()
+        };
+        let over_limit = CreateCollectionArgs {
+            total_supply: MAX_TOTAL_SUPPLY + 1,
+            ..collection_args()
+        };
+
+        assert_eq!(validate_create_collection(&at_limit), Ok(()));
+        assert_eq!(failing_fields(validate_create_collection(&over_limit)), vec!["total_supply"]);
+    }
+
+    #[test]
+    fn should_reject_chain_data_of_another_blockchain() {
+        let args = CreateCollectionArgs {
+            blockchain: Blockchain::Solana,
+            ..collection_args()
+        };
+
+        assert_eq!(failing_fields(validate_create_collection(&args)), vec!["chain_data"]);
+    }
+
+    #[test]
+    fn should_check_addresses_in_chain_data() {
+        let args = CreateCollectionArgs {
+            blockchain: Blockchain::Ethereum,
+            chain_data: ChainData::Ethereum(EthereumCollectionData {
+                deployment_stage: EthereumDeploymentStage::ContractDeploying,
+                contract_address: Some(SOLANA_ADDRESS.to_string()),
+                chain_id: 1,
+            }),
+            ..collection_args()
+        };
+
+        assert_eq!(failing_fields(validate_create_collection(&args)), vec!["chain_data.contract_address"]);
+    }
+
+    #[test]
+    fn should_name_the_failing_metadata_entry() {
+        let args = CreateCollectionArgs {
+            metadata: vec![
+                ("artist".to_string(), "someone".to_string()),
+                (" ".to_string(), "x".repeat(MAX_METADATA_VALUE_LEN + 1)),
+            ],
+            ..collection_args()
+        };
+
+        assert_eq!(
+            failing_fields(validate_create_collection(&args)),
+            vec!["metadata[1].key", "metadata[1].value"]
+        );
+    }
+}
+
+mod create_listing {
+    use super::*;
+
+    const NOW: u64 = 1_000;
+
+    #[test]
+    fn should_accept_valid_args() {
+        assert_eq!(validate_create_listing(&listing_args(), &Blockchain::Solana, NOW), Ok(()));
+    }
+
+    #[test]
+    fn should_check_the_seller_address_against_the_collection_chain() {
+        let result = validate_create_listing(&listing_args(), &Blockchain::Ethereum, NOW);
+
+        assert_eq!(failing_fields(result), vec!["seller_address"]);
+    }
+
+    #[test]
+    fn should_list_every_failing_field() {
+        let args = CreateListingArgs {
+            nft_id: String::new(),
+            price: 0,
+            currency: "x".repeat(MAX_CURRENCY_LEN + 1),
+            expires_at: Some(NOW),
+            ..listing_args()
+        };
+
+        assert_eq!(
+            failing_fields(validate_create_listing(&args, &Blockchain::Solana, NOW)),
+            vec!["nft_id", "price", "currency", "expires_at"]
+        );
+    }
+
+    #[test]
+    fn should_name_the_failing_nft_attribute() {
+        let mut args = listing_args();
+        args.nft_metadata.attributes = vec![
+            NftAttribute {
+                trait_type: "eyes".to_string(),
+                value: "blue".to_string(),
+            },
+            NftAttribute {
+                trait_type: "eyes".to_string(),
+                value: "x".repeat(MAX_ATTRIBUTE_LEN + 1),
+            },
+        ];
+
+        assert_eq!(
+            failing_fields(validate_create_listing(&args, &Blockchain::Solana, NOW)),
+            vec!["nft_metadata.attributes[1].value"]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+//! Decoding of the candy machine account.
+//!
+//! Only the header and the loaded-items count are read; the config lines, mint
+//! bitmask and mint indices that follow can run to megabytes and are skipped.
+
+use borsh::BorshDeserialize;
+use sha2::{Digest, Sha256};
+use solana_pubkey::Pubkey;
+use super::instructions::{CandyMachineData, CANDY_MACHINE_HEADER_SIZE};
+use crate::types::{CandyMachineState, ConfigLineSettings};
+
+#[cfg(test)]
+mod tests;
+
+/// Bytes of the account needed by [`decode_candy_machine`].
+pub const CANDY_MACHINE_STATE_SIZE: u32 = CANDY_MACHINE_HEADER_SIZE as u32 + 4;
+
+#[derive(BorshDeserialize)]
+struct CandyMachineAccount {
+    _version: u8,
+    _token_standard: u8,
+    _features: [u8; 6],
+    authority: [u8; 32],
+    mint_authority: [u8; 32],
+    collection_mint: [u8; 32],
+    items_redeemed: u64,
+    data: CandyMachineData,
+}
+
+fn account_discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{}", name).as_bytes());
+    hash[..8].try_into().unwrap()
+}
+
+/// Decodes the first [`CANDY_MACHINE_STATE_SIZE`] bytes of a candy machine account.
+pub fn decode_candy_machine(data: &[u8], synced_at: u64) -> Result<CandyMachineState, String> {
+    if data.get(..8) != Some(&account_discriminator("CandyMachine")[..]) {
+        return Err("Account is not a candy machine".to_string());
+    }
+    let account = CandyMachineAccount::deserialize(&mut &data[8..])
+        .map_err(|e| format!("Malformed candy machine account: {}", e))?;
+
+    let count_offset = CANDY_MACHINE_HEADER_SIZE as usize;
+    let items_loaded = data
+        .get(count_offset..count_offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or("Candy machine account is missing its items count")?;
+
+    let data_section = account.data;
+    Ok(CandyMachineState {
+        authority: Pubkey::from(account.authority).to_string(),
+        mint_authority: Pubkey::from(account.mint_authority).to_string(),
+        collection_mint: Pubkey::from(account.collection_mint).to_string(),
+        items_available: data_section.items_available,
+        items_redeemed: account.items_redeemed,
+        items_loaded: items_loaded as u64,
+        symbol: data_section.symbol,
+        seller_fee_basis_points: data_section.seller_fee_basis_points,
+        max_supply: data_section.max_supply,
+        is_mutable: data_section.is_mutable,
+        config_line_settings: data_section.config_line_settings.map(|settings| ConfigLineSettings {
+            prefix_name: settings.prefix_name,
+            name_length: settings.name_length,
+            prefix_uri: settings.prefix_uri,
+            uri_length: settings.uri_length,
+            is_sequential: settings.is_sequential,
+        }),
+        hidden_settings: data_section.hidden_settings.is_some(),
+        synced_at,
+    })
+}
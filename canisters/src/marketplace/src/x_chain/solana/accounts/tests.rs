@@ -0,0 +1,164 @@
+use super::*;
+use crate::solana::instructions::{self, Creator, HiddenSettings, MAX_NAME_LENGTH, MAX_SYMBOL_LENGTH, MAX_URI_LENGTH};
+use borsh::BorshSerialize;
+
+const AUTHORITY: [u8; 32] = [1; 32];
+const MINT_AUTHORITY: [u8; 32] = [2; 32];
+const COLLECTION_MINT: [u8; 32] = [3; 32];
+
+fn string(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+    bytes.extend_from_slice(value.as_bytes());
+    bytes
+}
+
+/// A candy machine account laid out field by field as the program writes it: the
+/// borsh-encoded account, zero-filled up to the header size, then the items count
+/// and the config lines.
+fn candy_machine_account(items_loaded: u32) -> Vec<u8> {
+    let mut data = account_discriminator("CandyMachine").to_vec();
+    data.push(1); // version
+    data.push(0); // token_standard
+    data.extend_from_slice(&[0; 6]); // features
+    data.extend_from_slice(&AUTHORITY);
+    data.extend_from_slice(&MINT_AUTHORITY);
+    data.extend_from_slice(&COLLECTION_MINT);
+    data.extend_from_slice(&4u64.to_le_bytes()); // items_redeemed
+    data.extend_from_slice(&10u64.to_le_bytes()); // items_available
+    data.extend_from_slice(&string("ICP"));
+    data.extend_from_slice(&500u16.to_le_bytes());
+    data.extend_from_slice(&0u64.to_le_bytes()); // max_supply
+    data.push(1); // is_mutable
+    data.extend_from_slice(&1u32.to_le_bytes()); // creators
+    data.extend_from_slice(&AUTHORITY);
+    data.extend_from_slice(&[1, 100]);
+    data.push(1); // config_line_settings
+    data.extend_from_slice(&string("#"));
+    data.extend_from_slice(&(MAX_NAME_LENGTH as u32).to_le_bytes());
+    data.extend_from_slice(&string(""));
+    data.extend_from_slice(&(MAX_URI_LENGTH as u32).to_le_bytes());
+    data.push(0); // is_sequential
+    data.push(0); // hidden_settings
+
+    data.resize(CANDY_MACHINE_HEADER_SIZE as usize, 0);
+    data.extend_from_slice(&items_loaded.to_le_bytes());
+    data.extend_from_slice(&[0xff; MAX_NAME_LENGTH + MAX_URI_LENGTH]);
+    data
+}
+
+mod layout {
+    use super::*;
+
+    #[test]
+    fn should_match_discriminator_of_candy_machine_account() {
+        assert_eq!(
+            account_discriminator("CandyMachine"),
+            [51, 173, 177, 113, 25, 241, 109, 189]
+        );
+    }
+
+    #[test]
+    fn should_fill_header_with_largest_candy_machine_data() {
+        let data = CandyMachineData {
+            items_available: u64::MAX,
+            symbol: "S".repeat(MAX_SYMBOL_LENGTH),
+            seller_fee_basis_points: 10_000,
+            max_supply: 0,
+            is_mutable: true,
+            creators: (0..5)
+                .map(|i| Creator {
+                    address: [i; 32],
+                    verified: false,
+                    percentage_share: 20,
+                })
+                .collect(),
+            config_line_settings: Some(instructions::ConfigLineSettings {
+                prefix_name: "N".repeat(MAX_NAME_LENGTH),
+                name_length: 0,
+                prefix_uri: "U".repeat(MAX_URI_LENGTH),
+                uri_length: 0,
+                is_sequential: true,
+            }),
+            hidden_settings: Some(HiddenSettings {
+                name: "N".repeat(MAX_NAME_LENGTH),
+                uri: "U".repeat(MAX_URI_LENGTH),
+                hash: [0; 32],
+            }),
+        };
+        let mut serialized = vec![];
+        data.serialize(&mut serialized).unwrap();
+
+        // Discriminator, version, token standard, features, three keys and items_redeemed
+        let account_fields = 8 + 1 + 1 + 6 + 3 * 32 + 8;
+        assert_eq!((account_fields + serialized.len()) as u64, CANDY_MACHINE_HEADER_SIZE);
+        assert_eq!(CANDY_MACHINE_STATE_SIZE, 854);
+    }
+}
+
+mod decode_candy_machine {
+    use super::*;
+
+    #[test]
+    fn should_decode_header_and_items_count() {
+        let data = candy_machine_account(7);
+
+        let state = decode_candy_machine(&data[..CANDY_MACHINE_STATE_SIZE as usize], 42).unwrap();
+
+        assert_eq!(state.authority, Pubkey::from(AUTHORITY).to_string());
+        assert_eq!(state.mint_authority, Pubkey::from(MINT_AUTHORITY).to_string());
+        assert_eq!(state.collection_mint, Pubkey::from(COLLECTION_MINT).to_string());
+        assert_eq!(state.items_available, 10);
+        assert_eq!(state.items_redeemed, 4);
+        assert_eq!(state.items_loaded, 7);
+        assert_eq!(state.symbol, "ICP");
+        assert_eq!(state.seller_fee_basis_points, 500);
+        assert_eq!(state.max_supply, 0);
+        assert!(state.is_mutable);
+        let settings = state.config_line_settings.unwrap();
+        assert_eq!(settings.prefix_name, "#");
+        assert_eq!(settings.name_length, MAX_NAME_LENGTH as u32);
+        assert_eq!(settings.uri_length, MAX_URI_LENGTH as u32);
+        assert!(!settings.is_sequential);
+        assert!(!state.hidden_settings);
+        assert_eq!(state.synced_at, 42);
+    }
+
+    #[test]
+    fn should_read_items_count_at_header_size() {
+        let mut data = candy_machine_account(0);
+        data[850..854].copy_from_slice(&0x0102_0304u32.to_le_bytes());
+
+        assert_eq!(decode_candy_machine(&data, 0).unwrap().items_loaded, 0x0102_0304);
+    }
+
+    #[test]
+    fn should_reject_other_accounts() {
+        let mut data = candy_machine_account(0);
+        data[..8].copy_from_slice(&account_discriminator("CandyGuard"));
+
+        assert_eq!(
+            decode_candy_machine(&data, 0).unwrap_err(),
+            "Account is not a candy machine"
+        );
+        assert!(decode_candy_machine(&[], 0).is_err());
+    }
+
+    #[test]
+    fn should_reject_account_without_items_count() {
+        let data = candy_machine_account(0);
+
+        assert_eq!(
+            decode_candy_machine(&data[..CANDY_MACHINE_HEADER_SIZE as usize], 0).unwrap_err(),
+            "Candy machine account is missing its items count"
+        );
+    }
+
+    #[test]
+    fn should_reject_truncated_header() {
+        let data = candy_machine_account(0);
+
+        assert!(decode_candy_machine(&data[..100], 0)
+            .unwrap_err()
+            .starts_with("Malformed candy machine account"));
+    }
+}
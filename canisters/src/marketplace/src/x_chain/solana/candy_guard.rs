@@ -0,0 +1,149 @@
+//! Compiles a collection's `CandyGuardConfig` into the Candy Guard program's data.
+//!
+//! A guard set is a little-endian u64 bitmask of the enabled guards followed by
+//! the arguments of each enabled guard in bitmask order. The groups follow as a
+//! u32 count and, for each group, its label padded to six bytes and its guard set.
+
+use borsh::BorshSerialize;
+use solana_pubkey::Pubkey;
+use spl_associated_token_account_interface::address::get_associated_token_address_with_program_id;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use super::programs::TOKEN_PROGRAM_ID;
+use crate::types::{CandyGuardConfig, FieldError, GuardSet};
+
+#[cfg(test)]
+mod tests;
+
+pub const MAX_LABEL_LENGTH: usize = 6;
+pub const MAX_GUARD_GROUPS: usize = 5;
+
+// Bit of each guard in the guard set bitmask
+const BOT_TAX: u32 = 0;
+const SOL_PAYMENT: u32 = 1;
+const TOKEN_PAYMENT: u32 = 2;
+const START_DATE: u32 = 3;
+const END_DATE: u32 = 7;
+const ALLOW_LIST: u32 = 8;
+const MINT_LIMIT: u32 = 9;
+
+struct Compiler {
+    data: Vec<u8>,
+    errors: Vec<FieldError>,
+}
+
+impl Compiler {
+    fn error(&mut self, field: String, message: &str) {
+        self.errors.push(FieldError {
+            field,
+            message: message.to_string(),
+        });
+    }
+
+    fn pubkey(&mut self, field: String, value: &str) -> Pubkey {
+        Pubkey::from_str(value).unwrap_or_else(|_| {
+            self.error(field, "must be a valid Solana address");
+            Pubkey::default()
+        })
+    }
+
+    fn write(&mut self, value: impl BorshSerialize) {
+        value.serialize(&mut self.data).expect("failed to serialize guard");
+    }
+
+    fn guard_set(&mut self, prefix: &str, guards: &GuardSet) {
+        let enabled = [
+            (BOT_TAX, guards.bot_tax.is_some()),
+            (SOL_PAYMENT, guards.sol_payment.is_some()),
+            (TOKEN_PAYMENT, guards.token_payment.is_some()),
+            (START_DATE, guards.start_date.is_some()),
+            (END_DATE, guards.end_date.is_some()),
+            (ALLOW_LIST, guards.allow_list.is_some()),
+            (MINT_LIMIT, guards.mint_limit.is_some()),
+        ];
+        let features = enabled
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .fold(0u64, |features, (bit, _)| features | 1 << bit);
+        self.write(features);
+
+        if let Some(bot_tax) = &guards.bot_tax {
+            self.write((bot_tax.lamports, bot_tax.last_instruction));
+        }
+        if let Some(payment) = &guards.sol_payment {
+            let destination = self.pubkey(format!("{}.sol_payment.destination", prefix), &payment.destination);
+            self.write((payment.lamports, destination.to_bytes()));
+        }
+        if let Some(payment) = &guards.token_payment {
+            let mint = self.pubkey(format!("{}.token_payment.mint", prefix), &payment.mint);
+            let destination = self.pubkey(format!("{}.token_payment.destination", prefix), &payment.destination);
+            let destination_ata = get_associated_token_address_with_program_id(&destination, &mint, &TOKEN_PROGRAM_ID);
+            self.write((payment.amount, mint.to_bytes(), destination_ata.to_bytes()));
+        }
+        if let (Some(start), Some(end)) = (guards.start_date, guards.end_date) {
+            if start >= end {
+                self.error(format!("{}.end_date", prefix), "must be after start_date");
+            }
+        }
+        if let Some(date) = guards.start_date {
+            self.write(unix_seconds(date));
+        }
+        if let Some(date) = guards.end_date {
+            self.write(unix_seconds(date));
+        }
+        if let Some(allow_list) = &guards.allow_list {
+            match <[u8; 32]>::try_from(allow_list.merkle_root.as_slice()) {
+                Ok(root) => self.write(root),
+                Err(_) => self.error(format!("{}.allow_list.merkle_root", prefix), "must be 32 bytes"),
+            }
+        }
+        if let Some(mint_limit) = &guards.mint_limit {
+            if mint_limit.limit == 0 {
+                self.error(format!("{}.mint_limit.limit", prefix), "must be at least 1");
+            }
+            self.write((mint_limit.id, mint_limit.limit));
+        }
+    }
+}
+
+fn unix_seconds(nanos: u64) -> i64 {
+    (nanos / 1_000_000_000) as i64
+}
+
+/// Checks `config` and encodes it as the `data` argument of the Candy Guard
+/// `initialize` and `update` instructions.
+pub fn compile(config: &CandyGuardConfig) -> Result<Vec<u8>, Vec<FieldError>> {
+    let mut compiler = Compiler {
+        data: vec![],
+        errors: vec![],
+    };
+    compiler.guard_set("default", &config.default);
+
+    if config.groups.len() > MAX_GUARD_GROUPS {
+        compiler.error("groups".to_string(), &format!("at most {} groups", MAX_GUARD_GROUPS));
+    }
+    compiler.write(config.groups.len() as u32);
+
+    let mut labels = BTreeSet::new();
+    for (i, group) in config.groups.iter().enumerate() {
+        if group.label.is_empty() || group.label.len() > MAX_LABEL_LENGTH {
+            compiler.error(
+                format!("groups[{}].label", i),
+                &format!("must be 1 to {} bytes", MAX_LABEL_LENGTH),
+            );
+        } else if !labels.insert(group.label.as_str()) {
+            compiler.error(format!("groups[{}].label", i), "must be unique");
+        }
+        let mut label = [0u8; MAX_LABEL_LENGTH];
+        let len = group.label.len().min(MAX_LABEL_LENGTH);
+        label[..len].copy_from_slice(&group.label.as_bytes()[..len]);
+        compiler.write(label);
+        compiler.guard_set(&format!("groups[{}].guards", i), &group.guards);
+    }
+
+    if compiler.errors.is_empty() {
+        Ok(compiler.data)
+    } else {
+        Err(compiler.errors)
+    }
+}
@@ -0,0 +1,202 @@
+use super::*;
+use crate::types::{AllowList, BotTax, GuardGroup, MintLimit, SolPayment};
+use serde_bytes::ByteBuf;
+
+const SECOND: u64 = 1_000_000_000;
+
+fn destination() -> Pubkey {
+    Pubkey::new_from_array([7; 32])
+}
+
+fn group(label: &str) -> GuardGroup {
+    GuardGroup {
+        label: label.to_string(),
+        guards: GuardSet::default(),
+    }
+}
+
+fn fields(errors: Vec<FieldError>) -> Vec<String> {
+    errors.into_iter().map(|error| error.field).collect()
+}
+
+mod guard_set {
+    use super::*;
+
+    #[test]
+    fn should_compile_empty_config() {
+        let data = compile(&CandyGuardConfig::default()).unwrap();
+
+        // No guards, no groups
+        assert_eq!(data, [0u8; 12]);
+    }
+
+    #[test]
+    fn should_write_arguments_in_bitmask_order() {
+        let config = CandyGuardConfig {
+            default: GuardSet {
+                bot_tax: Some(BotTax {
+                    lamports: 10_000_000,
+                    last_instruction: true,
+                }),
+                sol_payment: Some(SolPayment {
+                    lamports: 500_000_000,
+                    destination: destination().to_string(),
+                }),
+                token_payment: None,
+                start_date: Some(1_700_000_000 * SECOND),
+                end_date: Some(1_800_000_000 * SECOND + 999_999_999),
+                allow_list: Some(AllowList {
+                    merkle_root: ByteBuf::from(vec![9; 32]),
+                }),
+                mint_limit: Some(MintLimit { id: 1, limit: 3 }),
+            },
+            groups: vec![],
+        };
+
+        let mut expected = vec![];
+        let features: u64 =
+            1 << BOT_TAX | 1 << SOL_PAYMENT | 1 << START_DATE | 1 << END_DATE | 1 << ALLOW_LIST | 1 << MINT_LIMIT;
+        assert_eq!(features, 0b11_1000_1011);
+        expected.extend_from_slice(&features.to_le_bytes());
+        expected.extend_from_slice(&10_000_000u64.to_le_bytes());
+        expected.push(1);
+        expected.extend_from_slice(&500_000_000u64.to_le_bytes());
+        expected.extend_from_slice(&destination().to_bytes());
+        expected.extend_from_slice(&1_700_000_000i64.to_le_bytes());
+        expected.extend_from_slice(&1_800_000_000i64.to_le_bytes());
+        expected.extend_from_slice(&[9; 32]);
+        expected.push(1);
+        expected.extend_from_slice(&3u16.to_le_bytes());
+        expected.extend_from_slice(&0u32.to_le_bytes());
+
+        assert_eq!(compile(&config).unwrap(), expected);
+    }
+
+    #[test]
+    fn should_reject_end_date_not_after_start_date() {
+        let config = CandyGuardConfig {
+            default: GuardSet {
+                start_date: Some(2 * SECOND),
+                end_date: Some(2 * SECOND),
+                ..GuardSet::default()
+            },
+            groups: vec![],
+        };
+
+        assert_eq!(fields(compile(&config).unwrap_err()), vec!["default.end_date"]);
+    }
+
+    #[test]
+    fn should_reject_invalid_guard_arguments() {
+        let config = CandyGuardConfig {
+            default: GuardSet {
+                sol_payment: Some(SolPayment {
+                    lamports: 1,
+                    destination: "not an address".to_string(),
+                }),
+                allow_list: Some(AllowList {
+                    merkle_root: ByteBuf::from(vec![0; 31]),
+                }),
+                mint_limit: Some(MintLimit { id: 0, limit: 0 }),
+                ..GuardSet::default()
+            },
+            groups: vec![],
+        };
+
+        assert_eq!(
+            fields(compile(&config).unwrap_err()),
+            vec![
+                "default.sol_payment.destination",
+                "default.allow_list.merkle_root",
+                "default.mint_limit.limit"
+            ]
+        );
+    }
+}
+
+mod groups {
+    use super::*;
+
+    #[test]
+    fn should_pad_labels_to_six_bytes() {
+        let config = CandyGuardConfig {
+            default: GuardSet::default(),
+            groups: vec![group("pub"), group("wl1234")],
+        };
+
+        let data = compile(&config).unwrap();
+
+        assert_eq!(&data[..8], &[0; 8]);
+        assert_eq!(&data[8..12], &2u32.to_le_bytes());
+        assert_eq!(&data[12..18], b"pub\0\0\0");
+        assert_eq!(&data[18..26], &[0; 8]);
+        assert_eq!(&data[26..32], b"wl1234");
+        assert_eq!(&data[32..], &[0; 8]);
+    }
+
+    #[test]
+    fn should_compile_group_guards_after_their_label() {
+        let config = CandyGuardConfig {
+            default: GuardSet::default(),
+            groups: vec![GuardGroup {
+                label: "wl".to_string(),
+                guards: GuardSet {
+                    mint_limit: Some(MintLimit { id: 2, limit: 1 }),
+                    ..GuardSet::default()
+                },
+            }],
+        };
+
+        let data = compile(&config).unwrap();
+
+        assert_eq!(&data[12..18], b"wl\0\0\0\0");
+        assert_eq!(&data[18..26], &(1u64 << MINT_LIMIT).to_le_bytes());
+        assert_eq!(&data[26..], &[2, 1, 0]);
+    }
+
+    #[test]
+    fn should_reject_empty_long_and_duplicate_labels() {
+        let config = CandyGuardConfig {
+            default: GuardSet::default(),
+            groups: vec![group(""), group("public"), group("toolong"), group("public")],
+        };
+
+        let errors = compile(&config).unwrap_err();
+
+        assert_eq!(
+            fields(errors.clone()),
+            vec!["groups[0].label", "groups[2].label", "groups[3].label"]
+        );
+        assert_eq!(errors[2].message, "must be unique");
+    }
+
+    #[test]
+    fn should_reject_too_many_groups() {
+        let config = CandyGuardConfig {
+            default: GuardSet::default(),
+            groups: ["a", "b", "c", "d", "e", "f"].into_iter().map(group).collect(),
+        };
+
+        assert_eq!(fields(compile(&config).unwrap_err()), vec!["groups"]);
+    }
+
+    #[test]
+    fn should_report_group_errors_under_their_index() {
+        let config = CandyGuardConfig {
+            default: GuardSet::default(),
+            groups: vec![
+                group("pub"),
+                GuardGroup {
+                    label: "wl".to_string(),
+                    guards: GuardSet {
+                        start_date: Some(5 * SECOND),
+                        end_date: Some(SECOND),
+                        ..GuardSet::default()
+                    },
+                },
+            ],
+        };
+
+        assert_eq!(fields(compile(&config).unwrap_err()), vec!["groups[1].guards.end_date"]);
+    }
+}
@@ -1,584 +1,1157 @@
-use super::{client, solana_wallet::SolanaWallet};
-use crate::state::get_collection;
-use crate::types::{InstructionData, TransactionType};
+use super::accounts;
+use super::candy_guard;
+use super::priority_fees::{self, MAX_COMPUTE_UNIT_LIMIT, MAX_COMPUTE_UNIT_PRICE};
+use super::programs::{self, CandyGuardInstruction, CandyMachineInstruction, ProgramInstruction};
+use super::solana_wallet::{SolanaAccount, SolanaWallet};
+use super::client;
+use super::instructions;
+use crate::state::{self, get_collection};
+use crate::types::{
+    CandyGuardConfig, CandyMachineState, ChainData, Collection, ConfigLine, DeploymentStep,
+    DeploymentTransaction, DeploymentTransactionStatus, Entity, FieldError, MarketplaceError, MarketplaceResult,
+    PriorityFee, ProviderError, SolanaCollectionData, SolanaDeploymentStage, TransactionType, UpdateSolanaStageArgs,
+};
 use bincode::deserialize;
+use sol_rpc_types::{DataSlice, GetAccountInfoEncoding, MultiRpcResult, RpcError, TransactionDetails};
+use solana_hash::Hash;
 use ic_cdk::api::canister_self;
 use solana_instruction::Instruction;
 use solana_message::Message;
 use solana_pubkey::Pubkey;
+use solana_signature::Signature;
 use solana_transaction::Transaction;
 use std::str::FromStr;
+use crate::logs::{log, Priority};
+
+/// Upper bound on the lamports a single transaction may move out of the canister payer.
+/// Enough for the rent of a candy machine holding [`MAX_CANDY_MACHINE_ITEMS`].
+const MAX_LAMPORTS_PER_TRANSACTION: u64 = 20_000_000_000;
+/// Upper bound on the lamports the canister payer spends on one collection over its
+/// lifetime: rent, fees and priority fees of every transaction signed for it.
+const MAX_LAMPORTS_PER_COLLECTION: u64 = 40_000_000_000;
+const MAX_CANDY_MACHINE_ITEMS: u64 = 10_000;
+/// Largest serialized transaction the Solana network accepts.
+const MAX_TRANSACTION_SIZE: u64 = 1232;
+/// Compute units requested by canister-built transactions. Covers the heaviest of
+/// them, creating the candy machine and the collection NFT.
+const DEPLOYMENT_COMPUTE_UNIT_LIMIT: u32 = 400_000;
+/// Blocks after its own during which a blockhash is accepted by the cluster.
+const BLOCKHASH_VALIDITY_BLOCKS: u64 = 150;
+const BLOCKHASH_TRIES: usize = 3;
 
 pub async fn sign_and_send_transaction(
     collection_id: String,
     serialized_message: Vec<u8>,
     transaction_type: TransactionType,
     user_wallet_address: Option<String>,
-) -> Result<String, String> {
-    let _collection = get_collection(&collection_id).ok_or("Collection not found")?;
+) -> MarketplaceResult<String> {
+    let _collection = get_collection(&collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, &collection_id))?;
 
     let message: Message = deserialize(&serialized_message)
-        .map_err(|e| format!("Failed to deserialize message: {:?}", e))?;
+        .map_err(|e| MarketplaceError::invalid_input(format!("Failed to deserialize message: {:?}", e)))?;
 
-    validate_transaction(
+    let signers = CollectionSigners::new(&collection_id).await;
+
+    let lamports = validate_transaction(
         &message,
         &transaction_type,
-        &collection_id,
+        &signers,
         user_wallet_address.as_deref(),
+        candy_machine_created(&collection_id, None),
     )?;
 
-    let canister_wallet = SolanaWallet::new(ic_cdk::api::canister_self()).await;
-    let payer = canister_wallet.solana_account();
-
-    ic_cdk::println!(
+    log!(
+        Priority::Info,
         "Signing and sending {:?} transaction for collection {}",
         transaction_type,
         collection_id
     );
 
-    let signatures = match transaction_type {
-        TransactionType::CreateCandyMachine => {
-            vec![
-                payer.sign_message(&message).await,
-                payer.sign_message(&message).await,
-            ]
-        }
-        TransactionType::TransferAuthority | TransactionType::UpdateCandyMachine => {
-            vec![payer.sign_message(&message).await]
-        }
-    };
+    let signatures = signers.sign(&message).await?;
 
     let transaction = Transaction {
         message,
         signatures,
     };
 
-    let multi_result = client().send_transaction(transaction).send().await;
-
-    let signature = match multi_result {
-        sol_rpc_types::MultiRpcResult::Consistent(result) => {
-            ic_cdk::println!("All RPC providers agree on transaction result");
-            result.map_err(|e| format!("Failed to send transaction: {}", e))?
+    let step = match (transaction_type, user_wallet_address) {
+        (TransactionType::TransferAuthority, Some(new_authority)) => {
+            DeploymentStep::TransferAuthority { new_authority }
         }
-        sol_rpc_types::MultiRpcResult::Inconsistent(results) => {
-            ic_cdk::println!(
-                "RPC providers returned inconsistent results, using majority consensus"
-            );
+        (transaction_type, _) => DeploymentStep::ClientTransaction { transaction_type },
+    };
+    let signature =
+        send_charged(&collection_id, lamports, transaction, step, serialized_message, None, None).await?;
 
-            let mut successes = Vec::new();
-            let mut failures = Vec::new();
+    log!(Priority::Info, "Transaction sent successfully: {}", signature);
 
-            for (source, result) in results.iter() {
-                match result {
-                    Ok(sig) => {
-                        ic_cdk::println!("Provider {:?} succeeded with signature: {}", source, sig);
-                        successes.push(sig.clone());
-                    }
-                    Err(e) => {
-                        ic_cdk::println!("Provider {:?} failed with error: {:?}", source, e);
-                        failures.push(e);
-                    }
-                }
-            }
+    Ok(signature.to_string())
+}
 
-            if successes.len() >= 2 {
-                ic_cdk::println!(
-                    "Majority consensus: {} providers succeeded",
-                    successes.len()
-                );
-                successes[0].clone()
-            } else {
-                return Err(format!(
-                    "Transaction failed consensus: {} successes, {} failures",
-                    successes.len(),
-                    failures.len()
-                ));
-            }
+/// The canister-derived accounts of a collection: the only keys the canister signs with.
+struct CollectionSigners {
+    payer: SolanaAccount,
+    candy_machine: SolanaAccount,
+    collection_mint: SolanaAccount,
+}
+
+impl CollectionSigners {
+    async fn new(collection_id: &str) -> Self {
+        let canister_wallet = SolanaWallet::new(canister_self()).await;
+        Self {
+            payer: canister_wallet.solana_account(),
+            candy_machine: canister_wallet.candy_machine_account(collection_id),
+            collection_mint: canister_wallet.collection_account(collection_id),
         }
-    };
+    }
 
-    ic_cdk::println!("Transaction sent successfully: {}", signature);
+    fn account(&self, pubkey: &Pubkey) -> Option<&SolanaAccount> {
+        [&self.payer, &self.candy_machine, &self.collection_mint]
+            .into_iter()
+            .find(|account| account.as_ref() == pubkey)
+    }
 
-    Ok(signature.to_string())
+    /// Signs for each required signer, in message order, with the matching derived key.
+    async fn sign(&self, message: &Message) -> MarketplaceResult<Vec<Signature>> {
+        let num_signatures = message.header.num_required_signatures as usize;
+        let mut signatures = Vec::with_capacity(num_signatures);
+        for (index, signer) in message.account_keys.iter().take(num_signatures).enumerate() {
+            let account = self.account(signer).ok_or_else(|| {
+                MarketplaceError::invalid_input(format!(
+                    "Transaction requires signer {} that the canister cannot authorize",
+                    bs58::encode(signer).into_string()
+                ))
+            })?;
+            log!(Priority::Debug, "Signing as {} at position {}", account, index);
+            signatures.push(account.sign_message(message).await);
+        }
+        Ok(signatures)
+    }
 }
 
+/// Checks every instruction of `message` against the allowlist for `transaction_type`
+/// before the canister signs it with the collection's keys. Returns the lamports
+/// the transaction can take from the canister payer. Once `candy_machine_created`,
+/// the candy machine account cannot be created or initialized again.
 fn validate_transaction(
     message: &Message,
     transaction_type: &TransactionType,
-    collection_id: &str,
+    signers: &CollectionSigners,
     user_wallet_address: Option<&str>,
-) -> Result<(), String> {
+    candy_machine_created: bool,
+) -> MarketplaceResult<u64> {
     if message.instructions.is_empty() {
-        return Err("Transaction has no instructions".to_string());
+        return Err(MarketplaceError::invalid_input("Transaction has no instructions"));
+    }
+    if message.account_keys.first() != Some(signers.payer.as_ref()) {
+        return Err(MarketplaceError::invalid_input("Fee payer must be the canister payer account"));
     }
 
-    match transaction_type {
-        TransactionType::CreateCandyMachine => {
-            ic_cdk::println!(
-                "Validating CreateCandyMachine transaction for collection {}",
-                collection_id
-            );
-        }
+    let new_authority = match transaction_type {
         TransactionType::TransferAuthority => {
-            if user_wallet_address.is_none() {
-                return Err("User wallet address required for authority transfer".to_string());
-            }
-            ic_cdk::println!(
-                "Validating TransferAuthority transaction for collection {}",
-                collection_id
-            );
-        }
-        TransactionType::UpdateCandyMachine => {
-            ic_cdk::println!(
-                "Validating UpdateCandyMachine transaction for collection {}",
-                collection_id
-            );
+            let address = user_wallet_address.ok_or_else(|| {
+                MarketplaceError::invalid_input("User wallet address required for authority transfer")
+            })?;
+            Some(Pubkey::from_str(address).map_err(|e| {
+                MarketplaceError::invalid_input(format!("Invalid user wallet address: {:?}", e))
+            })?)
         }
-    }
-
-    Ok(())
-}
-
-pub async fn create_candy_machine_from_instruction(
-    collection_id: String,
-    instructions_data: Vec<InstructionData>,
-) -> Result<String, String> {
-    let _collection = get_collection(&collection_id).ok_or("Collection not found")?;
-
-    ic_cdk::println!(
-        "Creating Candy Machine from instruction for collection {}",
-        collection_id
-    );
-
-    let canister_wallet = SolanaWallet::new(canister_self()).await;
-    let payer = canister_wallet.solana_account();
-    let candy_machine_account = canister_wallet.candy_machine_account(&collection_id);
-    let collection_mint_account = canister_wallet.collection_account(&collection_id);
-
-    let payer_pubkey = *payer.as_ref();
-    let candy_machine_pubkey = *candy_machine_account.as_ref();
-    let collection_mint_pubkey = *collection_mint_account.as_ref();
+        TransactionType::CreateCandyMachine | TransactionType::UpdateCandyMachine => None,
+    };
 
-    ic_cdk::println!(
-        "Canister payer address: {}",
-        bs58::encode(payer.as_ref()).into_string()
-    );
-    ic_cdk::println!(
-        "Derived candy machine address: {}",
-        bs58::encode(candy_machine_account.as_ref()).into_string()
-    );
-    ic_cdk::println!(
-        "Derived collection mint address: {}",
-        bs58::encode(collection_mint_account.as_ref()).into_string()
-    );
+    let payer = signers.payer.as_ref();
+    let candy_machine = signers.candy_machine.as_ref();
+    let collection_mint = signers.collection_mint.as_ref();
+    let candy_guard = instructions::candy_guard_pda(candy_machine);
+    let mut lamports_out: u64 = 0;
+    let mut compute_unit_limit = None;
+    let mut compute_unit_price = 0;
+    let mut other_instructions = 0;
+
+    for (idx, compiled) in message.instructions.iter().enumerate() {
+        let reject = |reason: String| MarketplaceError::invalid_input(format!("Instruction {}: {}", idx, reason));
+        let account = |position: usize| {
+            compiled
+                .accounts
+                .get(position)
+                .and_then(|&key_index| message.account_keys.get(key_index as usize))
+                .ok_or_else(|| reject(format!("missing account {}", position)))
+        };
 
-    let mut instructions: Vec<Instruction> = Vec::new();
+        let program_id = message
+            .account_keys
+            .get(compiled.program_id_index as usize)
+            .ok_or_else(|| reject("invalid program index".to_string()))?;
+        let instruction = programs::decode_instruction(program_id, &compiled.data).map_err(&reject)?;
 
-    for (idx, instruction_data) in instructions_data.into_iter().enumerate() {
-        ic_cdk::println!(
-            "[create_cm] Instruction {} program_id={} data_len={}",
+        log!(
+            Priority::Debug,
+            "Validating {:?} instruction {}: {:?}",
+            transaction_type,
             idx,
-            instruction_data.program_id,
-            instruction_data.data.len()
+            instruction
         );
 
-        let program_id = Pubkey::from_str(&instruction_data.program_id)
-            .map_err(|e| format!("Invalid program ID: {:?}", e))?;
-
-        let mut account_metas = Vec::new();
-        let mut found_candy_machine = false;
-
-        for (acct_idx, account) in instruction_data.accounts.into_iter().enumerate() {
-            let pubkey = Pubkey::from_str(&account.pubkey)
-                .map_err(|e| format!("Invalid account pubkey: {:?}", e))?;
-
-            ic_cdk::println!(
-                "[create_cm]   Instruction {} Account {}: {} signer={} writable={}",
-                idx,
-                acct_idx,
-                bs58::encode(pubkey.to_bytes()).into_string(),
-                account.is_signer,
-                account.is_writable
-            );
+        lamports_out = lamports_out.saturating_add(instruction.lamports_paid());
+        if !matches!(
+            instruction,
+            ProgramInstruction::SetComputeUnitLimit(_) | ProgramInstruction::SetComputeUnitPrice(_)
+        ) {
+            other_instructions += 1;
+        }
 
-            if account.is_signer {
-                let is_payer = pubkey == payer_pubkey;
-                let is_candy_machine = pubkey == candy_machine_pubkey;
-                let is_collection_mint = pubkey == collection_mint_pubkey;
-
-                if !is_payer && !is_candy_machine && !is_collection_mint {
-                    return Err(format!(
-                        "Transaction requires signer {} that the canister cannot authorize. Payer={}, CandyMachine={}, CollectionMint={}",
-                        bs58::encode(pubkey.to_bytes()).into_string(),
-                        bs58::encode(payer_pubkey.to_bytes()).into_string(),
-                        bs58::encode(candy_machine_pubkey.to_bytes()).into_string(),
-                        bs58::encode(collection_mint_pubkey.to_bytes()).into_string()
-                    ));
+        match (transaction_type, instruction) {
+            (_, ProgramInstruction::SetComputeUnitLimit(limit)) => {
+                if limit > MAX_COMPUTE_UNIT_LIMIT {
+                    return Err(reject(format!(
+                        "compute unit limit {} exceeds {}",
+                        limit, MAX_COMPUTE_UNIT_LIMIT
+                    )));
                 }
+                compute_unit_limit = Some(limit);
             }
-
-            if pubkey == candy_machine_pubkey {
-                found_candy_machine = true;
+            (_, ProgramInstruction::SetComputeUnitPrice(price)) => {
+                if price > MAX_COMPUTE_UNIT_PRICE {
+                    return Err(reject(format!(
+                        "compute unit price {} exceeds {}",
+                        price, MAX_COMPUTE_UNIT_PRICE
+                    )));
+                }
+                compute_unit_price = price;
+            }
+            (TransactionType::CreateCandyMachine, ProgramInstruction::CreateAccount { lamports, space, owner }) => {
+                if account(0)? != payer {
+                    return Err(reject("new accounts must be funded by the canister payer".to_string()));
+                }
+                let new_account = account(1)?;
+                if new_account == candy_machine && candy_machine_created {
+                    return Err(reject("the candy machine has already been created".to_string()));
+                }
+                if new_account != candy_machine && new_account != collection_mint {
+                    return Err(reject(format!(
+                        "cannot create account {}",
+                        bs58::encode(new_account).into_string()
+                    )));
+                }
+                if owner != programs::CANDY_MACHINE_PROGRAM_ID && owner != programs::TOKEN_PROGRAM_ID {
+                    return Err(reject(format!("cannot assign new account to {}", owner)));
+                }
+                if lamports > programs::rent_exempt_minimum(space) {
+                    return Err(reject(format!(
+                        "{} lamports exceeds the rent-exempt minimum for {} bytes",
+                        lamports, space
+                    )));
+                }
+            }
+            (
+                TransactionType::CreateCandyMachine,
+                ProgramInstruction::InitializeMint | ProgramInstruction::MintTo,
+            ) => {
+                if account(0)? != collection_mint {
+                    return Err(reject("token instructions must target the collection mint".to_string()));
+                }
+            }
+            (TransactionType::CreateCandyMachine, ProgramInstruction::TokenMetadata(instruction)) => {
+                if account(instruction.mint_position())? != collection_mint {
+                    return Err(reject(format!("{:?} must target the collection mint", instruction)));
+                }
+            }
+            (transaction_type, ProgramInstruction::CandyMachine(instruction)) => {
+                let allowed = match transaction_type {
+                    TransactionType::CreateCandyMachine => matches!(
+                        instruction,
+                        CandyMachineInstruction::InitializeV2
+                            | CandyMachineInstruction::AddConfigLines
+                            | CandyMachineInstruction::SetCollectionV2
+                    ),
+                    TransactionType::UpdateCandyMachine => matches!(
+                        instruction,
+                        CandyMachineInstruction::Update
+                            | CandyMachineInstruction::AddConfigLines
+                            | CandyMachineInstruction::SetCollectionV2
+                            | CandyMachineInstruction::SetTokenStandard
+                    ),
+                    TransactionType::TransferAuthority => match instruction {
+                        CandyMachineInstruction::SetAuthority { new_authority: to } => {
+                            if Some(to) != new_authority {
+                                return Err(reject("new authority must be the user wallet".to_string()));
+                            }
+                            true
+                        }
+                        _ => false,
+                    },
+                };
+                if !allowed {
+                    return Err(reject(format!("{:?} is not allowed in this transaction", instruction)));
+                }
+                if instruction == CandyMachineInstruction::InitializeV2 && candy_machine_created {
+                    return Err(reject("the candy machine has already been created".to_string()));
+                }
+                if account(0)? != candy_machine {
+                    return Err(reject("instruction must target the collection's candy machine".to_string()));
+                }
+                // Position of the collection mint the candy machine is (re)assigned to
+                let collection_position = match instruction {
+                    CandyMachineInstruction::InitializeV2 => Some(6),
+                    CandyMachineInstruction::SetCollectionV2 => Some(9),
+                    _ => None,
+                };
+                if let Some(position) = collection_position {
+                    if account(position)? != collection_mint {
+                        return Err(reject("candy machine collection must be the collection's mint".to_string()));
+                    }
+                }
+            }
+            (
+                TransactionType::CreateCandyMachine | TransactionType::UpdateCandyMachine,
+                ProgramInstruction::CandyGuard(instruction),
+            ) => {
+                let allowed = matches!(
+                    (transaction_type, &instruction),
+                    (
+                        TransactionType::CreateCandyMachine,
+                        CandyGuardInstruction::Initialize { .. } | CandyGuardInstruction::Wrap
+                    ) | (TransactionType::UpdateCandyMachine, CandyGuardInstruction::Update { .. })
+                );
+                if !allowed {
+                    return Err(reject(format!("{:?} is not allowed in this transaction", instruction)));
+                }
+                if *account(0)? != candy_guard {
+                    return Err(reject("instruction must target the collection's candy guard".to_string()));
+                }
+                let candy_machine_position = match instruction {
+                    CandyGuardInstruction::Initialize { .. } => Some(1),
+                    CandyGuardInstruction::Wrap => Some(2),
+                    CandyGuardInstruction::Update { .. } => None,
+                };
+                if let Some(position) = candy_machine_position {
+                    if account(position)? != candy_machine {
+                        return Err(reject("candy guard must belong to the collection's candy machine".to_string()));
+                    }
+                }
+            }
+            (transaction_type, instruction) => {
+                return Err(reject(format!(
+                    "{:?} is not allowed in a {:?} transaction",
+                    instruction, transaction_type
+                )));
             }
-
-            account_metas.push(solana_instruction::AccountMeta {
-                pubkey,
-                is_signer: account.is_signer,
-                is_writable: account.is_writable,
-            });
         }
+    }
 
-        // Note: We don't require candy_machine to be present in every instruction
-        // Collection NFT creation instructions won't have it, only Candy Machine creation will
-        ic_cdk::println!(
-            "[create_cm]   Instruction {} includes candy_machine: {}",
-            idx,
-            found_candy_machine
-        );
+    if other_instructions == 0 {
+        return Err(MarketplaceError::invalid_input(
+            "Transaction has only compute budget instructions",
+        ));
+    }
 
-        instructions.push(Instruction {
-            program_id,
-            accounts: account_metas,
-            data: instruction_data.data,
-        });
+    let fees = (message.header.num_required_signatures as u64)
+        .saturating_mul(programs::LAMPORTS_PER_SIGNATURE)
+        .saturating_add(priority_fees::priority_fee_lamports(
+            compute_unit_limit,
+            compute_unit_price,
+            other_instructions,
+        ));
+    lamports_out = lamports_out.saturating_add(fees);
+
+    if lamports_out > MAX_LAMPORTS_PER_TRANSACTION {
+        return Err(MarketplaceError::invalid_input(format!(
+            "Transaction moves {} lamports out of the canister payer, above the {} limit",
+            lamports_out, MAX_LAMPORTS_PER_TRANSACTION
+        )));
     }
 
-    let blockhash = client()
-        .estimate_recent_blockhash()
-        .send()
-        .await
-        .map_err(|e| format!("Failed to get recent blockhash: {:?}", e))?;
+    Ok(lamports_out)
+}
 
-    ic_cdk::println!("Got recent blockhash: {:?}", blockhash);
+/// Whether the collection's candy machine exists or a transaction creating it,
+/// other than the tracked transaction `except`, is in flight or landed.
+fn candy_machine_created(collection_id: &str, except: Option<u64>) -> bool {
+    let recorded = get_collection(collection_id).is_some_and(|collection| {
+        matches!(&collection.chain_data, ChainData::Solana(data) if data.candy_machine_address.is_some())
+    });
+    recorded
+        || state::get_deployment_timeline(collection_id).iter().any(|transaction| {
+            Some(transaction.id) != except
+                && matches!(transaction.step, DeploymentStep::CreateCandyMachine { .. })
+                && !matches!(
+                    transaction.status,
+                    DeploymentTransactionStatus::Failed { .. } | DeploymentTransactionStatus::Expired
+                )
+        })
+}
 
-    let message =
-        Message::new_with_blockhash(instructions.as_slice(), Some(payer.as_ref()), &blockhash);
+/// Charges `lamports` to the collection's deposits and lifetime budget, sends
+/// `transaction` and tracks it as `step` of the collection's deployment. The charge
+/// is released only if every provider definitely rejected the transaction. When
+/// the outcome is unknown the transaction is tracked anyway, so the charge is
+/// settled once it lands or expires.
+async fn send_charged(
+    collection_id: &str,
+    lamports: u64,
+    transaction: Transaction,
+    step: DeploymentStep,
+    serialized_message: Vec<u8>,
+    priority_fee: Option<PriorityFee>,
+    last_valid_block_height: Option<u64>,
+) -> MarketplaceResult<Signature> {
+    let spent = state::get_lamports_spent(collection_id);
+    if spent.saturating_add(lamports) > MAX_LAMPORTS_PER_COLLECTION {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Collection has used {} of its {} lamport budget, the transaction needs {}",
+            spent, MAX_LAMPORTS_PER_COLLECTION, lamports
+        )));
+    }
+    let deposited = state::get_lamports_deposited(collection_id);
+    if spent.saturating_add(lamports) > deposited {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Collection has {} lamports left from its deposits, the transaction needs {}",
+            deposited.saturating_sub(spent),
+            lamports
+        )));
+    }
+    state::add_lamports_spent(collection_id, lamports);
 
-    let num_signatures = message.header.num_required_signatures as usize;
+    let (signature, result) = match send_transaction(transaction).await {
+        Ok(signature) => (signature, Ok(signature)),
+        Err(SendFailure::Rejected(e)) => {
+            state::release_lamports_spent(collection_id, lamports);
+            return Err(e);
+        }
+        Err(SendFailure::Unknown { signature, error }) => {
+            log!(
+                Priority::Info,
+                "Transaction {} may have reached the cluster, tracking it: {}",
+                signature,
+                error
+            );
+            (signature, Err(error))
+        }
+    };
+    state::track_deployment_transaction(
+        collection_id,
+        step,
+        signature.to_string(),
+        serialized_message,
+        priority_fee,
+        lamports,
+        last_valid_block_height,
+    );
+    result
+}
 
-    ic_cdk::println!("Number of required signatures: {}", num_signatures);
-    let num_signed_writable =
-        num_signatures.saturating_sub(message.header.num_readonly_signed_accounts as usize);
-    let total_unsigned = message.account_keys.len().saturating_sub(num_signatures);
-    let num_unsigned_writable =
-        total_unsigned.saturating_sub(message.header.num_readonly_unsigned_accounts as usize);
+fn solana_data(collection: &Collection) -> MarketplaceResult<&SolanaCollectionData> {
+    match &collection.chain_data {
+        ChainData::Solana(data) => Ok(data),
+        _ => Err(MarketplaceError::invalid_state("Collection is not a Solana collection")),
+    }
+}
 
-    for (index, key) in message.account_keys.iter().enumerate() {
-        let signer = index < num_signatures;
-        let writable = if signer {
-            index < num_signed_writable
-        } else {
-            let unsigned_index = index - num_signatures;
-            unsigned_index < num_unsigned_writable
-        };
+fn parse_pubkey(field: &str, value: &str) -> MarketplaceResult<Pubkey> {
+    Pubkey::from_str(value)
+        .map_err(|e| MarketplaceError::invalid_input(format!("Invalid {}: {:?}", field, e)))
+}
 
-        ic_cdk::println!(
-            "Account {}: {} signer={} writable={}",
-            index,
-            bs58::encode(key).into_string(),
-            signer,
-            writable
-        );
-    }
+/// The blockhash and height of the latest block.
+async fn latest_block() -> MarketplaceResult<(Hash, u64)> {
+    let slot = match client().get_slot().send().await {
+        MultiRpcResult::Consistent(Ok(slot)) => slot,
+        MultiRpcResult::Consistent(Err(e)) => {
+            return Err(MarketplaceError::rpc(format!("Failed to get slot: {}", e)))
+        }
+        MultiRpcResult::Inconsistent(_) => return Err(MarketplaceError::rpc("RPC providers disagree on the slot")),
+    };
+    let request = client()
+        .get_block(slot)
+        .with_transaction_details(TransactionDetails::None)
+        .with_max_supported_transaction_version(0)
+        .without_rewards();
+    let block = match request.send().await {
+        MultiRpcResult::Consistent(Ok(Some(block))) => block,
+        MultiRpcResult::Consistent(Ok(None)) => {
+            return Err(MarketplaceError::rpc(format!("No block at slot {}", slot)))
+        }
+        MultiRpcResult::Consistent(Err(e)) => {
+            return Err(MarketplaceError::rpc(format!("Failed to get block {}: {}", slot, e)))
+        }
+        MultiRpcResult::Inconsistent(_) => {
+            return Err(MarketplaceError::rpc(format!("RPC providers disagree on block {}", slot)))
+        }
+    };
+    let blockhash = Hash::from_str(&block.blockhash)
+        .map_err(|e| MarketplaceError::rpc(format!("Invalid blockhash of block {}: {:?}", slot, e)))?;
+    let height = block
+        .block_height
+        .ok_or_else(|| MarketplaceError::rpc(format!("Block {} has no height", slot)))?;
+    Ok((blockhash, height))
+}
 
-    let mut signatures = Vec::new();
-    for (index, signer) in message.account_keys.iter().take(num_signatures).enumerate() {
-        if signer == payer.as_ref() {
-            ic_cdk::println!("Signing as payer at position {}", index);
-            signatures.push(payer.sign_message(&message).await);
-        } else if signer == candy_machine_account.as_ref() {
-            ic_cdk::println!("Signing as candy machine at position {}", index);
-            signatures.push(candy_machine_account.sign_message(&message).await);
-        } else if signer == collection_mint_account.as_ref() {
-            ic_cdk::println!("Signing as collection mint at position {}", index);
-            signatures.push(collection_mint_account.sign_message(&message).await);
-        } else {
-            return Err(format!(
-                "Transaction requires signer {} that the canister cannot authorize",
-                bs58::encode(signer).into_string()
-            ));
+/// A recent blockhash and the last block height at which transactions using it can land.
+pub async fn recent_blockhash() -> MarketplaceResult<(Hash, u64)> {
+    let mut errors = Vec::new();
+    // The slot may have been skipped, in which case a later one has a block
+    for _ in 0..BLOCKHASH_TRIES {
+        match latest_block().await {
+            Ok((blockhash, height)) => return Ok((blockhash, height + BLOCKHASH_VALIDITY_BLOCKS)),
+            Err(e) => errors.push(e.to_string()),
         }
     }
+    Err(MarketplaceError::rpc(format!("Failed to get recent blockhash: {:?}", errors)))
+}
+
+pub async fn current_block_height() -> MarketplaceResult<u64> {
+    latest_block().await.map(|(_, height)| height)
+}
 
+/// Builds a transaction paid by the canister from `instructions` and a priority fee,
+/// checks it like a client-built one, then signs and sends it. The transaction is
+/// tracked as `step` of the collection's deployment until it is finalized.
+async fn send_instructions(
+    collection_id: &str,
+    signers: &CollectionSigners,
+    instructions: &[Instruction],
+    transaction_type: TransactionType,
+    step: DeploymentStep,
+    user_wallet_address: Option<&str>,
+) -> MarketplaceResult<String> {
+    let (instructions, priority_fee) =
+        priority_fees::with_compute_budget(instructions, DEPLOYMENT_COMPUTE_UNIT_LIMIT).await;
+    let (blockhash, last_valid_block_height) = recent_blockhash().await?;
+
+    let message = Message::new_with_blockhash(&instructions, Some(signers.payer.as_ref()), &blockhash);
+    let lamports = validate_transaction(
+        &message,
+        &transaction_type,
+        signers,
+        user_wallet_address,
+        candy_machine_created(collection_id, None),
+    )?;
+
+    let num_signatures = message.header.num_required_signatures as u64;
+    let size = bincode::serialized_size(&message).unwrap_or(u64::MAX) + 1 + num_signatures * 64;
+    if size > MAX_TRANSACTION_SIZE {
+        return Err(MarketplaceError::invalid_input(format!(
+            "Transaction is {} bytes, above the {} byte limit",
+            size, MAX_TRANSACTION_SIZE
+        )));
+    }
+
+    let serialized_message = bincode::serialize(&message)
+        .map_err(|e| MarketplaceError::internal(format!("Failed to serialize message: {:?}", e)))?;
+    let signatures = signers.sign(&message).await?;
     let transaction = Transaction {
         message,
         signatures,
     };
 
-    let multi_result = client().send_transaction(transaction).send().await;
+    let signature = send_charged(
+        collection_id,
+        lamports,
+        transaction,
+        step,
+        serialized_message,
+        Some(priority_fee.clone()),
+        Some(last_valid_block_height),
+    )
+    .await?;
+
+    log!(
+        Priority::Info,
+        "{:?} transaction sent successfully: {} ({:?})",
+        transaction_type,
+        signature,
+        priority_fee
+    );
 
-    let signature = match multi_result {
-        sol_rpc_types::MultiRpcResult::Consistent(result) => {
-            ic_cdk::println!("All RPC providers agree on transaction result");
-            result.map_err(|e| format!("Failed to send transaction: {}", e))?
-        }
-        sol_rpc_types::MultiRpcResult::Inconsistent(results) => {
-            ic_cdk::println!(
-                "RPC providers returned inconsistent results, using majority consensus"
-            );
+    Ok(signature.to_string())
+}
 
-            let mut successes = Vec::new();
-            let mut failures = Vec::new();
+/// Re-signs a tracked transaction with `blockhash`, fresh after the previous one
+/// expired before the transaction landed, and sends it again. The message is
+/// validated again first, against the collection as it is now. It was charged
+/// when first sent, and is not charged again.
+pub async fn resubmit_transaction(
+    transaction: &DeploymentTransaction,
+    serialized_message: &[u8],
+    blockhash: Hash,
+) -> Result<Signature, SendFailure> {
+    let collection_id = &transaction.collection_id;
+    let mut message: Message = deserialize(serialized_message)
+        .map_err(|e| MarketplaceError::internal(format!("Failed to deserialize message: {:?}", e)))?;
+    message.recent_blockhash = blockhash;
+
+    let signers = CollectionSigners::new(collection_id).await;
+    let (transaction_type, user_wallet_address) = step_transaction_type(collection_id, &transaction.step);
+    validate_transaction(
+        &message,
+        &transaction_type,
+        &signers,
+        user_wallet_address,
+        candy_machine_created(collection_id, Some(transaction.id)),
+    )?;
+    let signatures = signers.sign(&message).await?;
 
-            for (source, result) in results.iter() {
-                match result {
-                    Ok(sig) => {
-                        ic_cdk::println!("Provider {:?} succeeded with signature: {}", source, sig);
-                        successes.push(sig.clone());
-                    }
-                    Err(e) => {
-                        ic_cdk::println!("Provider {:?} failed with error: {:?}", source, e);
-                        failures.push(e);
-                    }
-                }
-            }
+    send_transaction(Transaction {
+        message,
+        signatures,
+    })
+    .await
+}
 
-            if successes.len() >= 2 {
-                ic_cdk::println!(
-                    "Majority consensus: {} providers succeeded, using transaction",
-                    successes.len()
-                );
-                successes[0].clone()
+/// The transaction type, and user wallet for an authority transfer, that a tracked
+/// step was validated as when it was first sent.
+fn step_transaction_type<'a>(collection_id: &str, step: &'a DeploymentStep) -> (TransactionType, Option<&'a str>) {
+    match step {
+        DeploymentStep::CreateCollectionNft { .. } | DeploymentStep::CreateCandyMachine { .. } => {
+            (TransactionType::CreateCandyMachine, None)
+        }
+        DeploymentStep::AddItems { .. } | DeploymentStep::SetCollection { .. } => {
+            (TransactionType::UpdateCandyMachine, None)
+        }
+        DeploymentStep::TransferAuthority { new_authority } => {
+            (TransactionType::TransferAuthority, Some(new_authority.as_str()))
+        }
+        // The first guard is created and wrapped, later ones update it
+        DeploymentStep::SetCandyGuard { .. } => {
+            let guard_created = get_collection(collection_id).is_some_and(|collection| {
+                matches!(&collection.chain_data, ChainData::Solana(data) if data.candy_guard_address.is_some())
+            });
+            if guard_created {
+                (TransactionType::UpdateCandyMachine, None)
             } else {
-                return Err(format!(
-                    "Transaction failed consensus: {} successes, {} failures. Errors: {:?}",
-                    successes.len(),
-                    failures.len(),
-                    failures
-                ));
+                (TransactionType::CreateCandyMachine, None)
             }
         }
-    };
+        DeploymentStep::ClientTransaction { transaction_type } => (transaction_type.clone(), None),
+    }
+}
 
-    ic_cdk::println!("Transaction sent successfully: {}", signature);
+/// Creates and mints the collection NFT at the collection's derived mint address,
+/// using the uploaded manifest as its metadata.
+pub async fn create_collection_nft(collection_id: &str) -> MarketplaceResult<String> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let data = solana_data(&collection)?;
+
+    let uri = data
+        .manifest_url
+        .clone()
+        .ok_or_else(|| MarketplaceError::invalid_state("Collection metadata has not been uploaded"))?;
+    if collection.name.len() > instructions::MAX_NAME_LENGTH {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Collection name is longer than {} bytes",
+            instructions::MAX_NAME_LENGTH
+        )));
+    }
+    if collection.symbol.len() > instructions::MAX_SYMBOL_LENGTH {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Collection symbol is longer than {} bytes",
+            instructions::MAX_SYMBOL_LENGTH
+        )));
+    }
+    if uri.len() > instructions::MAX_URI_LENGTH {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Manifest URL is longer than {} bytes",
+            instructions::MAX_URI_LENGTH
+        )));
+    }
 
-    Ok(signature.to_string())
+    let signers = CollectionSigners::new(collection_id).await;
+    let payer = signers.payer.as_ref();
+    let mint = signers.collection_mint.as_ref();
+
+    log!(Priority::Info, "Creating collection NFT {} for collection {}", mint, collection_id);
+
+    send_instructions(
+        collection_id,
+        &signers,
+        &[
+            instructions::create_collection_nft(
+                mint,
+                payer,
+                collection.name.clone(),
+                collection.symbol.clone(),
+                uri,
+                collection.royalty_bps,
+            ),
+            instructions::mint_collection_nft(mint, payer),
+        ],
+        TransactionType::CreateCandyMachine,
+        DeploymentStep::CreateCollectionNft {
+            collection_mint: mint.to_string(),
+        },
+        None,
+    )
+    .await
 }
 
-pub async fn add_items_to_candy_machine(
-    collection_id: String,
-    instruction_data: InstructionData,
-) -> Result<String, String> {
-    ic_cdk::println!(
-        "add_items_to_candy_machine: program_id={} data_len={}",
-        instruction_data.program_id,
-        instruction_data.data.len()
-    );
-    let preview: Vec<String> = instruction_data
-        .data
-        .iter()
-        .take(16)
-        .map(|b| format!("{:02x}", b))
-        .collect();
-    ic_cdk::println!(
-        "add_items_to_candy_machine: data preview (first 16 bytes hex): {:?}",
-        preview
-    );
-
-    let collection = get_collection(&collection_id).ok_or("Collection not found")?;
+/// Creates the candy machine account and initializes it from the stored
+/// `CandyMachineConfig`, with the collection NFT as its collection.
+pub async fn create_candy_machine(collection_id: &str) -> MarketplaceResult<String> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let data = solana_data(&collection)?;
+
+    let config = data
+        .candy_machine_config
+        .as_ref()
+        .ok_or_else(|| MarketplaceError::invalid_state("Collection has no Candy Machine config"))?;
+    let collection_mint = data
+        .collection_mint
+        .as_deref()
+        .ok_or_else(|| MarketplaceError::invalid_state("Collection NFT has not been created"))?;
+    let collection_mint = parse_pubkey("collection mint", collection_mint)?;
+    if candy_machine_created(collection_id, None) {
+        return Err(MarketplaceError::invalid_state("Candy Machine has already been created"));
+    }
+    if config.items_available == 0 || config.items_available > MAX_CANDY_MACHINE_ITEMS {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Candy Machine must hold between 1 and {} items",
+            MAX_CANDY_MACHINE_ITEMS
+        )));
+    }
+    if config.symbol.len() > instructions::MAX_SYMBOL_LENGTH {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Candy Machine symbol is longer than {} bytes",
+            instructions::MAX_SYMBOL_LENGTH
+        )));
+    }
 
-    let solana_data = match &collection.chain_data {
-        crate::types::ChainData::Solana(data) => data,
-        _ => return Err("Collection is not a Solana collection".to_string()),
-    };
+    let signers = CollectionSigners::new(collection_id).await;
+    let payer = signers.payer.as_ref();
+    let candy_machine = signers.candy_machine.as_ref();
 
-    if solana_data.candy_machine_address.is_none() {
-        return Err("Collection does not have a candy machine deployed".to_string());
-    }
+    let candy_machine_data = instructions::CandyMachineData::new(
+        config.items_available,
+        config.symbol.clone(),
+        config.seller_fee_basis_points,
+        payer,
+    );
+    let space = candy_machine_data.account_space();
 
-    ic_cdk::println!(
-        "Adding items to candy machine for collection {}",
+    log!(
+        Priority::Info,
+        "Creating Candy Machine {} ({} bytes) for collection {}",
+        candy_machine,
+        space,
         collection_id
     );
 
-    let canister_wallet = SolanaWallet::new(canister_self()).await;
-    let payer = canister_wallet.solana_account();
-    let candy_machine_account = canister_wallet.candy_machine_account(&collection_id);
-    let collection_mint_account = canister_wallet.collection_account(&collection_id);
+    let signature = send_instructions(
+        collection_id,
+        &signers,
+        &[
+            solana_system_interface::instruction::create_account(
+                payer,
+                candy_machine,
+                programs::rent_exempt_minimum(space),
+                space,
+                &programs::CANDY_MACHINE_PROGRAM_ID,
+            ),
+            instructions::initialize_v2(candy_machine, payer, &collection_mint, &candy_machine_data),
+        ],
+        TransactionType::CreateCandyMachine,
+        DeploymentStep::CreateCandyMachine {
+            candy_machine: candy_machine.to_string(),
+            authority: payer.to_string(),
+        },
+        None,
+    )
+    .await?;
+
+    // The address and authority are recorded once the transaction is finalized
+    state::update_solana_stage(UpdateSolanaStageArgs {
+        collection_id: collection_id.to_string(),
+        stage: SolanaDeploymentStage::CandyMachineDeploying,
+        candy_machine_address: None,
+        candy_machine_authority: None,
+        collection_mint: None,
+        manifest_url: None,
+        candy_machine_config: None,
+        files_uploaded: None,
+        metadata_created: None,
+    })?;
+
+    Ok(signature)
+}
 
-    let payer_pubkey = *payer.as_ref();
-    let candy_machine_pubkey = *candy_machine_account.as_ref();
-    let collection_mint_pubkey = *collection_mint_account.as_ref();
+/// Writes `items` into the candy machine's config lines, starting at `index`.
+pub async fn add_items_to_candy_machine(
+    collection_id: &str,
+    index: u32,
+    items: Vec<ConfigLine>,
+) -> MarketplaceResult<String> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let data = solana_data(&collection)?;
+
+    if data.candy_machine_address.is_none() {
+        return Err(MarketplaceError::invalid_state(
+            "Collection does not have a candy machine deployed",
+        ));
+    }
+    let items_available = data
+        .candy_machine_config
+        .as_ref()
+        .map(|config| config.items_available)
+        .unwrap_or_default();
+    if items.is_empty() {
+        return Err(MarketplaceError::invalid_input("No items to add"));
+    }
+    if index as u64 + items.len() as u64 > items_available {
+        return Err(MarketplaceError::invalid_input(format!(
+            "Items {}..{} are beyond the {} available",
+            index,
+            index as u64 + items.len() as u64,
+            items_available
+        )));
+    }
+    let fields: Vec<FieldError> = items
+        .iter()
+        .enumerate()
+        .flat_map(|(i, item)| {
+            let mut errors = vec![];
+            if item.name.len() > instructions::MAX_NAME_LENGTH {
+                errors.push(FieldError {
+                    field: format!("items[{}].name", i),
+                    message: format!("must be at most {} bytes", instructions::MAX_NAME_LENGTH),
+                });
+            }
+            if item.uri.len() > instructions::MAX_URI_LENGTH {
+                errors.push(FieldError {
+                    field: format!("items[{}].uri", i),
+                    message: format!("must be at most {} bytes", instructions::MAX_URI_LENGTH),
+                });
+            }
+            errors
+        })
+        .collect();
+    if !fields.is_empty() {
+        return Err(fields.into());
+    }
 
-    ic_cdk::println!(
-        "Payer: {}, Candy Machine: {}, Collection Mint: {}",
-        bs58::encode(payer.as_ref()).into_string(),
-        bs58::encode(candy_machine_account.as_ref()).into_string(),
-        bs58::encode(collection_mint_account.as_ref()).into_string()
-    );
+    let signers = CollectionSigners::new(collection_id).await;
 
-    let program_id = Pubkey::from_str(&instruction_data.program_id)
-        .map_err(|e| format!("Invalid program ID: {:?}", e))?;
+    log!(
+        Priority::Info,
+        "Adding {} items at index {} to candy machine for collection {}",
+        items.len(),
+        index,
+        collection_id
+    );
 
-    let mut account_metas = Vec::new();
-    let mut found_candy_machine = false;
+    send_instructions(
+        collection_id,
+        &signers,
+        &[instructions::add_config_lines(
+            signers.candy_machine.as_ref(),
+            signers.payer.as_ref(),
+            index,
+            &items,
+        )],
+        TransactionType::UpdateCandyMachine,
+        DeploymentStep::AddItems {
+            index,
+            count: items.len() as u32,
+        },
+        None,
+    )
+    .await
+}
 
-    for (idx, account) in instruction_data.accounts.into_iter().enumerate() {
-        let pubkey = Pubkey::from_str(&account.pubkey)
-            .map_err(|e| format!("Invalid account pubkey: {:?}", e))?;
+/// Points the candy machine back at the collection's own NFT, at the collection's
+/// derived mint address. No other collection can be set.
+pub async fn set_candy_machine_collection(
+    collection_id: &str,
+    new_collection_mint: &str,
+) -> MarketplaceResult<String> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let data = solana_data(&collection)?;
+
+    let (Some(_), Some(collection_mint)) = (&data.candy_machine_address, &data.collection_mint) else {
+        return Err(MarketplaceError::invalid_state(
+            "Collection does not have a candy machine deployed",
+        ));
+    };
+    let collection_mint = parse_pubkey("collection mint", collection_mint)?;
+    let new_collection_mint = parse_pubkey("new collection mint", new_collection_mint)?;
+
+    let signers = CollectionSigners::new(collection_id).await;
+    if new_collection_mint != *signers.collection_mint.as_ref() {
+        return Err(MarketplaceError::invalid_input(format!(
+            "Candy machine collection can only be the collection's mint {}",
+            signers.collection_mint
+        )));
+    }
 
-        ic_cdk::println!(
-            "[add_items] Account {}: {} signer={} writable_in_data={}",
-            idx,
-            bs58::encode(pubkey.to_bytes()).into_string(),
-            account.is_signer,
-            account.is_writable
-        );
+    send_instructions(
+        collection_id,
+        &signers,
+        &[instructions::set_collection_v2(
+            signers.candy_machine.as_ref(),
+            signers.payer.as_ref(),
+            &collection_mint,
+            &new_collection_mint,
+        )],
+        TransactionType::UpdateCandyMachine,
+        DeploymentStep::SetCollection {
+            collection_mint: new_collection_mint.to_string(),
+        },
+        None,
+    )
+    .await
+}
 
-        if account.is_signer {
-            let is_payer = pubkey == payer_pubkey;
-            let is_candy_machine = pubkey == candy_machine_pubkey;
-            let is_collection_mint = pubkey == collection_mint_pubkey;
-
-            if !is_payer && !is_candy_machine && !is_collection_mint {
-                return Err(format!(
-                    "Transaction requires signer {} that the canister cannot authorize. Payer={}, CandyMachine={}, CollectionMint={}",
-                    bs58::encode(pubkey.to_bytes()).into_string(),
-                    bs58::encode(payer_pubkey.to_bytes()).into_string(),
-                    bs58::encode(candy_machine_pubkey.to_bytes()).into_string(),
-                    bs58::encode(collection_mint_pubkey.to_bytes()).into_string()
-                ));
-            }
-        }
+/// Hands the candy machine authority to `new_authority`, typically the creator's
+/// wallet. The canister can no longer manage the candy machine afterwards.
+pub async fn transfer_candy_machine_authority(
+    collection_id: &str,
+    new_authority: &str,
+) -> MarketplaceResult<String> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    solana_data(&collection)?;
+    let new_authority_key = parse_pubkey("new authority", new_authority)?;
+
+    let signers = CollectionSigners::new(collection_id).await;
+
+    send_instructions(
+        collection_id,
+        &signers,
+        &[instructions::set_authority(
+            signers.candy_machine.as_ref(),
+            signers.payer.as_ref(),
+            &new_authority_key,
+        )],
+        TransactionType::TransferAuthority,
+        DeploymentStep::TransferAuthority {
+            new_authority: new_authority.to_string(),
+        },
+        Some(new_authority),
+    )
+    .await
+}
 
-        if pubkey == candy_machine_pubkey {
-            found_candy_machine = true;
-        }
+/// Sets the guards that every mint from the candy machine must pass. The first call
+/// creates the candy guard and makes it the candy machine's mint authority, which
+/// needs the canister to still hold the candy machine authority; later calls
+/// replace the guards.
+pub async fn set_candy_guard(collection_id: &str, config: CandyGuardConfig) -> MarketplaceResult<String> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let data = solana_data(&collection)?;
+
+    if data.candy_machine_address.is_none() {
+        return Err(MarketplaceError::invalid_state(
+            "Collection does not have a candy machine deployed",
+        ));
+    }
+    let guard_data = candy_guard::compile(&config)?;
 
-        let is_writable = account.is_writable || pubkey == candy_machine_pubkey;
+    let signers = CollectionSigners::new(collection_id).await;
+    let payer = signers.payer.as_ref();
+    let candy_machine = signers.candy_machine.as_ref();
+    let candy_guard = instructions::candy_guard_pda(candy_machine);
 
-        ic_cdk::println!(
-            "[add_items] Final meta {} signer={} writable_final={}",
-            bs58::encode(pubkey.to_bytes()).into_string(),
-            account.is_signer,
-            is_writable
-        );
+    let step = DeploymentStep::SetCandyGuard {
+        candy_guard: candy_guard.to_string(),
+        config,
+    };
 
-        account_metas.push(solana_instruction::AccountMeta {
-            pubkey,
-            is_signer: account.is_signer,
-            is_writable,
-        });
+    if data.candy_guard_address.is_some() {
+        log!(Priority::Info, "Updating candy guard {} for collection {}", candy_guard, collection_id);
+        return send_instructions(
+            collection_id,
+            &signers,
+            &[instructions::update_candy_guard(&candy_guard, payer, payer, guard_data)],
+            TransactionType::UpdateCandyMachine,
+            step,
+            None,
+        )
+        .await;
     }
 
-    if !found_candy_machine {
-        return Err(
-            "Instruction data does not reference the derived candy machine account".to_string(),
-        );
+    if data.candy_machine_authority.as_deref() != Some(payer.to_string().as_str()) {
+        return Err(MarketplaceError::invalid_state(
+            "Candy machine authority has been transferred, the canister can no longer add a candy guard",
+        ));
     }
 
-    let instruction = Instruction {
-        program_id,
-        accounts: account_metas,
-        data: instruction_data.data,
-    };
+    log!(Priority::Info, "Creating candy guard {} for collection {}", candy_guard, collection_id);
+
+    send_instructions(
+        collection_id,
+        &signers,
+        &[
+            instructions::initialize_candy_guard(candy_machine, payer, payer, guard_data),
+            instructions::wrap_candy_guard(&candy_guard, candy_machine, payer),
+        ],
+        TransactionType::CreateCandyMachine,
+        step,
+        None,
+    )
+    .await
+}
 
-    let blockhash = client()
-        .estimate_recent_blockhash()
+/// Reads the collection's candy machine from chain and records it on the collection.
+pub async fn sync_candy_machine_state(collection_id: &str) -> MarketplaceResult<CandyMachineState> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let data = solana_data(&collection)?;
+    let address = data
+        .candy_machine_address
+        .as_deref()
+        .ok_or_else(|| MarketplaceError::invalid_state("Collection does not have a candy machine deployed"))?;
+    let address = parse_pubkey("candy machine address", address)?;
+
+    let account = match client()
+        .get_account_info(address)
+        .with_encoding(GetAccountInfoEncoding::Base64)
+        .with_data_slice(DataSlice {
+            offset: 0,
+            length: accounts::CANDY_MACHINE_STATE_SIZE,
+        })
         .send()
         .await
-        .map_err(|e| format!("Failed to get recent blockhash: {:?}", e))?;
-
-    let message = Message::new_with_blockhash(&[instruction], Some(payer.as_ref()), &blockhash);
-
-    let num_signatures = message.header.num_required_signatures as usize;
+    {
+        MultiRpcResult::Consistent(Ok(account)) => account,
+        MultiRpcResult::Consistent(Err(e)) => {
+            return Err(MarketplaceError::rpc(format!("Failed to get candy machine account: {}", e)));
+        }
+        MultiRpcResult::Inconsistent(_) => {
+            return Err(MarketplaceError::rpc("RPC providers disagree on the candy machine account"));
+        }
+    };
+    let account = account.ok_or_else(|| {
+        MarketplaceError::invalid_state(format!("Candy machine account {} does not exist", address))
+    })?;
+    if account.owner != programs::CANDY_MACHINE_PROGRAM_ID.to_string() {
+        return Err(MarketplaceError::invalid_state(format!(
+            "Account {} is not owned by the Candy Machine program",
+            address
+        )));
+    }
+    let bytes = account
+        .data
+        .decode()
+        .ok_or_else(|| MarketplaceError::rpc("Unsupported account data encoding"))?;
+
+    let candy_machine = accounts::decode_candy_machine(&bytes, crate::time::get_current_time())
+        .map_err(MarketplaceError::invalid_state)?;
+
+    log!(
+        Priority::Debug,
+        "Candy machine {} for collection {}: {}/{} loaded, {} redeemed",
+        address,
+        collection_id,
+        candy_machine.items_loaded,
+        candy_machine.items_available,
+        candy_machine.items_redeemed
+    );
 
-    ic_cdk::println!("Number of required signatures: {}", num_signatures);
+    state::update_candy_machine_state(collection_id, candy_machine.clone())?;
+    Ok(candy_machine)
+}
 
-    let num_signed_writable =
-        num_signatures.saturating_sub(message.header.num_readonly_signed_accounts as usize);
-    let total_unsigned = message.account_keys.len().saturating_sub(num_signatures);
-    let num_unsigned_writable =
-        total_unsigned.saturating_sub(message.header.num_readonly_unsigned_accounts as usize);
+/// Why sending a transaction failed.
+pub enum SendFailure {
+    /// No provider forwarded the transaction to the cluster, so it cannot land.
+    Rejected(MarketplaceError),
+    /// A provider may have forwarded the transaction before failing, so it may
+    /// still land under `signature`.
+    Unknown {
+        signature: Signature,
+        error: MarketplaceError,
+    },
+}
 
-    for (index, key) in message.account_keys.iter().enumerate() {
-        let signer = index < num_signatures;
-        let writable = if signer {
-            index < num_signed_writable
-        } else {
-            let unsigned_index = index - num_signatures;
-            unsigned_index < num_unsigned_writable
-        };
+impl From<SendFailure> for MarketplaceError {
+    fn from(failure: SendFailure) -> Self {
+        match failure {
+            SendFailure::Rejected(error) | SendFailure::Unknown { error, .. } => error,
+        }
+    }
+}
 
-        ic_cdk::println!(
-            "Account {}: {} signer={} writable={}",
-            index,
-            bs58::encode(key).into_string(),
-            signer,
-            writable
-        );
+/// Errors before sending leave nothing on the network.
+impl From<MarketplaceError> for SendFailure {
+    fn from(error: MarketplaceError) -> Self {
+        SendFailure::Rejected(error)
     }
+}
 
-    let mut signatures = Vec::new();
-    for (index, signer) in message.account_keys.iter().take(num_signatures).enumerate() {
-        if signer == payer.as_ref() {
-            ic_cdk::println!("Signing as payer at position {}", index);
-            signatures.push(payer.sign_message(&message).await);
-        } else if signer == candy_machine_account.as_ref() {
-            ic_cdk::println!("Signing as candy machine at position {}", index);
-            signatures.push(candy_machine_account.sign_message(&message).await);
-        } else if signer == collection_mint_account.as_ref() {
-            ic_cdk::println!("Signing as collection mint at position {}", index);
-            signatures.push(collection_mint_account.sign_message(&message).await);
+/// Sends a signed transaction. With inconsistent provider results, at least two
+/// providers must have accepted the transaction.
+async fn send_transaction(transaction: Transaction) -> Result<Signature, SendFailure> {
+    let signature = transaction.signatures.first().copied().unwrap_or_default();
+    // A failed HTTP outcall may have reached the provider, any other error means the
+    // provider did not forward the transaction
+    let failure = |reached: bool, error: MarketplaceError| {
+        if reached {
+            SendFailure::Unknown { signature, error }
         } else {
-            return Err(format!(
-                "Transaction requires signer {} that the canister cannot authorize",
-                bs58::encode(signer).into_string()
-            ));
+            SendFailure::Rejected(error)
         }
-    }
-
-    let transaction = Transaction {
-        message,
-        signatures,
     };
 
-    let multi_result = client().send_transaction(transaction).send().await;
-
-    let signature = match multi_result {
+    match client().send_transaction(transaction).send().await {
         sol_rpc_types::MultiRpcResult::Consistent(result) => {
-            ic_cdk::println!("All RPC providers agree on transaction result");
-            result.map_err(|e| format!("Failed to send transaction: {}", e))?
+            log!(Priority::TraceHttp, "All RPC providers agree on transaction result");
+            result.map_err(|e| {
+                failure(
+                    matches!(e, RpcError::HttpOutcallError(_)),
+                    MarketplaceError::rpc(format!("Failed to send transaction: {}", e)),
+                )
+            })
         }
         sol_rpc_types::MultiRpcResult::Inconsistent(results) => {
-            ic_cdk::println!(
+            log!(
+                Priority::Info,
                 "RPC providers returned inconsistent results, using majority consensus"
             );
 
             let mut successes = Vec::new();
-            let mut failures = Vec::new();
+            let mut errors = Vec::new();
+            let mut reached = false;
 
-            for (source, result) in results.iter() {
+            for (source, result) in results.into_iter() {
                 match result {
                     Ok(sig) => {
-                        ic_cdk::println!("Provider {:?} succeeded with signature: {}", source, sig);
-                        successes.push(sig.clone());
+                        log!(Priority::TraceHttp, "Provider {:?} succeeded with signature: {}", source, sig);
+                        successes.push(sig);
                     }
                     Err(e) => {
-                        ic_cdk::println!("Provider {:?} failed with error: {:?}", source, e);
-                        failures.push(e);
+                        log!(Priority::TraceHttp, "Provider {:?} failed with error: {:?}", source, e);
+                        reached |= matches!(e, RpcError::HttpOutcallError(_));
+                        errors.push(ProviderError {
+                            provider: format!("{:?}", source),
+                            message: e.to_string(),
+                        });
                     }
                 }
             }
 
             if successes.len() >= 2 {
-                ic_cdk::println!(
-                    "Majority consensus: {} providers succeeded, using transaction",
+                log!(
+                    Priority::Info,
+                    "Majority consensus: {} providers succeeded",
                     successes.len()
                 );
-                successes[0].clone()
+                Ok(successes[0])
+            } else if successes.is_empty() {
+                Err(failure(reached, MarketplaceError::SolanaRpc { errors }))
             } else {
-                return Err(format!(
-                    "Transaction failed consensus: {} successes, {} failures. Errors: {:?}",
-                    successes.len(),
-                    failures.len(),
-                    failures
-                ));
+                // One provider forwarded the transaction
+                Err(failure(
+                    true,
+                    MarketplaceError::ConsensusFailed {
+                        successes: successes.len() as u32,
+                        errors,
+                    },
+                ))
             }
         }
-    };
-
-    ic_cdk::println!("Items added transaction sent: {}", signature);
-
-    Ok(signature.to_string())
+    }
 }
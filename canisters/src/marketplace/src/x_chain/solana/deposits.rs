@@ -0,0 +1,91 @@
+//! Deposits that fund a collection's Solana transactions.
+//!
+//! The canister payer signs and pays for every transaction of a collection, but
+//! only up to the SOL the collection's creator has sent to it. A deposit is a
+//! transfer to the payer carrying the collection id as an SPL Memo, so it cannot
+//! be claimed for another collection. Its signature is recorded once credited.
+
+use super::sale_verification::{account_keys, decode_transaction, fetch_transaction};
+use super::solana_wallet::SolanaWallet;
+use crate::logs::{log, Priority};
+use crate::state;
+use crate::types::{MarketplaceError, MarketplaceResult};
+use ic_cdk::api::canister_self;
+use solana_pubkey::{pubkey, Pubkey};
+use solana_signature::Signature;
+use std::str::FromStr;
+
+pub const MEMO_PROGRAM_ID: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+/// Verifies the deposit `tx_signature` and credits it to the collection. Returns
+/// the collection's total deposits.
+pub async fn record_deposit(collection_id: &str, tx_signature: &str) -> MarketplaceResult<u64> {
+    if state::is_deposit_recorded(tx_signature) {
+        return Err(MarketplaceError::invalid_state("Deposit has already been recorded"));
+    }
+
+    let payer = SolanaWallet::new(canister_self()).await.solana_account().to_string();
+    let lamports = verify_deposit(&payer, collection_id, tx_signature).await?;
+
+    // Another call may have recorded it while the transaction was fetched
+    if state::is_deposit_recorded(tx_signature) {
+        return Err(MarketplaceError::invalid_state("Deposit has already been recorded"));
+    }
+    let total = state::record_lamports_deposit(collection_id, tx_signature, lamports);
+    log!(
+        Priority::Info,
+        "Recorded deposit {} of {} lamports for collection {}",
+        tx_signature,
+        lamports,
+        collection_id
+    );
+    Ok(total)
+}
+
+/// Fetches `tx_signature` and returns the lamports it credited to `payer`. The
+/// payer must not have signed it, so only transfers from other wallets count, and
+/// it must carry `collection_id` as a memo.
+pub async fn verify_deposit(payer: &str, collection_id: &str, tx_signature: &str) -> MarketplaceResult<u64> {
+    let rejected = |reason: String| MarketplaceError::invalid_input(format!("Deposit rejected: {}", reason));
+
+    let signature = Signature::from_str(tx_signature).map_err(|e| rejected(e.to_string()))?;
+    let confirmed = fetch_transaction(signature)
+        .await
+        .map_err(|reason| rejected(format!("{:?}", reason)))?;
+
+    let meta = confirmed
+        .transaction
+        .meta
+        .ok_or_else(|| rejected("transaction has no status meta".to_string()))?;
+    if let Err(e) = meta.status {
+        return Err(rejected(format!("transaction failed: {:?}", e)));
+    }
+
+    let transaction = decode_transaction(confirmed.transaction.transaction)
+        .map_err(|reason| rejected(format!("{:?}", reason)))?;
+    let num_signers = transaction.message.header().num_required_signatures as usize;
+
+    let static_keys = transaction.message.static_account_keys();
+    let has_memo = transaction.message.instructions().iter().any(|instruction| {
+        static_keys.get(instruction.program_id_index as usize) == Some(&MEMO_PROGRAM_ID)
+            && instruction.data == collection_id.as_bytes()
+    });
+    if !has_memo {
+        return Err(rejected(format!("transaction has no memo for collection {}", collection_id)));
+    }
+
+    let index = account_keys(&transaction, meta.loaded_addresses.as_ref())
+        .iter()
+        .position(|key| key == payer)
+        .ok_or_else(|| rejected(format!("transaction does not touch the payer {}", payer)))?;
+    if index < num_signers {
+        return Err(rejected("the payer signed the transaction".to_string()));
+    }
+
+    let pre = meta.pre_balances.get(index).copied().unwrap_or_default();
+    let post = meta.post_balances.get(index).copied().unwrap_or_default();
+    match post.saturating_sub(pre) {
+        0 => Err(rejected(format!("transaction did not credit the payer {}", payer))),
+        lamports => Ok(lamports),
+    }
+}
@@ -0,0 +1,431 @@
+//! Builders for the Metaplex instructions the canister signs for a collection.
+//!
+//! Account order and argument layouts follow Candy Machine Core v3, Candy Guard
+//! and Token Metadata. Optional accounts that are not used are passed as the owning
+//! program's id, which is how both programs encode `None`.
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_instruction::{AccountMeta, Instruction};
+use solana_pubkey::Pubkey;
+use spl_associated_token_account_interface::address::get_associated_token_address_with_program_id;
+use super::programs::{
+    anchor_discriminator, ASSOCIATED_TOKEN_PROGRAM_ID, CANDY_GUARD_PROGRAM_ID, CANDY_MACHINE_PROGRAM_ID,
+    SYSTEM_PROGRAM_ID, SYSVAR_INSTRUCTIONS_ID, TOKEN_METADATA_PROGRAM_ID, TOKEN_PROGRAM_ID,
+};
+
+pub const MAX_NAME_LENGTH: usize = 32;
+pub const MAX_URI_LENGTH: usize = 200;
+pub const MAX_SYMBOL_LENGTH: usize = 10;
+
+/// Size of a candy machine account before its config lines: the account fields
+/// with every string, the creators and both settings padded to their maximum size.
+pub const CANDY_MACHINE_HEADER_SIZE: u64 = 850;
+const TOKEN_STANDARD_NON_FUNGIBLE: u8 = 0;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(super) struct Creator {
+    pub address: [u8; 32],
+    pub verified: bool,
+    pub percentage_share: u8,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(super) struct ConfigLineSettings {
+    pub prefix_name: String,
+    pub name_length: u32,
+    pub prefix_uri: String,
+    pub uri_length: u32,
+    pub is_sequential: bool,
+}
+
+// Never set by the canister, only decoded from candy machines configured elsewhere
+#[allow(dead_code)]
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(super) struct HiddenSettings {
+    pub name: String,
+    pub uri: String,
+    pub hash: [u8; 32],
+}
+
+/// `CandyMachineData` as stored on chain. The canister gives every item its own
+/// config line of up to [`MAX_NAME_LENGTH`] and [`MAX_URI_LENGTH`] bytes, minted
+/// in random order.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct CandyMachineData {
+    pub(super) items_available: u64,
+    pub(super) symbol: String,
+    pub(super) seller_fee_basis_points: u16,
+    pub(super) max_supply: u64,
+    pub(super) is_mutable: bool,
+    pub(super) creators: Vec<Creator>,
+    pub(super) config_line_settings: Option<ConfigLineSettings>,
+    pub(super) hidden_settings: Option<HiddenSettings>,
+}
+
+impl CandyMachineData {
+    /// Royalties go to `creator`, the canister payer, which also holds the update authority.
+    pub fn new(items_available: u64, symbol: String, seller_fee_basis_points: u16, creator: &Pubkey) -> Self {
+        Self {
+            items_available,
+            symbol,
+            seller_fee_basis_points,
+            max_supply: 0,
+            is_mutable: true,
+            creators: vec![Creator {
+                address: creator.to_bytes(),
+                verified: true,
+                percentage_share: 100,
+            }],
+            config_line_settings: Some(ConfigLineSettings {
+                prefix_name: String::new(),
+                name_length: MAX_NAME_LENGTH as u32,
+                prefix_uri: String::new(),
+                uri_length: MAX_URI_LENGTH as u32,
+                is_sequential: false,
+            }),
+            hidden_settings: None,
+        }
+    }
+
+    /// Space the candy machine account needs: the header, the config lines, the
+    /// mint bitmask and the shuffled mint indices.
+    pub fn account_space(&self) -> u64 {
+        let line_size = (MAX_NAME_LENGTH + MAX_URI_LENGTH) as u64;
+        CANDY_MACHINE_HEADER_SIZE
+            + 4
+            + self.items_available * line_size
+            + self.items_available / 8
+            + 1
+            + 4
+            + self.items_available * 4
+    }
+}
+
+// Only ever serialized as `None`
+#[allow(dead_code)]
+#[derive(BorshSerialize)]
+struct MetadataCollection {
+    verified: bool,
+    key: [u8; 32],
+}
+
+// Only ever serialized as `None`
+#[allow(dead_code)]
+#[derive(BorshSerialize)]
+struct Uses {
+    use_method: u8,
+    remaining: u64,
+    total: u64,
+}
+
+#[derive(BorshSerialize)]
+enum CollectionDetails {
+    V1 { size: u64 },
+}
+
+#[derive(BorshSerialize)]
+enum PrintSupply {
+    Zero,
+}
+
+/// Token Metadata `AssetData` followed by the rest of `CreateArgs::V1`.
+#[derive(BorshSerialize)]
+struct CreateArgsV1 {
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+    creators: Option<Vec<Creator>>,
+    primary_sale_happened: bool,
+    is_mutable: bool,
+    token_standard: u8,
+    collection: Option<MetadataCollection>,
+    uses: Option<Uses>,
+    collection_details: Option<CollectionDetails>,
+    rule_set: Option<[u8; 32]>,
+    decimals: Option<u8>,
+    print_supply: Option<PrintSupply>,
+}
+
+#[derive(BorshSerialize)]
+struct ConfigLine<'a> {
+    name: &'a str,
+    uri: &'a str,
+}
+
+fn find_pda(seeds: &[&[u8]], program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(seeds, program_id).0
+}
+
+pub fn candy_machine_authority_pda(candy_machine: &Pubkey) -> Pubkey {
+    find_pda(&[b"candy_machine", candy_machine.as_ref()], &CANDY_MACHINE_PROGRAM_ID)
+}
+
+/// Candy guard created with `base` as its base signer.
+pub fn candy_guard_pda(base: &Pubkey) -> Pubkey {
+    find_pda(&[b"candy_guard", base.as_ref()], &CANDY_GUARD_PROGRAM_ID)
+}
+
+pub fn metadata_pda(mint: &Pubkey) -> Pubkey {
+    find_pda(
+        &[b"metadata", TOKEN_METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+}
+
+pub fn master_edition_pda(mint: &Pubkey) -> Pubkey {
+    find_pda(
+        &[b"metadata", TOKEN_METADATA_PROGRAM_ID.as_ref(), mint.as_ref(), b"edition"],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+}
+
+/// Record that lets the candy machine's authority PDA verify items into the collection.
+pub fn collection_delegate_record_pda(mint: &Pubkey, update_authority: &Pubkey, delegate: &Pubkey) -> Pubkey {
+    find_pda(
+        &[
+            b"metadata",
+            TOKEN_METADATA_PROGRAM_ID.as_ref(),
+            mint.as_ref(),
+            b"collection_delegate",
+            update_authority.as_ref(),
+            delegate.as_ref(),
+        ],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+}
+
+fn anchor_data(name: &str, args: &impl BorshSerialize) -> Vec<u8> {
+    let mut data = anchor_discriminator(name).to_vec();
+    args.serialize(&mut data).expect("failed to serialize instruction args");
+    data
+}
+
+/// Token Metadata `Create` (V1) for a sized collection NFT, with the canister
+/// payer as mint and update authority.
+pub fn create_collection_nft(
+    mint: &Pubkey,
+    payer: &Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+) -> Instruction {
+    let args = CreateArgsV1 {
+        name,
+        symbol,
+        uri,
+        seller_fee_basis_points,
+        creators: Some(vec![Creator {
+            address: payer.to_bytes(),
+            verified: true,
+            percentage_share: 100,
+        }]),
+        primary_sale_happened: false,
+        is_mutable: true,
+        token_standard: TOKEN_STANDARD_NON_FUNGIBLE,
+        collection: None,
+        uses: None,
+        collection_details: Some(CollectionDetails::V1 { size: 0 }),
+        rule_set: None,
+        decimals: Some(0),
+        print_supply: Some(PrintSupply::Zero),
+    };
+    let mut data = vec![42, 0]; // Create, CreateArgs::V1
+    args.serialize(&mut data).expect("failed to serialize instruction args");
+
+    Instruction {
+        program_id: TOKEN_METADATA_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(metadata_pda(mint), false),
+            AccountMeta::new(master_edition_pda(mint), false),
+            AccountMeta::new(*mint, true),
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(SYSVAR_INSTRUCTIONS_ID, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+        ],
+        data,
+    }
+}
+
+/// Token Metadata `Mint` (V1) of the single collection NFT to the canister payer.
+pub fn mint_collection_nft(mint: &Pubkey, payer: &Pubkey) -> Instruction {
+    let mut data = vec![43, 0]; // Mint, MintArgs::V1
+    data.extend_from_slice(&1u64.to_le_bytes());
+    data.push(0); // authorization_data: None
+
+    Instruction {
+        program_id: TOKEN_METADATA_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(get_associated_token_address_with_program_id(payer, mint, &TOKEN_PROGRAM_ID), false),
+            AccountMeta::new_readonly(*payer, false),
+            AccountMeta::new_readonly(metadata_pda(mint), false),
+            AccountMeta::new(master_edition_pda(mint), false),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+            AccountMeta::new(*mint, false),
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(SYSVAR_INSTRUCTIONS_ID, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+        ],
+        data,
+    }
+}
+
+/// Candy Machine `initialize_v2`. The canister payer is the candy machine authority
+/// and the collection's update authority.
+pub fn initialize_v2(
+    candy_machine: &Pubkey,
+    payer: &Pubkey,
+    collection_mint: &Pubkey,
+    data: &CandyMachineData,
+) -> Instruction {
+    let authority_pda = candy_machine_authority_pda(candy_machine);
+    Instruction {
+        program_id: CANDY_MACHINE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*candy_machine, false),
+            AccountMeta::new(authority_pda, false),
+            AccountMeta::new_readonly(*payer, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(CANDY_MACHINE_PROGRAM_ID, false),
+            AccountMeta::new(metadata_pda(collection_mint), false),
+            AccountMeta::new_readonly(*collection_mint, false),
+            AccountMeta::new_readonly(master_edition_pda(collection_mint), false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(collection_delegate_record_pda(collection_mint, payer, &authority_pda), false),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(SYSVAR_INSTRUCTIONS_ID, false),
+            AccountMeta::new_readonly(CANDY_MACHINE_PROGRAM_ID, false),
+            AccountMeta::new_readonly(CANDY_MACHINE_PROGRAM_ID, false),
+        ],
+        data: anchor_data("initialize_v2", &(data, TOKEN_STANDARD_NON_FUNGIBLE)),
+    }
+}
+
+/// Candy Machine `add_config_lines`, writing `lines` from position `index`.
+pub fn add_config_lines(
+    candy_machine: &Pubkey,
+    authority: &Pubkey,
+    index: u32,
+    lines: &[crate::types::ConfigLine],
+) -> Instruction {
+    let lines: Vec<ConfigLine> = lines
+        .iter()
+        .map(|line| ConfigLine {
+            name: &line.name,
+            uri: &line.uri,
+        })
+        .collect();
+    Instruction {
+        program_id: CANDY_MACHINE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*candy_machine, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: anchor_data("add_config_lines", &(index, lines)),
+    }
+}
+
+/// Candy Machine `set_collection_v2`. The canister payer is the update authority
+/// of both the current and the new collection.
+pub fn set_collection_v2(
+    candy_machine: &Pubkey,
+    payer: &Pubkey,
+    collection_mint: &Pubkey,
+    new_collection_mint: &Pubkey,
+) -> Instruction {
+    let authority_pda = candy_machine_authority_pda(candy_machine);
+    Instruction {
+        program_id: CANDY_MACHINE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*candy_machine, false),
+            AccountMeta::new_readonly(*payer, true),
+            AccountMeta::new(authority_pda, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*payer, false),
+            AccountMeta::new_readonly(*collection_mint, false),
+            AccountMeta::new(metadata_pda(collection_mint), false),
+            AccountMeta::new(collection_delegate_record_pda(collection_mint, payer, &authority_pda), false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*new_collection_mint, false),
+            AccountMeta::new(metadata_pda(new_collection_mint), false),
+            AccountMeta::new_readonly(master_edition_pda(new_collection_mint), false),
+            AccountMeta::new(collection_delegate_record_pda(new_collection_mint, payer, &authority_pda), false),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(SYSVAR_INSTRUCTIONS_ID, false),
+            AccountMeta::new_readonly(CANDY_MACHINE_PROGRAM_ID, false),
+            AccountMeta::new_readonly(CANDY_MACHINE_PROGRAM_ID, false),
+        ],
+        data: anchor_discriminator("set_collection_v2").to_vec(),
+    }
+}
+
+/// Candy Machine `set_authority`, handing the candy machine to `new_authority`.
+pub fn set_authority(candy_machine: &Pubkey, authority: &Pubkey, new_authority: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: CANDY_MACHINE_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*candy_machine, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: anchor_data("set_authority", &new_authority.to_bytes()),
+    }
+}
+
+/// Candy Guard `initialize`, creating the guard at [`candy_guard_pda`] of `base`
+/// with `data` compiled by `candy_guard::compile`.
+pub fn initialize_candy_guard(base: &Pubkey, authority: &Pubkey, payer: &Pubkey, data: Vec<u8>) -> Instruction {
+    Instruction {
+        program_id: CANDY_GUARD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(candy_guard_pda(base), false),
+            AccountMeta::new_readonly(*base, true),
+            AccountMeta::new_readonly(*authority, false),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+        data: anchor_data("initialize", &data),
+    }
+}
+
+/// Candy Guard `update`, replacing every guard with `data`. The account is resized
+/// at the payer's expense.
+pub fn update_candy_guard(candy_guard: &Pubkey, authority: &Pubkey, payer: &Pubkey, data: Vec<u8>) -> Instruction {
+    Instruction {
+        program_id: CANDY_GUARD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*candy_guard, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+        data: anchor_data("update", &data),
+    }
+}
+
+/// Candy Guard `wrap`, making the candy guard the candy machine's mint authority so
+/// every mint goes through its guards. `authority` must hold both.
+pub fn wrap_candy_guard(candy_guard: &Pubkey, candy_machine: &Pubkey, authority: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: CANDY_GUARD_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*candy_guard, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*candy_machine, false),
+            AccountMeta::new_readonly(CANDY_MACHINE_PROGRAM_ID, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+        data: anchor_discriminator("wrap").to_vec(),
+    }
+}
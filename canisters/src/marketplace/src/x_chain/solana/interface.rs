@@ -1,4 +1,9 @@
-use super::{client, solana_wallet::SolanaWallet, spl::transfer_instruction_with_program_id, validate_caller_not_anonymous};
+use crate::guards;
+use crate::types::{MarketplaceResult, PauseTarget, SentTransaction};
+use super::{
+    client, priority_fees, solana_wallet::SolanaWallet, spl::transfer_instruction_with_program_id,
+    validate_caller_not_anonymous,
+};
 use candid::{Nat, Principal};
 use ic_cdk::update;
 use num::ToPrimitive;
@@ -14,6 +19,13 @@ use spl_associated_token_account_interface::{
     instruction::create_associated_token_account_idempotent,
 };
 use std::str::FromStr;
+use crate::logs::{log, Priority};
+
+// Compute units requested by wallet transactions, with headroom over their usual cost
+const SOL_TRANSFER_COMPUTE_UNITS: u32 = 1_000;
+const NONCE_TRANSFER_COMPUTE_UNITS: u32 = 2_000;
+const SPL_TRANSFER_COMPUTE_UNITS: u32 = 20_000;
+const CREATE_ATA_COMPUTE_UNITS: u32 = 40_000;
 
 #[update]
 pub async fn solana_account(owner: Option<Principal>) -> String {
@@ -90,10 +102,11 @@ pub async fn get_spl_token_balance(account: Option<String>, mint_account: String
 }
 
 #[update]
-pub async fn create_nonce_account(owner: Option<Principal>) -> String {
+pub async fn create_nonce_account(owner: Option<Principal>) -> MarketplaceResult<String> {
     let client = client();
 
-    let owner = owner.unwrap_or_else(validate_caller_not_anonymous);
+    let owner = guards::authorize_wallet_owner(owner)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)?;
     let wallet = SolanaWallet::new(owner).await;
 
     let payer = wallet.solana_account();
@@ -111,11 +124,12 @@ pub async fn create_nonce_account(owner: Option<Principal>) -> String {
             )
         })
     {
-        ic_cdk::println!(
+        log!(
+            Priority::Debug,
             "[create_nonce_account]: Account {} already exists. Skipping creation of nonce account",
             nonce_account.as_ref()
         );
-        return nonce_account.as_ref().to_string();
+        return Ok(nonce_account.as_ref().to_string());
     }
 
     let instructions = instruction::create_nonce_account(
@@ -148,17 +162,18 @@ pub async fn create_nonce_account(owner: Option<Principal>) -> String {
         .expect_consistent()
         .expect("Call to `sendTransaction` failed");
 
-    nonce_account.as_ref().to_string()
+    Ok(nonce_account.as_ref().to_string())
 }
 
 #[update]
 pub async fn create_associated_token_account(
     owner: Option<Principal>,
     mint_account: String,
-) -> String {
+) -> MarketplaceResult<SentTransaction> {
     let client = client();
 
-    let owner = owner.unwrap_or_else(validate_caller_not_anonymous);
+    let owner = guards::authorize_wallet_owner(owner)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)?;
     let wallet = SolanaWallet::new(owner).await;
 
     let payer = wallet.solana_account();
@@ -173,8 +188,11 @@ pub async fn create_associated_token_account(
         &account_owner,
     );
 
+    let (instructions, priority_fee) =
+        priority_fees::with_compute_budget(&[instruction], CREATE_ATA_COMPUTE_UNITS).await;
+
     let message = Message::new_with_blockhash(
-        &[instruction],
+        &instructions,
         Some(payer.as_ref()),
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
@@ -185,36 +203,47 @@ pub async fn create_associated_token_account(
         signatures,
     };
 
-    client
+    let signature = client
         .send_transaction(transaction)
         .send()
         .await
         .expect_consistent()
         .expect("Call to `sendTransaction` failed")
         .to_string();
-
-    get_associated_token_address_with_program_id(payer.as_ref(), &mint, &account_owner).to_string()
+    log!(Priority::Info, "Sent transaction {} with {:?}", signature, priority_fee);
+
+    Ok(SentTransaction {
+        signature,
+        priority_fee,
+        created_account: Some(
+            get_associated_token_address_with_program_id(payer.as_ref(), &mint, &account_owner).to_string(),
+        ),
+    })
 }
 
 #[update]
-pub async fn send_sol(owner: Option<Principal>, to: String, amount: Nat) -> String {
+pub async fn send_sol(owner: Option<Principal>, to: String, amount: Nat) -> MarketplaceResult<SentTransaction> {
     let client = client();
 
-    let owner = owner.unwrap_or_else(validate_caller_not_anonymous);
+    let owner = guards::authorize_wallet_owner(owner)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)?;
     let wallet = SolanaWallet::new(owner).await;
 
     let recipient = Pubkey::from_str(&to).unwrap();
     let payer = wallet.solana_account();
     let amount = amount.0.to_u64().unwrap();
 
-    ic_cdk::println!(
+    log!(
+        Priority::Debug,
         "Instruction to transfer {amount} lamports from {} to {recipient}",
         payer.as_ref()
     );
     let instruction = instruction::transfer(payer.as_ref(), &recipient, amount);
+    let (instructions, priority_fee) =
+        priority_fees::with_compute_budget(&[instruction], SOL_TRANSFER_COMPUTE_UNITS).await;
 
     let message = Message::new_with_blockhash(
-        &[instruction],
+        &instructions,
         Some(payer.as_ref()),
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
@@ -224,13 +253,19 @@ pub async fn send_sol(owner: Option<Principal>, to: String, amount: Nat) -> Stri
         signatures,
     };
 
-    client
+    let signature = client
         .send_transaction(transaction)
         .send()
         .await
         .expect_consistent()
         .expect("Call to `sendTransaction` failed")
-        .to_string()
+        .to_string();
+    log!(Priority::Info, "Sent transaction {} with {:?}", signature, priority_fee);
+    Ok(SentTransaction {
+        signature,
+        priority_fee,
+        created_account: None,
+    })
 }
 
 #[update]
@@ -238,10 +273,11 @@ pub async fn send_sol_with_durable_nonce(
     owner: Option<Principal>,
     to: String,
     amount: Nat,
-) -> String {
+) -> MarketplaceResult<SentTransaction> {
     let client = client();
 
-    let owner = owner.unwrap_or_else(validate_caller_not_anonymous);
+    let owner = guards::authorize_wallet_owner(owner)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)?;
     let wallet = SolanaWallet::new(owner).await;
 
     let recipient = Pubkey::from_str(&to).unwrap();
@@ -249,27 +285,39 @@ pub async fn send_sol_with_durable_nonce(
     let amount = amount.0.to_u64().unwrap();
     let nonce_account = wallet.derived_nonce_account();
 
-    let instructions = &[
+    let (mut instructions, priority_fee) = priority_fees::with_compute_budget(
+        &[instruction::transfer(payer.as_ref(), &recipient, amount)],
+        NONCE_TRANSFER_COMPUTE_UNITS,
+    )
+    .await;
+    // Advancing the nonce must be the first instruction of a durable transaction
+    instructions.insert(
+        0,
         instruction::advance_nonce_account(nonce_account.as_ref(), payer.as_ref()),
-        instruction::transfer(payer.as_ref(), &recipient, amount),
-    ];
+    );
 
     let blockhash = Hash::from(get_nonce(Some(nonce_account.as_ref().into())).await);
 
-    let message = Message::new_with_blockhash(instructions, Some(payer.as_ref()), &blockhash);
+    let message = Message::new_with_blockhash(&instructions, Some(payer.as_ref()), &blockhash);
     let signatures = vec![payer.sign_message(&message).await];
     let transaction = Transaction {
         message,
         signatures,
     };
 
-    client
+    let signature = client
         .send_transaction(transaction)
         .send()
         .await
         .expect_consistent()
         .expect("Call to `sendTransaction` failed")
-        .to_string()
+        .to_string();
+    log!(Priority::Info, "Sent transaction {} with {:?}", signature, priority_fee);
+    Ok(SentTransaction {
+        signature,
+        priority_fee,
+        created_account: None,
+    })
 }
 
 #[update]
@@ -278,10 +326,11 @@ pub async fn send_spl_token(
     mint_account: String,
     to: String,
     amount: Nat,
-) -> String {
+) -> MarketplaceResult<SentTransaction> {
     let client = client();
 
-    let owner = owner.unwrap_or_else(validate_caller_not_anonymous);
+    let owner = guards::authorize_wallet_owner(owner)?;
+    guards::ensure_not_paused(PauseTarget::SolanaSigning)?;
     let wallet = SolanaWallet::new(owner).await;
 
     let payer = wallet.solana_account();
@@ -296,9 +345,11 @@ pub async fn send_spl_token(
 
     let instruction =
         transfer_instruction_with_program_id(&from, &to, payer.as_ref(), amount, &token_program);
+    let (instructions, priority_fee) =
+        priority_fees::with_compute_budget(&[instruction], SPL_TRANSFER_COMPUTE_UNITS).await;
 
     let message = Message::new_with_blockhash(
-        &[instruction],
+        &instructions,
         Some(payer.as_ref()),
         &client.estimate_recent_blockhash().send().await.unwrap(),
     );
@@ -308,13 +359,19 @@ pub async fn send_spl_token(
         signatures,
     };
 
-    client
+    let signature = client
         .send_transaction(transaction)
         .send()
         .await
         .expect_consistent()
         .expect("Call to `sendTransaction` failed")
-        .to_string()
+        .to_string();
+    log!(Priority::Info, "Sent transaction {} with {:?}", signature, priority_fee);
+    Ok(SentTransaction {
+        signature,
+        priority_fee,
+        created_account: None,
+    })
 }
 
 async fn get_account_owner(account: &Pubkey) -> Pubkey {
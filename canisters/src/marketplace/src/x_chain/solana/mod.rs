@@ -2,7 +2,16 @@ mod ed25519;
 pub mod solana_wallet;
 pub mod spl;
 pub mod interface;
+pub mod accounts;
+pub mod candy_guard;
+pub mod instructions;
+pub mod programs;
 pub mod candy_machine;
+pub mod priority_fees;
+pub mod tracker;
+pub mod sale_verification;
+pub mod deposits;
+pub mod runtime;
 
 use crate::state::config::{
     self, Ed25519KeyName, SolanaNetwork,
@@ -10,12 +19,13 @@ use crate::state::config::{
 use candid::Principal;
 use ic_cdk::api::msg_caller;
 use ic_ed25519::PublicKey;
+use runtime::MetricsRuntime;
 use sol_rpc_client::{ed25519::Ed25519KeyId, IcRuntime, SolRpcClient};
 use sol_rpc_types::{
     ConsensusStrategy, RpcEndpoint, RpcSource, RpcSources, SolanaCluster,
 };
 
-pub fn client() -> SolRpcClient<IcRuntime> {
+pub fn client() -> SolRpcClient<MetricsRuntime> {
     let rpc_sources = config::get_solana_network().into();
     let consensus_strategy = match rpc_sources {
         RpcSources::Custom(_) => ConsensusStrategy::Equality,
@@ -27,6 +37,7 @@ pub fn client() -> SolRpcClient<IcRuntime> {
     config::get_sol_rpc_canister_id()
         .map(|canister_id| SolRpcClient::builder(IcRuntime, canister_id))
         .unwrap_or(SolRpcClient::builder_for_ic())
+        .with_runtime(MetricsRuntime)
         .with_rpc_sources(rpc_sources)
         .with_consensus_strategy(consensus_strategy)
         .with_default_commitment_level(config::get_solana_commitment_level())
@@ -0,0 +1,132 @@
+//! Compute budget of the transactions the canister signs.
+//!
+//! The compute unit price is a percentile of the fees recently paid to write the
+//! transaction's writable accounts, capped by the admin's `PriorityFeePolicy`. When
+//! the fees cannot be fetched the policy's fallback price is used.
+
+use super::client;
+use super::programs::COMPUTE_BUDGET_PROGRAM_ID;
+use crate::logs::{log, Priority};
+use crate::state::config::{self, PriorityFeePolicy};
+use crate::types::PriorityFee;
+use sol_rpc_types::MultiRpcResult;
+use solana_instruction::Instruction;
+use solana_pubkey::Pubkey;
+use std::collections::BTreeSet;
+
+#[cfg(test)]
+mod tests;
+
+/// Highest compute unit price the canister signs for, in micro-lamports.
+pub const MAX_COMPUTE_UNIT_PRICE: u64 = 1_000_000;
+
+/// Highest compute unit limit the canister signs for. Leaves headroom over the
+/// heaviest deployment transaction.
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 600_000;
+
+// Limit the runtime applies when a transaction does not set one
+const DEFAULT_UNITS_PER_INSTRUCTION: u64 = 200_000;
+const MAX_UNITS_PER_TRANSACTION: u64 = 1_400_000;
+
+/// Most accounts `getRecentPrioritizationFees` accepts.
+const MAX_FEE_ACCOUNTS: usize = 128;
+
+pub fn set_compute_unit_limit(units: u32) -> Instruction {
+    let mut data = vec![2];
+    data.extend_from_slice(&units.to_le_bytes());
+    Instruction {
+        program_id: COMPUTE_BUDGET_PROGRAM_ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+pub fn set_compute_unit_price(micro_lamports: u64) -> Instruction {
+    let mut data = vec![3];
+    data.extend_from_slice(&micro_lamports.to_le_bytes());
+    Instruction {
+        program_id: COMPUTE_BUDGET_PROGRAM_ID,
+        accounts: vec![],
+        data,
+    }
+}
+
+/// Prepends the compute budget instructions to `instructions`, limiting the
+/// transaction to `compute_unit_limit` units at the current priority fee.
+pub async fn with_compute_budget(
+    instructions: &[Instruction],
+    compute_unit_limit: u32,
+) -> (Vec<Instruction>, PriorityFee) {
+    let writable: BTreeSet<Pubkey> = instructions
+        .iter()
+        .flat_map(|instruction| &instruction.accounts)
+        .filter(|account| account.is_writable)
+        .map(|account| account.pubkey)
+        .collect();
+
+    let fee = PriorityFee {
+        compute_unit_limit,
+        compute_unit_price: compute_unit_price(&writable).await,
+    };
+
+    let mut budgeted = vec![
+        set_compute_unit_limit(fee.compute_unit_limit),
+        set_compute_unit_price(fee.compute_unit_price),
+    ];
+    budgeted.extend_from_slice(instructions);
+    (budgeted, fee)
+}
+
+/// Priority fee in lamports of a transaction with `instructions` non-compute-budget
+/// instructions, at `compute_unit_price` and an explicit or default unit limit.
+pub fn priority_fee_lamports(compute_unit_limit: Option<u32>, compute_unit_price: u64, instructions: usize) -> u64 {
+    let units = compute_unit_limit.map_or_else(
+        || (instructions as u64).saturating_mul(DEFAULT_UNITS_PER_INSTRUCTION).min(MAX_UNITS_PER_TRANSACTION),
+        u64::from,
+    );
+    units.saturating_mul(compute_unit_price).div_ceil(1_000_000)
+}
+
+async fn compute_unit_price(writable: &BTreeSet<Pubkey>) -> u64 {
+    let policy = config::get_priority_fee_policy();
+
+    let request = match client().get_recent_prioritization_fees(writable.iter().take(MAX_FEE_ACCOUNTS)) {
+        Ok(request) => request,
+        Err(e) => {
+            log!(Priority::Info, "Failed to build prioritization fee request: {:?}", e);
+            return fallback_compute_unit_price(&policy);
+        }
+    };
+    let mut fees: Vec<u64> = match request.send().await {
+        MultiRpcResult::Consistent(Ok(fees)) => fees.into_iter().map(|fee| fee.prioritization_fee).collect(),
+        MultiRpcResult::Consistent(Err(e)) => {
+            log!(Priority::Info, "Failed to get recent prioritization fees, using the fallback price: {}", e);
+            return fallback_compute_unit_price(&policy);
+        }
+        MultiRpcResult::Inconsistent(_) => {
+            log!(
+                Priority::Info,
+                "RPC providers disagree on recent prioritization fees, using the fallback price"
+            );
+            return fallback_compute_unit_price(&policy);
+        }
+    };
+
+    percentile(&mut fees, policy.percentile).min(policy.max_compute_unit_price)
+}
+
+fn fallback_compute_unit_price(policy: &PriorityFeePolicy) -> u64 {
+    policy
+        .fallback_compute_unit_price
+        .map_or(policy.max_compute_unit_price, |price| price.min(policy.max_compute_unit_price))
+}
+
+/// Nearest-rank percentile, 0 when there are no fees.
+fn percentile(fees: &mut [u64], percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let rank = (fees.len() * percentile.min(100) as usize).div_ceil(100);
+    fees[rank.saturating_sub(1)]
+}
@@ -0,0 +1,84 @@
+use super::*;
+
+mod priority_fee_lamports {
+    use super::*;
+
+    #[test]
+    fn should_charge_the_explicit_limit() {
+        assert_eq!(priority_fee_lamports(Some(400_000), 1_000_000, 3), 400_000);
+        assert_eq!(priority_fee_lamports(Some(200_000), 5, 1), 1);
+        assert_eq!(priority_fee_lamports(Some(200_000), 0, 1), 0);
+    }
+
+    #[test]
+    fn should_charge_the_default_limit_without_one() {
+        assert_eq!(priority_fee_lamports(None, 1_000_000, 2), 400_000);
+        assert_eq!(priority_fee_lamports(None, 1_000_000, 10), 1_400_000);
+    }
+}
+
+mod percentile {
+    use super::*;
+
+    #[test]
+    fn should_return_zero_without_fees() {
+        assert_eq!(percentile(&mut [], 75), 0);
+    }
+
+    #[test]
+    fn should_return_the_only_fee() {
+        for p in [0, 50, 100] {
+            assert_eq!(percentile(&mut [42], p), 42);
+        }
+    }
+
+    #[test]
+    fn should_use_nearest_rank() {
+        let fees: Vec<u64> = (1..=10).collect();
+
+        assert_eq!(percentile(&mut fees.clone(), 0), 1);
+        assert_eq!(percentile(&mut fees.clone(), 50), 5);
+        assert_eq!(percentile(&mut fees.clone(), 51), 6);
+        assert_eq!(percentile(&mut fees.clone(), 75), 8);
+        assert_eq!(percentile(&mut fees.clone(), 100), 10);
+    }
+
+    #[test]
+    fn should_sort_fees() {
+        assert_eq!(percentile(&mut [900, 0, 300, 100, 0], 50), 100);
+        assert_eq!(percentile(&mut [900, 0, 300, 100, 0], 100), 900);
+    }
+
+    #[test]
+    fn should_clamp_percentile_to_100() {
+        assert_eq!(percentile(&mut [3, 1, 2], 255), 3);
+    }
+}
+
+mod fallback_compute_unit_price {
+    use super::*;
+
+    fn policy(fallback_compute_unit_price: Option<u64>) -> PriorityFeePolicy {
+        PriorityFeePolicy {
+            percentile: 75,
+            max_compute_unit_price: 100_000,
+            fallback_compute_unit_price,
+        }
+    }
+
+    #[test]
+    fn should_use_the_configured_price() {
+        assert_eq!(fallback_compute_unit_price(&policy(Some(5_000))), 5_000);
+        assert_eq!(fallback_compute_unit_price(&policy(Some(0))), 0);
+    }
+
+    #[test]
+    fn should_default_to_the_cap() {
+        assert_eq!(fallback_compute_unit_price(&policy(None)), 100_000);
+    }
+
+    #[test]
+    fn should_cap_the_configured_price() {
+        assert_eq!(fallback_compute_unit_price(&policy(Some(500_000))), 100_000);
+    }
+}
@@ -0,0 +1,248 @@
+//! Decoding of the instructions the canister is willing to sign.
+//!
+//! Only the programs and instructions needed to deploy and manage a Candy Machine
+//! are recognised; anything else fails to decode and the transaction is rejected.
+
+use sha2::{Digest, Sha256};
+use solana_pubkey::{pubkey, Pubkey};
+
+#[cfg(test)]
+mod tests;
+
+pub const SYSTEM_PROGRAM_ID: Pubkey = pubkey!("11111111111111111111111111111111");
+pub const COMPUTE_BUDGET_PROGRAM_ID: Pubkey = pubkey!("ComputeBudget111111111111111111111111111111");
+pub const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAdjNfeK9CHGPpJb2FwnXaeNDZ1VV");
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+pub const CANDY_MACHINE_PROGRAM_ID: Pubkey = pubkey!("CndyV3LdqHUfDLmE5naZjVN8rBZz4tqhdefbAnjHG3JR");
+pub const CANDY_GUARD_PROGRAM_ID: Pubkey = pubkey!("Guard1JwRhJkVH6XZhzoYxeBVQe872VH6QggF4BWmS9g");
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey = pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+pub const SYSVAR_INSTRUCTIONS_ID: Pubkey = pubkey!("Sysvar1nstructions1111111111111111111111111");
+
+// Rent parameters, used to bound the lamports funding a new account
+const ACCOUNT_STORAGE_OVERHEAD: u64 = 128;
+const LAMPORTS_PER_BYTE_YEAR: u64 = 3_480;
+const EXEMPTION_THRESHOLD_YEARS: u64 = 2;
+
+/// Base fee for each signature a transaction carries.
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+
+// Sizes of the accounts the programs create at the payer's expense
+const MINT_SIZE: u64 = 82;
+const TOKEN_ACCOUNT_SIZE: u64 = 165;
+const METADATA_SIZE: u64 = 679;
+const MASTER_EDITION_SIZE: u64 = 282;
+const METADATA_DELEGATE_RECORD_SIZE: u64 = 98;
+/// Candy guard account before its guard data: discriminator, base, bump and authority.
+const CANDY_GUARD_HEADER_SIZE: u64 = 8 + 32 + 1 + 32;
+/// Protocol fee Token Metadata collects when it creates a metadata account.
+const TOKEN_METADATA_CREATE_FEE: u64 = 10_000_000;
+
+/// Balance that makes an account of `space` bytes rent-exempt.
+pub fn rent_exempt_minimum(space: u64) -> u64 {
+    (ACCOUNT_STORAGE_OVERHEAD + space)
+        .saturating_mul(LAMPORTS_PER_BYTE_YEAR)
+        .saturating_mul(EXEMPTION_THRESHOLD_YEARS)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CandyMachineInstruction {
+    InitializeV2,
+    AddConfigLines,
+    Update,
+    SetCollectionV2,
+    SetTokenStandard,
+    SetAuthority { new_authority: Pubkey },
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CandyGuardInstruction {
+    /// `data_len` is the size of the compiled guard data.
+    Initialize { data_len: u64 },
+    Update { data_len: u64 },
+    /// Makes the candy guard the candy machine's mint authority.
+    Wrap,
+}
+
+/// Token Metadata instructions that create or verify the collection NFT.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenMetadataInstruction {
+    CreateMasterEditionV3,
+    CreateMetadataAccountV3,
+    SetCollectionSize,
+    Create,
+    Mint,
+    Verify,
+}
+
+impl TokenMetadataInstruction {
+    /// Position of the mint the instruction acts on: the NFT's mint, or the
+    /// collection mint for `Verify`.
+    pub fn mint_position(&self) -> usize {
+        match self {
+            TokenMetadataInstruction::CreateMasterEditionV3 => 1,
+            TokenMetadataInstruction::CreateMetadataAccountV3 => 1,
+            TokenMetadataInstruction::SetCollectionSize => 2,
+            TokenMetadataInstruction::Create => 2,
+            TokenMetadataInstruction::Mint => 5,
+            TokenMetadataInstruction::Verify => 3,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProgramInstruction {
+    CreateAccount { lamports: u64, space: u64, owner: Pubkey },
+    SetComputeUnitLimit(u32),
+    SetComputeUnitPrice(u64),
+    /// `InitializeMint` or `InitializeMint2`.
+    InitializeMint,
+    /// `MintTo` or `MintToChecked`.
+    MintTo,
+    TokenMetadata(TokenMetadataInstruction),
+    CandyMachine(CandyMachineInstruction),
+    CandyGuard(CandyGuardInstruction),
+}
+
+impl ProgramInstruction {
+    /// Lamports the instruction takes from the payer: the funding of a new account,
+    /// or the rent and fees of the accounts a program creates on its behalf. An
+    /// upper bound; a candy guard `Update` is charged as if the account were new.
+    pub fn lamports_paid(&self) -> u64 {
+        match self {
+            ProgramInstruction::CreateAccount { lamports, .. } => *lamports,
+            ProgramInstruction::TokenMetadata(instruction) => match instruction {
+                TokenMetadataInstruction::Create => rent_exempt_minimum(METADATA_SIZE)
+                    + rent_exempt_minimum(MASTER_EDITION_SIZE)
+                    + rent_exempt_minimum(MINT_SIZE)
+                    + TOKEN_METADATA_CREATE_FEE,
+                TokenMetadataInstruction::CreateMetadataAccountV3 => {
+                    rent_exempt_minimum(METADATA_SIZE) + TOKEN_METADATA_CREATE_FEE
+                }
+                TokenMetadataInstruction::CreateMasterEditionV3 => rent_exempt_minimum(MASTER_EDITION_SIZE),
+                TokenMetadataInstruction::Mint => rent_exempt_minimum(TOKEN_ACCOUNT_SIZE),
+                TokenMetadataInstruction::SetCollectionSize | TokenMetadataInstruction::Verify => 0,
+            },
+            // Both create the delegate record that lets the candy machine verify into the collection
+            ProgramInstruction::CandyMachine(
+                CandyMachineInstruction::InitializeV2 | CandyMachineInstruction::SetCollectionV2,
+            ) => rent_exempt_minimum(METADATA_DELEGATE_RECORD_SIZE),
+            ProgramInstruction::CandyGuard(
+                CandyGuardInstruction::Initialize { data_len } | CandyGuardInstruction::Update { data_len },
+            ) => rent_exempt_minimum(CANDY_GUARD_HEADER_SIZE.saturating_add(*data_len)),
+            _ => 0,
+        }
+    }
+}
+
+/// First 8 bytes of `sha256("global:<name>")`, the Anchor instruction discriminator.
+pub fn anchor_discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("global:{}", name).as_bytes());
+    hash[..8].try_into().unwrap()
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+fn read_pubkey(data: &[u8], offset: usize) -> Option<Pubkey> {
+    let bytes: [u8; 32] = data.get(offset..offset + 32)?.try_into().ok()?;
+    Some(Pubkey::from(bytes))
+}
+
+pub fn decode_instruction(program_id: &Pubkey, data: &[u8]) -> Result<ProgramInstruction, String> {
+    let malformed = || format!("malformed instruction data for program {}", program_id);
+
+    if *program_id == SYSTEM_PROGRAM_ID {
+        // Only `CreateAccount`; transfers out of the payer are never signed
+        return match read_u32(data, 0) {
+            Some(0) if data.len() == 52 => Ok(ProgramInstruction::CreateAccount {
+                lamports: read_u64(data, 4).ok_or_else(malformed)?,
+                space: read_u64(data, 12).ok_or_else(malformed)?,
+                owner: read_pubkey(data, 20).ok_or_else(malformed)?,
+            }),
+            Some(tag) => Err(format!("System instruction {} is not allowed", tag)),
+            None => Err(malformed()),
+        };
+    }
+
+    if *program_id == COMPUTE_BUDGET_PROGRAM_ID {
+        return match data.first() {
+            Some(2) => read_u32(data, 1)
+                .map(ProgramInstruction::SetComputeUnitLimit)
+                .ok_or_else(malformed),
+            Some(3) => read_u64(data, 1)
+                .map(ProgramInstruction::SetComputeUnitPrice)
+                .ok_or_else(malformed),
+            Some(tag) => Err(format!("Compute Budget instruction {} is not allowed", tag)),
+            None => Err(malformed()),
+        };
+    }
+
+    if *program_id == TOKEN_PROGRAM_ID {
+        return match data.first() {
+            Some(0) | Some(20) => Ok(ProgramInstruction::InitializeMint),
+            Some(7) | Some(14) => Ok(ProgramInstruction::MintTo),
+            Some(tag) => Err(format!("SPL Token instruction {} is not allowed", tag)),
+            None => Err(malformed()),
+        };
+    }
+
+    if *program_id == TOKEN_METADATA_PROGRAM_ID {
+        // CreateMasterEditionV3, CreateMetadataAccountV3, SetCollectionSize, Create, Mint, Verify
+        let instruction = match data.first() {
+            Some(17) => TokenMetadataInstruction::CreateMasterEditionV3,
+            Some(33) => TokenMetadataInstruction::CreateMetadataAccountV3,
+            Some(34) => TokenMetadataInstruction::SetCollectionSize,
+            Some(42) => TokenMetadataInstruction::Create,
+            Some(43) => TokenMetadataInstruction::Mint,
+            Some(52) => TokenMetadataInstruction::Verify,
+            Some(tag) => return Err(format!("Token Metadata instruction {} is not allowed", tag)),
+            None => return Err(malformed()),
+        };
+        return Ok(ProgramInstruction::TokenMetadata(instruction));
+    }
+
+    if *program_id == CANDY_MACHINE_PROGRAM_ID {
+        let discriminator = data.get(..8).ok_or_else(malformed)?;
+        let instruction = if discriminator == anchor_discriminator("initialize_v2") {
+            CandyMachineInstruction::InitializeV2
+        } else if discriminator == anchor_discriminator("add_config_lines") {
+            CandyMachineInstruction::AddConfigLines
+        } else if discriminator == anchor_discriminator("update") {
+            CandyMachineInstruction::Update
+        } else if discriminator == anchor_discriminator("set_collection_v2") {
+            CandyMachineInstruction::SetCollectionV2
+        } else if discriminator == anchor_discriminator("set_token_standard") {
+            CandyMachineInstruction::SetTokenStandard
+        } else if discriminator == anchor_discriminator("set_authority") {
+            CandyMachineInstruction::SetAuthority {
+                new_authority: read_pubkey(data, 8).ok_or_else(malformed)?,
+            }
+        } else {
+            return Err("Candy Machine instruction is not allowed".to_string());
+        };
+        return Ok(ProgramInstruction::CandyMachine(instruction));
+    }
+
+    if *program_id == CANDY_GUARD_PROGRAM_ID {
+        let discriminator = data.get(..8).ok_or_else(malformed)?;
+        // `initialize` and `update` take the guard data as a borsh `Vec<u8>`
+        let data_len = || read_u32(data, 8).map(u64::from).ok_or_else(malformed);
+        let instruction = if discriminator == anchor_discriminator("initialize") {
+            CandyGuardInstruction::Initialize { data_len: data_len()? }
+        } else if discriminator == anchor_discriminator("update") {
+            CandyGuardInstruction::Update { data_len: data_len()? }
+        } else if discriminator == anchor_discriminator("wrap") {
+            CandyGuardInstruction::Wrap
+        } else {
+            return Err("Candy Guard instruction is not allowed".to_string());
+        };
+        return Ok(ProgramInstruction::CandyGuard(instruction));
+    }
+
+    Err(format!("Program {} is not allowed", program_id))
+}
@@ -0,0 +1,325 @@
+use super::*;
+use crate::solana::instructions::{self, CandyMachineData};
+
+fn key(byte: u8) -> Pubkey {
+    Pubkey::new_from_array([byte; 32])
+}
+
+mod discriminators {
+    use super::*;
+
+    #[test]
+    fn should_match_candy_machine_idl() {
+        assert_eq!(
+            anchor_discriminator("initialize_v2"),
+            [67, 153, 175, 39, 218, 16, 38, 32]
+        );
+        assert_eq!(
+            anchor_discriminator("add_config_lines"),
+            [223, 50, 224, 227, 151, 8, 115, 106]
+        );
+        assert_eq!(anchor_discriminator("update"), [219, 200, 88, 176, 158, 63, 253, 127]);
+        assert_eq!(
+            anchor_discriminator("set_authority"),
+            [133, 250, 37, 21, 110, 163, 26, 121]
+        );
+    }
+
+    #[test]
+    fn should_match_candy_guard_idl() {
+        assert_eq!(
+            anchor_discriminator("initialize"),
+            [175, 175, 109, 31, 13, 152, 155, 237]
+        );
+        assert_eq!(anchor_discriminator("wrap"), [178, 40, 10, 189, 228, 129, 186, 140]);
+    }
+
+    #[test]
+    fn should_decode_instructions_built_by_the_canister() {
+        let candy_machine = key(1);
+        let payer = key(2);
+        let collection_mint = key(3);
+        let data = CandyMachineData::new(100, "SYM".to_string(), 500, &payer);
+
+        let decode = |instruction: solana_instruction::Instruction| {
+            decode_instruction(&instruction.program_id, &instruction.data)
+        };
+
+        assert_eq!(
+            decode(instructions::initialize_v2(
+                &candy_machine,
+                &payer,
+                &collection_mint,
+                &data
+            )),
+            Ok(ProgramInstruction::CandyMachine(CandyMachineInstruction::InitializeV2))
+        );
+        assert_eq!(
+            decode(instructions::add_config_lines(&candy_machine, &payer, 0, &[])),
+            Ok(ProgramInstruction::CandyMachine(
+                CandyMachineInstruction::AddConfigLines
+            ))
+        );
+        assert_eq!(
+            decode(instructions::set_collection_v2(
+                &candy_machine,
+                &payer,
+                &collection_mint,
+                &key(4)
+            )),
+            Ok(ProgramInstruction::CandyMachine(
+                CandyMachineInstruction::SetCollectionV2
+            ))
+        );
+        assert_eq!(
+            decode(instructions::set_authority(&candy_machine, &payer, &key(5))),
+            Ok(ProgramInstruction::CandyMachine(
+                CandyMachineInstruction::SetAuthority { new_authority: key(5) }
+            ))
+        );
+        assert_eq!(
+            decode(instructions::initialize_candy_guard(
+                &candy_machine,
+                &payer,
+                &payer,
+                vec![0; 20]
+            )),
+            Ok(ProgramInstruction::CandyGuard(CandyGuardInstruction::Initialize {
+                data_len: 20
+            }))
+        );
+        assert_eq!(
+            decode(instructions::update_candy_guard(&key(6), &payer, &payer, vec![0; 12])),
+            Ok(ProgramInstruction::CandyGuard(CandyGuardInstruction::Update {
+                data_len: 12
+            }))
+        );
+        assert_eq!(
+            decode(instructions::wrap_candy_guard(&key(6), &candy_machine, &payer)),
+            Ok(ProgramInstruction::CandyGuard(CandyGuardInstruction::Wrap))
+        );
+        assert_eq!(
+            decode(instructions::create_collection_nft(
+                &collection_mint,
+                &payer,
+                "Name".to_string(),
+                "SYM".to_string(),
+                "https://example.com".to_string(),
+                500,
+            )),
+            Ok(ProgramInstruction::TokenMetadata(TokenMetadataInstruction::Create))
+        );
+        assert_eq!(
+            decode(instructions::mint_collection_nft(&collection_mint, &payer)),
+            Ok(ProgramInstruction::TokenMetadata(TokenMetadataInstruction::Mint))
+        );
+    }
+
+    #[test]
+    fn should_reject_other_anchor_instructions() {
+        let withdraw = anchor_discriminator("withdraw");
+
+        assert_eq!(
+            decode_instruction(&CANDY_MACHINE_PROGRAM_ID, &withdraw),
+            Err("Candy Machine instruction is not allowed".to_string())
+        );
+        assert_eq!(
+            decode_instruction(&CANDY_GUARD_PROGRAM_ID, &withdraw),
+            Err("Candy Guard instruction is not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn should_reject_truncated_discriminator() {
+        assert!(decode_instruction(&CANDY_MACHINE_PROGRAM_ID, &[67, 153, 175]).is_err());
+        assert!(decode_instruction(&CANDY_GUARD_PROGRAM_ID, &anchor_discriminator("initialize")).is_err());
+    }
+}
+
+mod system {
+    use super::*;
+    use solana_system_interface::instruction as system_instruction;
+
+    #[test]
+    fn should_decode_create_account() {
+        let instruction = system_instruction::create_account(&key(1), &key(2), 1_000, 82, &TOKEN_PROGRAM_ID);
+
+        assert_eq!(
+            decode_instruction(&SYSTEM_PROGRAM_ID, &instruction.data),
+            Ok(ProgramInstruction::CreateAccount {
+                lamports: 1_000,
+                space: 82,
+                owner: TOKEN_PROGRAM_ID,
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_transfer() {
+        let instruction = system_instruction::transfer(&key(1), &key(2), 1_000);
+
+        assert_eq!(
+            decode_instruction(&SYSTEM_PROGRAM_ID, &instruction.data),
+            Err("System instruction 2 is not allowed".to_string())
+        );
+    }
+
+    #[test]
+    fn should_reject_other_tags() {
+        for tag in [1u32, 3, 4, 8, 9, 11] {
+            let mut data = tag.to_le_bytes().to_vec();
+            data.extend_from_slice(&[0; 48]);
+
+            assert_eq!(
+                decode_instruction(&SYSTEM_PROGRAM_ID, &data),
+                Err(format!("System instruction {} is not allowed", tag))
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_create_account_with_trailing_data() {
+        let mut data = system_instruction::create_account(&key(1), &key(2), 1_000, 82, &TOKEN_PROGRAM_ID).data;
+        data.push(0);
+
+        assert_eq!(
+            decode_instruction(&SYSTEM_PROGRAM_ID, &data),
+            Err("System instruction 0 is not allowed".to_string())
+        );
+    }
+}
+
+mod token {
+    use super::*;
+
+    #[test]
+    fn should_decode_mint_instructions() {
+        for tag in [0u8, 20] {
+            assert_eq!(
+                decode_instruction(&TOKEN_PROGRAM_ID, &[tag]),
+                Ok(ProgramInstruction::InitializeMint)
+            );
+        }
+        for tag in [7u8, 14] {
+            assert_eq!(
+                decode_instruction(&TOKEN_PROGRAM_ID, &[tag]),
+                Ok(ProgramInstruction::MintTo)
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_transfers_approvals_and_authority_changes() {
+        // Transfer, Approve, SetAuthority, Burn, CloseAccount, TransferChecked
+        for tag in [3u8, 4, 6, 8, 9, 12] {
+            assert_eq!(
+                decode_instruction(&TOKEN_PROGRAM_ID, &[tag]),
+                Err(format!("SPL Token instruction {} is not allowed", tag))
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_other_token_metadata_instructions() {
+        // UpdateMetadataAccountV2, Burn, Transfer, Update
+        for tag in [15u8, 41, 49, 50] {
+            assert_eq!(
+                decode_instruction(&TOKEN_METADATA_PROGRAM_ID, &[tag]),
+                Err(format!("Token Metadata instruction {} is not allowed", tag))
+            );
+        }
+    }
+
+    #[test]
+    fn should_reject_empty_data() {
+        assert!(decode_instruction(&TOKEN_PROGRAM_ID, &[]).is_err());
+        assert!(decode_instruction(&TOKEN_METADATA_PROGRAM_ID, &[]).is_err());
+    }
+}
+
+mod compute_budget {
+    use super::*;
+    use crate::solana::priority_fees::{set_compute_unit_limit, set_compute_unit_price};
+
+    #[test]
+    fn should_decode_limit_and_price() {
+        assert_eq!(
+            decode_instruction(&COMPUTE_BUDGET_PROGRAM_ID, &set_compute_unit_limit(400_000).data),
+            Ok(ProgramInstruction::SetComputeUnitLimit(400_000))
+        );
+        assert_eq!(
+            decode_instruction(&COMPUTE_BUDGET_PROGRAM_ID, &set_compute_unit_price(1_234).data),
+            Ok(ProgramInstruction::SetComputeUnitPrice(1_234))
+        );
+    }
+
+    #[test]
+    fn should_reject_heap_frame_and_data_size_requests() {
+        for tag in [1u8, 4] {
+            assert_eq!(
+                decode_instruction(&COMPUTE_BUDGET_PROGRAM_ID, &[tag, 0, 0, 0, 0]),
+                Err(format!("Compute Budget instruction {} is not allowed", tag))
+            );
+        }
+    }
+}
+
+#[test]
+fn should_reject_unknown_program() {
+    assert_eq!(
+        decode_instruction(&key(9), &[0]),
+        Err(format!("Program {} is not allowed", key(9)))
+    );
+}
+
+mod accounts {
+    use super::*;
+
+    #[test]
+    fn should_locate_the_mint_of_token_metadata_instructions() {
+        let mint = key(3);
+        let position = |instruction: &solana_instruction::Instruction, decoded: TokenMetadataInstruction| {
+            instruction.accounts[decoded.mint_position()].pubkey
+        };
+
+        let create = instructions::create_collection_nft(
+            &mint,
+            &key(2),
+            "Name".to_string(),
+            "SYM".to_string(),
+            "https://example.com".to_string(),
+            500,
+        );
+        assert_eq!(position(&create, TokenMetadataInstruction::Create), mint);
+
+        let mint_to = instructions::mint_collection_nft(&mint, &key(2));
+        assert_eq!(position(&mint_to, TokenMetadataInstruction::Mint), mint);
+    }
+
+    #[test]
+    fn should_charge_rent_of_implicitly_created_accounts() {
+        assert_eq!(
+            ProgramInstruction::CreateAccount {
+                lamports: 42,
+                space: 0,
+                owner: SYSTEM_PROGRAM_ID
+            }
+            .lamports_paid(),
+            42
+        );
+        assert_eq!(
+            ProgramInstruction::CandyGuard(CandyGuardInstruction::Update { data_len: 100 }).lamports_paid(),
+            rent_exempt_minimum(CANDY_GUARD_HEADER_SIZE + 100)
+        );
+        assert!(
+            ProgramInstruction::TokenMetadata(TokenMetadataInstruction::Create).lamports_paid()
+                > rent_exempt_minimum(METADATA_SIZE) + TOKEN_METADATA_CREATE_FEE
+        );
+        assert!(ProgramInstruction::CandyMachine(CandyMachineInstruction::InitializeV2).lamports_paid() > 0);
+        assert_eq!(
+            ProgramInstruction::TokenMetadata(TokenMetadataInstruction::Verify).lamports_paid(),
+            0
+        );
+        assert_eq!(ProgramInstruction::SetComputeUnitPrice(1_000_000).lamports_paid(), 0);
+    }
+}
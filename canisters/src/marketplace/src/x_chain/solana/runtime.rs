@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+use candid::{utils::ArgumentEncoder, CandidType, Deserialize, Principal, Reserved};
+use ic_error_types::RejectCode;
+use serde::de::DeserializeOwned;
+use sol_rpc_client::{IcRuntime, Runtime};
+use crate::metrics::{self, RpcOutcome};
+
+/// Just the shape of `MultiRpcResult`, enough to tell the outcome of a call
+/// without knowing its result type.
+#[derive(CandidType, Deserialize)]
+enum MultiRpcOutcome {
+    Consistent(Reserved),
+    Inconsistent(Reserved),
+}
+
+fn outcome<Out: CandidType>(result: &Out) -> RpcOutcome {
+    let decoded = candid::encode_one(result)
+        .ok()
+        .and_then(|bytes| candid::decode_one::<MultiRpcOutcome>(&bytes).ok());
+    match decoded {
+        Some(MultiRpcOutcome::Consistent(_)) => RpcOutcome::Consistent,
+        Some(MultiRpcOutcome::Inconsistent(_)) => RpcOutcome::Inconsistent,
+        None => RpcOutcome::Other,
+    }
+}
+
+/// [`IcRuntime`] that records every SOL RPC call in the canister metrics.
+#[derive(Copy, Clone, Debug)]
+pub struct MetricsRuntime(pub IcRuntime);
+
+#[async_trait]
+impl Runtime for MetricsRuntime {
+    async fn update_call<In, Out>(
+        &self,
+        id: Principal,
+        method: &str,
+        args: In,
+        cycles: u128,
+    ) -> Result<Out, (RejectCode, String)>
+    where
+        In: ArgumentEncoder + Send,
+        Out: CandidType + DeserializeOwned,
+    {
+        let result = self.0.update_call::<In, Out>(id, method, args, cycles).await;
+        let spent = cycles.saturating_sub(ic_cdk::api::msg_cycles_refunded());
+        let outcome = match &result {
+            Ok(response) => outcome(response),
+            Err(_) => RpcOutcome::Rejected,
+        };
+        metrics::observe_rpc_call(method, outcome, spent);
+        result
+    }
+
+    async fn query_call<In, Out>(
+        &self,
+        id: Principal,
+        method: &str,
+        args: In,
+    ) -> Result<Out, (RejectCode, String)>
+    where
+        In: ArgumentEncoder + Send,
+        Out: CandidType + DeserializeOwned,
+    {
+        self.0.query_call(id, method, args).await
+    }
+}
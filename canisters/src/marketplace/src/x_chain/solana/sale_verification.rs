@@ -0,0 +1,151 @@
+use super::client;
+use crate::types::{Listing, SaleRejectionReason};
+use base64::Engine;
+use sol_rpc_types::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransaction, GetTransactionEncoding,
+    LoadedAddresses, MultiRpcResult, TransactionBinaryEncoding, TransactionTokenBalance,
+};
+use solana_signature::Signature;
+use solana_transaction::versioned::VersionedTransaction;
+use std::str::FromStr;
+
+/// Fetches `tx_signature` and checks that it was processed after the listing was
+/// created, moved the listed NFT from the seller to `buyer_address` and credited the
+/// seller with exactly the listing's sale price.
+pub async fn verify_sale(
+    listing: &Listing,
+    buyer_address: &str,
+    tx_signature: &str,
+) -> Result<(), SaleRejectionReason> {
+    let signature = Signature::from_str(tx_signature)
+        .map_err(|e| SaleRejectionReason::InvalidSignature(e.to_string()))?;
+
+    let confirmed = fetch_transaction(signature).await?;
+
+    // A transaction from before the listing existed cannot be its purchase, e.g. an
+    // earlier sale of the same NFT between the same wallets
+    let block_time = confirmed.block_time.ok_or(SaleRejectionReason::MissingBlockTime)?;
+    if block_time < (listing.listed_at / 1_000_000_000) as i64 {
+        return Err(SaleRejectionReason::TransactionBeforeListing {
+            block_time,
+            listed_at: listing.listed_at,
+        });
+    }
+
+    let meta = confirmed
+        .transaction
+        .meta
+        .ok_or(SaleRejectionReason::MissingTransactionMeta)?;
+    if let Err(e) = meta.status {
+        return Err(SaleRejectionReason::TransactionFailed(format!("{:?}", e)));
+    }
+
+    let transaction = decode_transaction(confirmed.transaction.transaction)?;
+
+    let pre_tokens = meta.pre_token_balances.unwrap_or_default();
+    let post_tokens = meta.post_token_balances.unwrap_or_default();
+    let seller_delta = token_delta(&pre_tokens, &post_tokens, &listing.nft_id, &listing.seller_address);
+    let buyer_delta = token_delta(&pre_tokens, &post_tokens, &listing.nft_id, buyer_address);
+    if seller_delta >= 0 || buyer_delta <= 0 {
+        return Err(SaleRejectionReason::NftNotTransferred {
+            mint: listing.nft_id.clone(),
+            from: listing.seller_address.clone(),
+            to: buyer_address.to_string(),
+        });
+    }
+
+    if !listing.currency.eq_ignore_ascii_case("SOL") {
+        return Err(SaleRejectionReason::UnsupportedCurrency(listing.currency.clone()));
+    }
+
+    let seller_index = account_keys(&transaction, meta.loaded_addresses.as_ref())
+        .iter()
+        .position(|key| *key == listing.seller_address);
+    let received = match seller_index {
+        Some(index) => {
+            let pre = meta.pre_balances.get(index).copied().unwrap_or_default();
+            let post = meta.post_balances.get(index).copied().unwrap_or_default();
+            // The fee payer is always the first account key
+            let fee = if index == 0 { meta.fee } else { 0 };
+            post.saturating_add(fee).saturating_sub(pre)
+        }
+        None => 0,
+    };
+    if received != listing.sale_price() {
+        return Err(SaleRejectionReason::PriceMismatch {
+            expected: listing.sale_price(),
+            paid: received,
+        });
+    }
+
+    Ok(())
+}
+
+pub(super) async fn fetch_transaction(
+    signature: Signature,
+) -> Result<EncodedConfirmedTransactionWithStatusMeta, SaleRejectionReason> {
+    let result = client()
+        .get_transaction(signature)
+        .with_encoding(GetTransactionEncoding::Base64)
+        .with_max_supported_transaction_version(0)
+        .send()
+        .await;
+
+    let transaction = match result {
+        MultiRpcResult::Consistent(result) => {
+            result.map_err(|e| SaleRejectionReason::RpcError(e.to_string()))?
+        }
+        MultiRpcResult::Inconsistent(_) => return Err(SaleRejectionReason::InconsistentRpcResults),
+    };
+
+    let transaction = transaction.ok_or(SaleRejectionReason::TransactionNotFound)?;
+    EncodedConfirmedTransactionWithStatusMeta::try_from(transaction)
+        .map_err(|e| SaleRejectionReason::UndecodableTransaction(e.to_string()))
+}
+
+pub(super) fn decode_transaction(encoded: EncodedTransaction) -> Result<VersionedTransaction, SaleRejectionReason> {
+    let bytes = match encoded {
+        EncodedTransaction::LegacyBinary(data) => bs58::decode(data).into_vec().map_err(|e| e.to_string()),
+        EncodedTransaction::Binary(data, TransactionBinaryEncoding::Base58) => {
+            bs58::decode(data).into_vec().map_err(|e| e.to_string())
+        }
+        EncodedTransaction::Binary(data, TransactionBinaryEncoding::Base64) => {
+            base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| e.to_string())
+        }
+    }
+    .map_err(SaleRejectionReason::UndecodableTransaction)?;
+
+    bincode::deserialize(&bytes)
+        .map_err(|e| SaleRejectionReason::UndecodableTransaction(e.to_string()))
+}
+
+/// Account keys in the order the balances are reported: the message's static keys,
+/// then the writable and readonly addresses loaded from lookup tables.
+pub(super) fn account_keys(transaction: &VersionedTransaction, loaded: Option<&LoadedAddresses>) -> Vec<String> {
+    let static_keys = transaction.message.static_account_keys().iter().map(|key| key.to_string());
+    let loaded_keys = loaded
+        .into_iter()
+        .flat_map(|loaded| loaded.writable.iter().chain(&loaded.readonly))
+        .map(|key| key.to_string());
+    static_keys.chain(loaded_keys).collect()
+}
+
+/// Net change of `owner`'s balance of `mint` across the transaction, in raw token units.
+fn token_delta(
+    pre: &[TransactionTokenBalance],
+    post: &[TransactionTokenBalance],
+    mint: &str,
+    owner: &str,
+) -> i128 {
+    let total = |balances: &[TransactionTokenBalance]| -> i128 {
+        balances
+            .iter()
+            .filter(|b| b.mint == mint)
+            .filter(|b| b.owner.as_ref().is_some_and(|o| o.to_string() == owner))
+            .filter_map(|b| b.ui_token_amount.amount.parse::<i128>().ok())
+            .sum()
+    };
+    total(post) - total(pre)
+}
@@ -0,0 +1,312 @@
+//! Follows the transactions the canister signs for a collection until Solana
+//! finalizes them. Finalized steps move the collection's deployment forward;
+//! submissions whose blockhash expired are re-signed and sent again. Once a
+//! transaction settles, its lamport charge is corrected to what it actually cost.
+
+use super::candy_machine::{self, SendFailure};
+use super::client;
+use super::sale_verification::fetch_transaction;
+use crate::logs::{log, Priority};
+use crate::state::{self, get_collection};
+use crate::types::{
+    ChainData, DeploymentStep, DeploymentTransaction, DeploymentTransactionStatus, Entity,
+    MarketplaceError, MarketplaceResult, PauseTarget, SolanaDeploymentStage, UpdateSolanaStageArgs,
+};
+use sol_rpc_types::MultiRpcResult;
+use solana_signature::Signature;
+use solana_transaction_status_client_types::TransactionConfirmationStatus;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+
+/// Most signatures `getSignatureStatuses` accepts in one call.
+const MAX_SIGNATURES_PER_POLL: usize = 256;
+
+/// For submissions whose blockhash validity is not known by block height: a
+/// blockhash is valid for 150 blocks, about a minute, so a submission still unknown
+/// to the cluster after this long can no longer land and is re-signed.
+const BLOCKHASH_EXPIRY_NANOS: u64 = 3 * 60 * 1_000_000_000;
+
+const MAX_RESUBMISSIONS: usize = 5;
+
+pub async fn poll_deployment_transactions() {
+    let pending = state::get_pending_deployment_transactions(MAX_SIGNATURES_PER_POLL);
+    if pending.is_empty() {
+        return;
+    }
+
+    let mut tracked = Vec::with_capacity(pending.len());
+    for transaction in pending {
+        match Signature::from_str(&transaction.signature) {
+            Ok(signature) => tracked.push((transaction, signature)),
+            Err(e) => settle(
+                transaction,
+                DeploymentTransactionStatus::Failed {
+                    error: format!("Invalid signature: {:?}", e),
+                },
+                Some(0),
+            ),
+        }
+    }
+
+    let request = match client().get_signature_statuses(tracked.iter().map(|(_, signature)| signature)) {
+        Ok(request) => request.with_search_transaction_history(true),
+        Err(e) => {
+            log!(Priority::Info, "Failed to build signature status request: {:?}", e);
+            return;
+        }
+    };
+    let statuses = match request.send().await {
+        MultiRpcResult::Consistent(Ok(statuses)) => statuses,
+        MultiRpcResult::Consistent(Err(e)) => {
+            log!(Priority::Info, "Failed to get signature statuses: {}", e);
+            return;
+        }
+        MultiRpcResult::Inconsistent(_) => {
+            log!(
+                Priority::Info,
+                "RPC providers disagree on signature statuses, retrying on the next poll"
+            );
+            return;
+        }
+    };
+
+    // Only needed to tell whether submissions the cluster doesn't know of expired
+    let unknown_with_height = tracked
+        .iter()
+        .zip(&statuses)
+        .any(|((transaction, _), status)| status.is_none() && transaction.last_valid_block_height.is_some());
+    let block_height = if unknown_with_height {
+        match candy_machine::current_block_height().await {
+            Ok(height) => Some(height),
+            Err(e) => {
+                log!(Priority::Info, "Failed to get block height: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let now = crate::time::get_current_time();
+    let mut changed_candy_machines = BTreeSet::new();
+    for ((mut transaction, _), status) in tracked.into_iter().zip(statuses) {
+        match status {
+            Some(status) => {
+                if let Some(err) = status.err {
+                    // A transaction that failed on chain still paid its fees
+                    let cost = landed_cost(&transaction).await;
+                    settle(
+                        transaction,
+                        DeploymentTransactionStatus::Failed {
+                            error: err.to_string(),
+                        },
+                        cost,
+                    );
+                } else if status.confirmation_status == Some(TransactionConfirmationStatus::Finalized) {
+                    if changes_candy_machine(&transaction.step) {
+                        changed_candy_machines.insert(transaction.collection_id.clone());
+                    }
+                    let cost = landed_cost(&transaction).await;
+                    settle(transaction, DeploymentTransactionStatus::Finalized, cost);
+                } else if transaction.status != DeploymentTransactionStatus::Confirmed {
+                    transaction.status = DeploymentTransactionStatus::Confirmed;
+                    state::update_deployment_transaction(transaction);
+                }
+            }
+            None if !blockhash_expired(&transaction, block_height, now) => {}
+            None => resubmit(transaction).await,
+        }
+    }
+
+    for collection_id in changed_candy_machines {
+        if let Err(e) = candy_machine::sync_candy_machine_state(&collection_id).await {
+            log!(
+                Priority::Info,
+                "Failed to sync candy machine of collection {}: {}",
+                collection_id,
+                e
+            );
+        }
+    }
+}
+
+fn changes_candy_machine(step: &DeploymentStep) -> bool {
+    !matches!(
+        step,
+        DeploymentStep::CreateCollectionNft { .. } | DeploymentStep::ClientTransaction { .. }
+    )
+}
+
+/// Whether the cluster passed the last block height at which the latest submission
+/// could land. Without a recorded height, whether it was submitted long enough ago.
+fn blockhash_expired(transaction: &DeploymentTransaction, block_height: Option<u64>, now: u64) -> bool {
+    match (transaction.last_valid_block_height, block_height) {
+        (Some(last_valid), Some(height)) => height > last_valid,
+        // Check again on the next poll
+        (Some(_), None) => false,
+        (None, _) => now.saturating_sub(transaction.submitted_at) >= BLOCKHASH_EXPIRY_NANOS,
+    }
+}
+
+async fn resubmit(mut transaction: DeploymentTransaction) {
+    if transaction.replaced_signatures.len() >= MAX_RESUBMISSIONS {
+        settle(transaction, DeploymentTransactionStatus::Expired, Some(0));
+        return;
+    }
+    // Wait for signing to resume rather than giving up on the transaction
+    if state::get_pause(PauseTarget::SolanaSigning).is_some() {
+        return;
+    }
+    let Some(message) = transaction.message.clone() else {
+        settle(transaction, DeploymentTransactionStatus::Expired, Some(0));
+        return;
+    };
+
+    let (blockhash, last_valid_block_height) = match candy_machine::recent_blockhash().await {
+        Ok(blockhash) => blockhash,
+        Err(e) => {
+            log!(Priority::Info, "Failed to get a blockhash to resubmit {}: {}", transaction.signature, e);
+            return;
+        }
+    };
+    let signature = match candy_machine::resubmit_transaction(&transaction, &message, blockhash).await {
+        Ok(signature) => signature,
+        // Follow the new submission, it may still land
+        Err(SendFailure::Unknown { signature, error }) => {
+            log!(
+                Priority::Info,
+                "Resubmission {} of transaction {} may have reached the cluster: {}",
+                signature,
+                transaction.signature,
+                error
+            );
+            signature
+        }
+        Err(SendFailure::Rejected(e)) => {
+            settle(
+                transaction,
+                DeploymentTransactionStatus::Failed {
+                    error: format!("Failed to resubmit: {}", e),
+                },
+                Some(0),
+            );
+            return;
+        }
+    };
+    log!(
+        Priority::Info,
+        "Blockhash of transaction {} expired, resubmitted as {}",
+        transaction.signature,
+        signature
+    );
+    let expired = std::mem::replace(&mut transaction.signature, signature.to_string());
+    transaction.replaced_signatures.push(expired);
+    transaction.submitted_at = crate::time::get_current_time();
+    transaction.last_valid_block_height = Some(last_valid_block_height);
+    transaction.status = DeploymentTransactionStatus::Pending;
+    state::update_deployment_transaction(transaction);
+}
+
+/// What a transaction that landed cost the canister payer, which pays its fees and
+/// so is its first account. `None` if the transaction can't be fetched.
+async fn landed_cost(transaction: &DeploymentTransaction) -> Option<u64> {
+    let signature = Signature::from_str(&transaction.signature).ok()?;
+    match fetch_transaction(signature).await {
+        Ok(confirmed) => {
+            let meta = confirmed.transaction.meta?;
+            let pre = meta.pre_balances.first().copied().unwrap_or_default();
+            let post = meta.post_balances.first().copied().unwrap_or_default();
+            Some(pre.saturating_sub(post))
+        }
+        Err(e) => {
+            log!(
+                Priority::Info,
+                "Failed to fetch transaction {}, keeping its charge: {:?}",
+                transaction.signature,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Settles the transaction as `status`. Its charge is corrected to `cost`, zero for
+/// a transaction that never landed, or kept as it is if the cost is unknown.
+fn settle(mut transaction: DeploymentTransaction, status: DeploymentTransactionStatus, cost: Option<u64>) {
+    log!(
+        Priority::Info,
+        "Transaction {} for collection {} settled as {:?}",
+        transaction.signature,
+        transaction.collection_id,
+        status
+    );
+    let finalized = status == DeploymentTransactionStatus::Finalized;
+    transaction.status = status;
+    if let (Some(charged), Some(cost)) = (transaction.lamports_charged, cost) {
+        state::correct_lamports_spent(&transaction.collection_id, charged, cost);
+        transaction.lamports_charged = Some(cost);
+    }
+    if let Err(e) = apply_outcome(&transaction.collection_id, &transaction.step, finalized) {
+        log!(
+            Priority::Info,
+            "Failed to update collection {} after transaction {}: {}",
+            transaction.collection_id,
+            transaction.signature,
+            e
+        );
+    }
+    state::update_deployment_transaction(transaction);
+}
+
+/// Leaves everything but the stage unchanged.
+fn stage_args(collection_id: &str, stage: SolanaDeploymentStage) -> UpdateSolanaStageArgs {
+    UpdateSolanaStageArgs {
+        collection_id: collection_id.to_string(),
+        stage,
+        candy_machine_address: None,
+        candy_machine_authority: None,
+        collection_mint: None,
+        manifest_url: None,
+        candy_machine_config: None,
+        files_uploaded: None,
+        metadata_created: None,
+    }
+}
+
+/// Writes what a settled step changed on chain to the collection.
+fn apply_outcome(collection_id: &str, step: &DeploymentStep, finalized: bool) -> MarketplaceResult<()> {
+    let collection = get_collection(collection_id)
+        .ok_or_else(|| MarketplaceError::not_found(Entity::Collection, collection_id))?;
+    let ChainData::Solana(data) = &collection.chain_data else {
+        return Err(MarketplaceError::invalid_state("Collection is not a Solana collection"));
+    };
+
+    let args = match (step, finalized) {
+        (
+            DeploymentStep::CreateCollectionNft { collection_mint }
+            | DeploymentStep::SetCollection { collection_mint },
+            true,
+        ) => UpdateSolanaStageArgs {
+            collection_mint: Some(collection_mint.clone()),
+            ..stage_args(collection_id, data.deployment_stage.clone())
+        },
+        (DeploymentStep::CreateCandyMachine { candy_machine, authority }, true) => UpdateSolanaStageArgs {
+            candy_machine_address: Some(candy_machine.clone()),
+            candy_machine_authority: Some(authority.clone()),
+            ..stage_args(collection_id, SolanaDeploymentStage::Deployed)
+        },
+        // Back to before the deployment so the creator can try again
+        (DeploymentStep::CreateCandyMachine { .. }, false) => {
+            stage_args(collection_id, SolanaDeploymentStage::MetadataCreated)
+        }
+        (DeploymentStep::TransferAuthority { new_authority }, true) => UpdateSolanaStageArgs {
+            candy_machine_authority: Some(new_authority.clone()),
+            ..stage_args(collection_id, SolanaDeploymentStage::Deployed)
+        },
+        (DeploymentStep::SetCandyGuard { candy_guard, config }, true) => {
+            return state::update_candy_guard(collection_id, candy_guard.clone(), config.clone());
+        }
+        _ => return Ok(()),
+    };
+    state::update_solana_stage(args)
+}
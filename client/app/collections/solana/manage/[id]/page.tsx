@@ -12,16 +12,9 @@ import { toast } from "sonner"
 import { Collection } from "@/declarations/marketplace/marketplace.did"
 import { useConnection, useWallet } from "@solana/wallet-adapter-react"
 import { PublicKey } from "@solana/web3.js"
-import { buildAddConfigLinesInstruction } from "@/lib/solana/candyMachine"
-import { createUmi } from "@metaplex-foundation/umi-bundle-defaults"
-import { mplCandyMachine, fetchCandyMachine } from "@metaplex-foundation/mpl-candy-machine"
-import { mplTokenMetadata } from "@metaplex-foundation/mpl-token-metadata"
-import { publicKey as umiPublicKey } from "@metaplex-foundation/umi"
+import { MarketplaceCallError } from "@/lib/marketplace-error"
 
 const ITEMS_PER_TRANSACTION = 5
-const SOLANA_NETWORK = (process.env.NEXT_PUBLIC_SOLANA_NETWORK === "mainnet-beta"
-  ? "mainnet-beta"
-  : "devnet") as "devnet" | "mainnet-beta"
 
 const chunkArray = <T,>(array: T[], size: number) => {
   const chunks: T[][] = []
@@ -48,6 +41,7 @@ export default function ManageSolanaCollectionPage() {
     authority: string
     itemsAvailable: number
     itemsLoaded: number
+    itemsRedeemed: number
     prefixUri?: string
     nameLength?: number
     uriLength?: number
@@ -121,19 +115,12 @@ export default function ManageSolanaCollectionPage() {
       toast.info("Uploading items to your candy machine...")
 
       const actor = await getMarketplaceActor(identity)
-      const [manifestResponse, solanaAccountsResult] = await Promise.all([
-        fetch(manifestUrl),
-        actor.get_collection_solana_accounts(collectionId)
-      ])
+      const manifestResponse = await fetch(manifestUrl)
 
       if (!manifestResponse.ok) {
         throw new Error(`Failed to fetch manifest (${manifestResponse.status})`)
       }
 
-      if ('Err' in solanaAccountsResult) {
-        throw new Error(solanaAccountsResult.Err)
-      }
-
       const manifestJson = await manifestResponse.json()
       const rawItems: any[] = Array.isArray(manifestJson?.items)
         ? manifestJson.items
@@ -175,35 +162,13 @@ export default function ManageSolanaCollectionPage() {
         throw new Error("No valid manifest entries found")
       }
 
-      const { payer_address: payerAddress, candy_machine_address: derivedCandyMachine } = solanaAccountsResult.Ok
-      const resolvedCandyMachine = derivedCandyMachine || candyMachineAddress
-
-      if (!payerAddress || !resolvedCandyMachine) {
-        throw new Error("Failed to resolve candy machine addresses")
-      }
-
+      // The canister builds and signs the add_config_lines instruction itself
       let startIndex = 0
       for (const chunk of chunkArray(testItems, ITEMS_PER_TRANSACTION)) {
-        const instructionData = await buildAddConfigLinesInstruction({
-          canisterPayerAddress: payerAddress,
-          candyMachineAddress: resolvedCandyMachine,
-          startIndex,
-          items: chunk,
-          network: SOLANA_NETWORK,
-        })
-
-        const result = await (actor as any).add_items_to_candy_machine(collectionId, {
-          program_id: instructionData.programId,
-          accounts: instructionData.accounts.map(acc => ({
-            pubkey: acc.pubkey,
-            is_signer: acc.isSigner,
-            is_writable: acc.isWritable,
-          })),
-          data: Array.from(instructionData.data),
-        })
+        const result = await (actor as any).add_items_to_candy_machine(collectionId, startIndex, chunk)
 
         if ('Err' in result) {
-          throw new Error(result.Err)
+          throw new MarketplaceCallError(result.Err)
         }
 
         startIndex += chunk.length
@@ -221,30 +186,33 @@ export default function ManageSolanaCollectionPage() {
   }
 
   const handleInspectCandyMachine = async () => {
-    if (!candyMachineAddress) {
+    if (!identity || !candyMachineAddress) {
       toast.error("No candy machine address found for this collection")
       return
     }
 
     try {
       setIsInspecting(true)
-      const rpcEndpoint = SOLANA_NETWORK === "mainnet-beta"
-        ? "https://api.mainnet-beta.solana.com"
-        : "https://api.devnet.solana.com"
 
-      const umi = createUmi(rpcEndpoint)
-        .use(mplTokenMetadata())
-        .use(mplCandyMachine())
-      const cm = await fetchCandyMachine(umi, umiPublicKey(candyMachineAddress))
+      // The canister reads the account and records it on the collection
+      const actor = await getMarketplaceActor(identity)
+      const result = await (actor as any).sync_candy_machine_state(collectionId)
+      if ('Err' in result) {
+        throw new MarketplaceCallError(result.Err)
+      }
+      const cm = result.Ok
+      const settings = cm.config_line_settings?.[0]
 
       setCandyMachineState({
-        authority: cm.authority.toString(),
-        itemsAvailable: Number(cm.data.itemsAvailable),
-        itemsLoaded: Number(cm.itemsLoaded),
-        prefixUri: cm.data.configLineSettings?.prefixUri,
-        nameLength: cm.data.configLineSettings?.nameLength,
-        uriLength: cm.data.configLineSettings?.uriLength,
+        authority: cm.authority,
+        itemsAvailable: Number(cm.items_available),
+        itemsLoaded: Number(cm.items_loaded),
+        itemsRedeemed: Number(cm.items_redeemed),
+        prefixUri: settings?.prefix_uri,
+        nameLength: settings?.name_length,
+        uriLength: settings?.uri_length,
       })
+      await loadCollectionData()
 
       toast.success("Candy machine state fetched")
     } catch (error) {
@@ -435,6 +403,7 @@ export default function ManageSolanaCollectionPage() {
                 <div><span className="font-medium">Authority:</span> {candyMachineState.authority}</div>
                 <div><span className="font-medium">Items Available:</span> {candyMachineState.itemsAvailable}</div>
                 <div><span className="font-medium">Items Loaded:</span> {candyMachineState.itemsLoaded}</div>
+                <div><span className="font-medium">Items Minted:</span> {candyMachineState.itemsRedeemed}</div>
                 <div><span className="font-medium">Prefix URI:</span> {candyMachineState.prefixUri || "N/A"}</div>
                 <div><span className="font-medium">Name Length:</span> {candyMachineState.nameLength ?? "N/A"}</div>
                 <div><span className="font-medium">URI Length:</span> {candyMachineState.uriLength ?? "N/A"}</div>
@@ -19,9 +19,10 @@ import { PaymentModal } from "@/components/nft/PaymentModal"
 import { useAuth } from "@/providers/auth-context"
 import { getMarketplaceActor } from "@/providers/actors/marketplace"
 import { uploadToStorage, StorageProvider } from "@/lib/storage"
-import { useWallet } from "@solana/wallet-adapter-react"
+import { useConnection, useWallet } from "@solana/wallet-adapter-react"
 import { toast } from "sonner"
-import { createCollectionNFTInstruction, createCandyMachineInstruction, deployCandyMachineViaCanister } from "@/lib/solana/candyMachine"
+import { createCollectionNFTViaCanister, deployCandyMachineViaCanister, depositTransaction, estimateDeploymentLamports, recordDepositViaCanister, setCandyGuardViaCanister, toCanisterCandyGuardConfig, toCanisterCandyMachineConfig, waitForFinalizedTransaction } from "@/lib/solana/candyMachine"
+import { MarketplaceCallError } from "@/lib/marketplace-error"
 
 interface CollectionFormData {
   name: string
@@ -46,7 +47,8 @@ type SaveStatus = "saving" | "saved" | "idle"
 export default function CreateSolanaCollectionPage() {
   const router = useRouter()
   const { identity, usersActor } = useAuth()
-  const { wallet, publicKey, connected } = useWallet()
+  const { wallet, publicKey, connected, sendTransaction } = useWallet()
+  const { connection } = useConnection()
 
   const draftId = "solana-draft"
   const [currentStep, setCurrentStep] = useState(1)
@@ -201,7 +203,7 @@ export default function CreateSolanaCollectionPage() {
         })
 
         if ('Err' in createResult) {
-          throw new Error(createResult.Err)
+          throw new MarketplaceCallError(createResult.Err)
         }
 
         const canisterRecordId = createResult.Ok
@@ -292,7 +294,7 @@ export default function CreateSolanaCollectionPage() {
 
       const collectionAccountsResult = await actor.get_collection_solana_accounts(formData.canisterRecordId)
       if ('Err' in collectionAccountsResult) {
-        throw new Error(collectionAccountsResult.Err)
+        throw new MarketplaceCallError(collectionAccountsResult.Err)
       }
       const collectionAccounts = collectionAccountsResult.Ok
       const canisterPayerAddress = collectionAccounts.payer_address
@@ -308,84 +310,85 @@ export default function CreateSolanaCollectionPage() {
       console.log("  Candy Machine:", candyMachineAddress)
       console.log("  Collection Mint:", collectionMintAddress)
 
-      // STEP 1: Create Collection NFT (REQUIRED for Candy Machine V3)
-      setDeploymentStep("Creating Collection NFT instruction...")
-
-      const collectionNFTInstructions = await createCollectionNFTInstruction({
-        name: formData.name,
-        uri: formData.manifestUrl,
-        royaltyBps: parseInt(formData.royaltyBps),
-        collectionMintAddress,
-        canisterPayerAddress,
-        network: 'devnet',
-      })
+      // STEP 0: Fund rent and fees; the canister only spends what the collection deposited
+      const requiredLamports = estimateDeploymentLamports(parseInt(formData.supply))
+      const availableLamports = collectionAccounts.lamports_deposited - collectionAccounts.lamports_spent
+      if (availableLamports < requiredLamports) {
+        setDeploymentStep("Depositing SOL for rent and fees...")
+
+        const depositTx = depositTransaction(
+          publicKey,
+          canisterPayerAddress,
+          formData.canisterRecordId,
+          requiredLamports - availableLamports
+        )
+        const {
+          context: { slot: minContextSlot },
+          value: { blockhash, lastValidBlockHeight },
+        } = await connection.getLatestBlockhashAndContext()
+        const depositSignature = await sendTransaction(depositTx, connection, { minContextSlot })
+        await connection.confirmTransaction({ blockhash, lastValidBlockHeight, signature: depositSignature }, "finalized")
+
+        await recordDepositViaCanister(actor, formData.canisterRecordId, depositSignature)
+        console.log("Deposit recorded:", depositSignature)
+      }
 
+      // STEP 1: Create Collection NFT (REQUIRED for Candy Machine V3)
       setDeploymentStep("Creating Collection NFT via canister...")
 
-      // Send Collection NFT creation to canister
-      // IMPORTANT: This must use 'finalized' commitment
-      const collectionTxResult = await actor.create_candy_machine_from_instruction(
-        formData.canisterRecordId,
-        collectionNFTInstructions.map(ix => ({
-          program_id: ix.programId,
-          accounts: ix.accounts.map(acc => ({
-            pubkey: acc.pubkey,
-            is_signer: acc.isSigner,
-            is_writable: acc.isWritable,
-          })),
-          data: ix.data,
-        }))
-      )
-
-      if ('Err' in collectionTxResult) {
-        throw new Error(`Collection NFT creation failed: ${collectionTxResult.Err}`)
-      }
-
-      const collectionTxSignature = collectionTxResult.Ok
+      const collectionTxSignature = await createCollectionNFTViaCanister(actor, formData.canisterRecordId)
       console.log("Collection NFT created:", collectionTxSignature)
 
-      // Wait a bit for the Collection NFT to be indexed
-      setDeploymentStep("Waiting for Collection NFT to be indexed...")
-      await new Promise(resolve => setTimeout(resolve, 3000))
+      // The canister records the collection mint once the transaction is finalized
+      setDeploymentStep("Waiting for Collection NFT to be finalized...")
+      await waitForFinalizedTransaction(actor, formData.canisterRecordId, collectionTxSignature)
 
-      // STEP 2: Create Candy Machine V3
-      setDeploymentStep("Building Candy Machine V3 instruction...")
+      // STEP 2: Store the Candy Machine config the canister builds from
+      setDeploymentStep("Saving Candy Machine config...")
 
-      const candyMachineInstructions = await createCandyMachineInstruction({
-        collectionId: formData.canisterRecordId,
-        name: formData.name,
-        symbol: formData.symbol,
+      const candyMachineConfig = {
         supply: parseInt(formData.supply),
         mintPrice: parseFloat(formData.mintPrice),
         goLiveDate: formData.goLiveDate,
         royaltyBps: parseInt(formData.royaltyBps),
-        manifestUrl: formData.manifestUrl,
-        canisterPayerAddress,
-        candyMachineAddress,
-        collectionMintAddress,
-        network: 'devnet',
+        symbol: formData.symbol,
+        maxPerWallet: parseInt(formData.maxPerWallet) || undefined,
+      }
+
+      const configResult = await actor.update_solana_stage({
+        collection_id: formData.canisterRecordId,
+        stage: { CandyMachineDeploying: null },
+        manifest_url: [],
+        files_uploaded: [],
+        metadata_created: [],
+        candy_machine_address: [],
+        collection_mint: [],
+        candy_machine_authority: [],
+        candy_machine_config: [toCanisterCandyMachineConfig(candyMachineConfig) as any],
       })
 
+      if ('Err' in configResult) {
+        throw new MarketplaceCallError(configResult.Err)
+      }
+
+      // STEP 3: Create Candy Machine V3
       setDeploymentStep("Creating Candy Machine via canister...")
 
-      // Send Candy Machine creation to canister
-      const txSignature = await deployCandyMachineViaCanister(
-        actor,
-        formData.canisterRecordId,
-        candyMachineInstructions
-      )
+      const txSignature = await deployCandyMachineViaCanister(actor, formData.canisterRecordId)
+
+      // The canister marks the collection deployed once the transaction is finalized
+      setDeploymentStep("Waiting for Candy Machine to be finalized...")
+      await waitForFinalizedTransaction(actor, formData.canisterRecordId, txSignature)
 
-      setDeploymentStep("Updating collection record...")
+      // STEP 4: Enforce the mint price, go-live date and wallet limit on chain
+      setDeploymentStep("Setting up Candy Guard via canister...")
 
-      // Update collection with candy machine address
-      const updateResult = await actor.update_candy_machine_address(
+      const guardTxSignature = await setCandyGuardViaCanister(
+        actor,
         formData.canisterRecordId,
-        candyMachineAddress
+        toCanisterCandyGuardConfig(candyMachineConfig, publicKey.toBase58())
       )
-
-      if ('Err' in updateResult) {
-        throw new Error(updateResult.Err)
-      }
+      await waitForFinalizedTransaction(actor, formData.canisterRecordId, guardTxSignature)
 
       setDeploymentStep("Finalizing deployment...")
 
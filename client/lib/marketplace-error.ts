@@ -0,0 +1,39 @@
+import type { MarketplaceError } from "@/declarations/marketplace/marketplace.did"
+
+export function formatMarketplaceError(err: MarketplaceError): string {
+  if ('NotFound' in err) {
+    return `${Object.keys(err.NotFound.entity)[0]} ${err.NotFound.id} not found`
+  }
+  if ('Unauthorized' in err) {
+    return 'Unauthorized'
+  }
+  if ('InvalidState' in err) {
+    return err.InvalidState
+  }
+  if ('InvalidInput' in err) {
+    return err.InvalidInput
+  }
+  if ('ValidationFailed' in err) {
+    return err.ValidationFailed.fields.map(({ field, message }) => `${field}: ${message}`).join('\n')
+  }
+  if ('SaleRejected' in err) {
+    return `Sale rejected: ${Object.keys(err.SaleRejected)[0]}`
+  }
+  if ('SolanaRpc' in err) {
+    return `Solana RPC error: ${err.SolanaRpc.errors.map((e) => e.message).join('; ')}`
+  }
+  if ('ConsensusFailed' in err) {
+    return `RPC providers did not reach consensus (${err.ConsensusFailed.successes} succeeded)`
+  }
+  if ('Paused' in err) {
+    return `${Object.keys(err.Paused.target)[0]} paused: ${err.Paused.reason}`
+  }
+  return err.Internal
+}
+
+export class MarketplaceCallError extends Error {
+  constructor(public readonly error: MarketplaceError, prefix?: string) {
+    const message = formatMarketplaceError(error)
+    super(prefix ? `${prefix}: ${message}` : message)
+  }
+}